@@ -1,8 +1,138 @@
-use clap::Parser;
+use crate::output_formatter::output_formatter::OutputFormat;
+use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Taco database management tool")]
 pub struct Args {
     #[arg(long, short, default_value = "inventory.taco.yml")]
     pub inventory: String,
+
+    /// Environment of the inventory to use instead of default_environment_name
+    #[arg(long, short, global = true)]
+    pub environment: Option<String>,
+
+    /// Cluster of the environment to use instead of its default_cluster_name
+    #[arg(long, global = true)]
+    pub cluster: Option<String>,
+
+    /// Server group for a one-shot request (runs it and exits instead of starting the prompt)
+    #[arg(long, short)]
+    pub group: Option<String>,
+
+    /// Query to run on the server group (same as "<group> ? <query>")
+    #[arg(long, short, requires = "group", conflicts_with_all = ["command", "macro_name"])]
+    pub query: Option<String>,
+
+    /// Command to run on the server group (same as "<group> ! <command>")
+    #[arg(long, short, requires = "group", conflicts_with = "macro_name")]
+    pub command: Option<String>,
+
+    /// Macro to run on the server group (same as "<group> $ <macro>")
+    #[arg(long = "macro", short, requires = "group")]
+    pub macro_name: Option<String>,
+
+    /// Macro parameter value, e.g. --param DB_NAME=constellation
+    #[arg(long = "param", short, value_name = "NAME=VALUE", global = true)]
+    pub params: Vec<String>,
+
+    /// Database to run the one-shot request in. Default DB is postgres
+    #[arg(long, short, global = true)]
+    pub db: Option<String>,
+
+    /// Output format for query results: table, csv, tsv, json, jsonl, markdown or expanded
+    #[arg(long, short, global = true)]
+    pub format: Option<OutputFormat>,
+
+    /// Writes query results to one "<host>_<db>.<ext>" file per server in this directory
+    #[arg(long, value_name = "DIR", global = true)]
+    pub out_dir: Option<String>,
+
+    /// Runs commands with two-phase commit: committed on all servers or on none (same as "<group> !! <command>")
+    #[arg(long, global = true)]
+    pub atomic: bool,
+
+    /// Runs the request on N servers at a time (1 when N is omitted), the next batch starts
+    /// only when the previous one succeeded and all servers are online and consistent
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "1", global = true)]
+    pub rolling: Option<usize>,
+
+    /// Cancels the request on servers which run it longer than SEC seconds
+    #[arg(long, value_name = "SEC", global = true)]
+    pub timeout: Option<u64>,
+
+    /// Runs the request on at most N servers at the same time
+    #[arg(long, value_name = "N", global = true)]
+    pub parallel: Option<usize>,
+
+    /// Runs destructive commands and commands on protected inventories without the typed confirmation
+    #[arg(long, short, global = true)]
+    pub yes: bool,
+
+    /// Prints every distinct result once with its servers and marks outlier servers
+    #[arg(long, global = true, conflicts_with_all = ["merge", "order_by"])]
+    pub compare: bool,
+
+    /// Merges rows of all servers into one result with host, port and db columns
+    #[arg(long, global = true)]
+    pub merge: bool,
+
+    /// Client-side ORDER BY of merged rows, e.g. --order-by "size desc, host" (implies --merge)
+    #[arg(long, value_name = "COLUMNS", global = true)]
+    pub order_by: Option<String>,
+
+    #[command(subcommand)]
+    pub subcommand: Option<Commands>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Runs a file of "<group> ? ...", "<group> ! ..." and "<group> $ macro" lines one after another
+    Run {
+        /// Script file to run
+        file: String,
+
+        /// Stops at the first failed line (default)
+        #[arg(long, conflicts_with = "continue_on_error")]
+        stop_on_error: bool,
+
+        /// Runs the remaining lines after a failed line
+        #[arg(long = "continue")]
+        continue_on_error: bool,
+    },
+    /// Prints the servers selected by a server group expression without running anything
+    Resolve {
+        /// Server group expression, e.g. "pgl & workers", "all - dr", "host~'^10\\.'"
+        expression: String,
+    },
+    /// Works with the inventory file
+    Inventory {
+        #[command(subcommand)]
+        command: InventoryCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum InventoryCommands {
+    /// Checks the inventory file and prints every problem with its line, exits with 1 on problems
+    Check,
+    /// Discovers a cluster from one node with Patroni and pg_dist_node and adds it to the
+    /// environment. Connection settings and citus_db_name come from the current cluster, or from
+    /// the cluster with the name which gets the discovered server groups merged into its own
+    Discover {
+        /// Any postgres node of the cluster
+        #[arg(long, value_name = "HOST:PORT")]
+        seed: String,
+
+        /// Cluster name instead of the Patroni scope
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Citus database for pg_dist_node, a cluster without it is not a Citus cluster
+        #[arg(long, value_name = "DB")]
+        citus_db: Option<String>,
+
+        /// Inventory file to write, it can not be the inventory file (comments are not kept)
+        #[arg(long, value_name = "FILE")]
+        output: String,
+    },
 }
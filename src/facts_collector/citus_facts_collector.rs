@@ -1,26 +1,21 @@
 use crate::shared::active_worker_nodes_result::ActiveWorkerNodesResult;
 use crate::shared::pg_dist_node_info_result::PgDistNodeInfoResult;
 use anyhow::Result;
-use tokio_postgres::NoTls;
+use tokio_postgres::Client;
 
+/// Runs the queries on a session of the server, see SessionManager
 pub struct CitusFactsCollector<'a> {
-    connection_string: &'a str,
+    client: &'a Client,
 }
 
 impl<'a> CitusFactsCollector<'a> {
-    pub fn new(connection_string: &'a str) -> Self {
-        CitusFactsCollector { connection_string }
+    pub fn new(client: &'a Client) -> Self {
+        CitusFactsCollector { client }
     }
 
     pub async fn get_active_worker_nodes(&self) -> Result<Vec<ActiveWorkerNodesResult>> {
-        let (client, connection) = tokio_postgres::connect(&self.connection_string, NoTls).await?;
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                eprintln!("connection error: {}", e);
-            }
-        });
-
-        let rows = client
+        let rows = self
+            .client
             .query("SELECT * FROM citus_get_active_worker_nodes();", &[])
             .await?;
         let mut result: Vec<ActiveWorkerNodesResult> = Vec::new();
@@ -35,14 +30,8 @@ impl<'a> CitusFactsCollector<'a> {
 
     // https://docs.citusdata.com/en/v13.0/develop/api_metadata.html#worker-node-table
     pub async fn get_pg_dist_node_info(&self) -> Result<Vec<PgDistNodeInfoResult>> {
-        let (client, connection) = tokio_postgres::connect(&self.connection_string, NoTls).await?;
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                eprintln!("connection error: {}", e);
-            }
-        });
-
-        let rows = client
+        let rows = self
+            .client
             .query(
                 "SELECT *, noderole::varchar FROM pg_dist_node order by groupid, nodename;",
                 &[],
@@ -2,6 +2,7 @@ use crate::facts_collector::citus_facts_collector::CitusFactsCollector;
 use crate::facts_collector::patroni_facts_collector::PatroniFactsCollector;
 use crate::facts_collector::postgres_facts_collector::PostgresFactsCollector;
 use crate::inventory::inventory_manager::Server;
+use crate::session_manager::session_manager::SessionManager;
 use crate::shared::pg_dist_node_info_result::PgDistNodeInfoResult;
 use rayon::iter::ParallelIterator;
 use rayon::prelude::{IntoParallelRefIterator, IntoParallelRefMutIterator};
@@ -9,13 +10,21 @@ use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 use tokio::task::JoinSet;
 
+/// Collects facts on sessions of the session manager, requests reuse them afterwards
 pub struct FactsCollector<'a> {
     settings: &'a Arc<Mutex<HashMap<String, String>>>,
+    session_manager: &'a Arc<SessionManager>,
 }
 
 impl<'a> FactsCollector<'a> {
-    pub fn new(settings: &'a Arc<Mutex<HashMap<String, String>>>) -> Self {
-        FactsCollector { settings }
+    pub fn new(
+        settings: &'a Arc<Mutex<HashMap<String, String>>>,
+        session_manager: &'a Arc<SessionManager>,
+    ) -> Self {
+        FactsCollector {
+            settings,
+            session_manager,
+        }
     }
 
     pub async fn collect_facts(&self, servers: &mut Vec<Server>, citus_db_name: Option<String>) {
@@ -42,8 +51,9 @@ impl<'a> FactsCollector<'a> {
 
         for server in servers.iter_mut() {
             let mut server_clone = server.clone();
+            let session_manager = self.session_manager.clone();
             join_set_extract.spawn(async move {
-                Self::update_postgres_status(&mut server_clone).await;
+                Self::update_postgres_status(&mut server_clone, &session_manager).await;
                 if let Some(true) = collect_patroni_facts {
                     Self::update_patroni_status(&mut server_clone).await;
                 }
@@ -75,12 +85,14 @@ impl<'a> FactsCollector<'a> {
             .find_any(|server| server.is_node_online.unwrap() == true);
 
         if let Some(server_online) = first_server_online {
-            if let Some(true) = collect_citus_facts {
-                let db_name_temp = server_online.db_name.clone();
-                server_online.db_name = Some(citus_db_name.unwrap());
-                let connection_string = &server_online.to_string();
-                server_online.db_name = db_name_temp;
-                let citus_facts_collector = CitusFactsCollector::new(connection_string);
+            // clusters without citus_db_name are not citus clusters
+            if let (Some(true), Some(citus_db_name)) = (collect_citus_facts, citus_db_name) {
+                let mut citus_server = server_online.clone();
+                citus_server.db_name = Some(citus_db_name);
+                let Ok(client) = self.session_manager.checkout(&citus_server).await else {
+                    return;
+                };
+                let citus_facts_collector = CitusFactsCollector::new(&client);
                 let active_worker_nodes = citus_facts_collector.get_active_worker_nodes().await;
                 match active_worker_nodes {
                     Ok(value) => {
@@ -111,16 +123,39 @@ impl<'a> FactsCollector<'a> {
                     }
                     _ => {}
                 }
+                self.session_manager.checkin(&citus_server, client);
             }
         }
     }
 
-    async fn update_postgres_status(server_clone: &mut Server) {
-        let postgres_connection_string = server_clone.to_string();
-        let postgres_facts_collector = PostgresFactsCollector::new(&postgres_connection_string);
+    pub async fn collect_database_names(&self, servers: &[Server]) -> Vec<String> {
+        let Some(server_online) = servers
+            .iter()
+            .find(|server| server.is_node_online.unwrap_or(false))
+        else {
+            return Vec::new();
+        };
+        let Ok(client) = self.session_manager.checkout(server_online).await else {
+            return Vec::new();
+        };
+        let database_names = PostgresFactsCollector::new(&client)
+            .get_database_names()
+            .await
+            .unwrap_or_default();
+        self.session_manager.checkin(server_online, client);
+        database_names
+    }
+
+    async fn update_postgres_status(server_clone: &mut Server, session_manager: &SessionManager) {
+        let Ok(client) = session_manager.checkout(server_clone).await else {
+            server_clone.is_node_online = Some(false);
+            return;
+        };
+        let postgres_facts_collector = PostgresFactsCollector::new(&client);
         let pg_stat_replication_result = postgres_facts_collector.check_pg_stat_replication().await;
         let pg_stat_wal_receiver_result =
             postgres_facts_collector.check_pg_stat_wal_receiver().await;
+        session_manager.checkin(server_clone, client);
         match pg_stat_replication_result {
             Ok(value) => {
                 if !value.is_empty() {
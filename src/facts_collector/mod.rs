@@ -1,4 +1,4 @@
-mod citus_facts_collector;
+pub mod citus_facts_collector;
 pub mod facts_collector;
-mod patroni_facts_collector;
+pub mod patroni_facts_collector;
 mod postgres_facts_collector;
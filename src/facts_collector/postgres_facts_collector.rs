@@ -1,25 +1,21 @@
 use crate::shared::pg_stat_replication_result::PgStatReplicationResult;
 use crate::shared::pg_stat_wal_receiver_result::PgStatWalReceiverResult;
 use anyhow::Result;
-use tokio_postgres::NoTls;
+use tokio_postgres::Client;
 
+/// Runs the queries on a session of the server, see SessionManager
 pub struct PostgresFactsCollector<'a> {
-    connection_string: &'a str,
+    client: &'a Client,
 }
 
 impl<'a> PostgresFactsCollector<'a> {
-    pub fn new(connection_string: &'a str) -> Self {
-        PostgresFactsCollector { connection_string }
+    pub fn new(client: &'a Client) -> Self {
+        PostgresFactsCollector { client }
     }
 
     pub async fn check_pg_stat_replication(&self) -> Result<Vec<PgStatReplicationResult>> {
-        let (client, connection) = tokio_postgres::connect(&self.connection_string, NoTls).await?;
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                eprintln!("connection error: {}", e);
-            }
-        });
-        let rows = client
+        let rows = self
+            .client
             .query("SELECT * FROM pg_stat_replication;", &[])
             .await?;
         if rows.is_empty() {
@@ -54,13 +50,8 @@ impl<'a> PostgresFactsCollector<'a> {
     }
 
     pub async fn check_pg_stat_wal_receiver(&self) -> Result<Vec<PgStatWalReceiverResult>> {
-        let (client, connection) = tokio_postgres::connect(&self.connection_string, NoTls).await?;
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                eprintln!("connection error: {}", e);
-            }
-        });
-        let rows = client
+        let rows = self
+            .client
             .query("SELECT * FROM pg_stat_wal_receiver;", &[])
             .await?;
         if rows.is_empty() {
@@ -88,4 +79,15 @@ impl<'a> PostgresFactsCollector<'a> {
         }
         Ok(result)
     }
-}
+
+    pub async fn get_database_names(&self) -> Result<Vec<String>> {
+        let rows = self
+            .client
+            .query(
+                "SELECT datname FROM pg_database WHERE datallowconn AND NOT datistemplate ORDER BY datname;",
+                &[],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+}
\ No newline at end of file
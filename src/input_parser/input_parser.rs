@@ -0,0 +1,502 @@
+use crate::output_formatter::output_formatter::OutputFormat;
+use crate::server_provider::server_group_expression::ServerGroupExpression;
+use crate::shared::request_type::RequestType;
+use std::path::Path;
+
+/// Request split into its parts.
+/// `server_group` is trimmed and lowercased outside quotes, `body` is kept as typed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedRequest {
+    pub server_group: String,
+    pub request_type: RequestType,
+    pub body: String,
+}
+
+/// Trailing "> file.ext" or ">> file.ext" of a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputRedirect {
+    pub path: String,
+    pub append: bool,
+}
+
+impl OutputRedirect {
+    /// A json array can not be continued, ">>" needs a jsonl file
+    pub fn check(&self) -> anyhow::Result<()> {
+        let output_format = Path::new(&self.path)
+            .extension()
+            .and_then(|extension| OutputFormat::from_extension(&extension.to_string_lossy()));
+        if self.append && output_format == Some(OutputFormat::Json) {
+            return Err(anyhow::anyhow!(
+                "CAN NOT APPEND TO JSON FILE {}, USE A .jsonl FILE WITH >>",
+                self.path
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Finds the request separator in "<SERVER_GROUP><SEPARATOR><COMMAND>".
+/// Only the first separator outside quotes counts, so "?", "!" and "$"
+/// inside the SQL part (jsonb operators, "!=", "$1", "$$") never change the request type
+pub fn find_request_separator(line: &str) -> Option<(usize, RequestType)> {
+    let mut quote: Option<char> = None;
+    for (index, c) in line.char_indices() {
+        match quote {
+            Some(quote_char) => {
+                if c == quote_char {
+                    quote = None;
+                }
+            }
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '?' => return Some((index, RequestType::Query)),
+                '!' if line[index + 1..].starts_with('!') => {
+                    return Some((index, RequestType::AtomicCommand));
+                }
+                '!' => return Some((index, RequestType::Command)),
+                '$' => return Some((index, RequestType::Macro)),
+                _ => {}
+            },
+        }
+    }
+    None
+}
+
+pub fn parse_request(line: &str) -> Option<ParsedRequest> {
+    let (separator_index, request_type) = find_request_separator(line)?;
+    let server_group = normalize_server_group(&line[..separator_index]);
+    if server_group.is_empty() {
+        return None;
+    }
+    let separator_length = match request_type {
+        RequestType::AtomicCommand => 2,
+        _ => 1,
+    };
+    let body = line[separator_index + separator_length..].trim();
+    let body = match request_type {
+        RequestType::Macro => body.to_lowercase(),
+        _ => body.to_string(),
+    };
+    Some(ParsedRequest {
+        server_group,
+        request_type,
+        body,
+    })
+}
+
+/// Returns false for a query or command which is not terminated by ";" yet,
+/// everything else (built-in commands, macros, unknown input) is complete as is
+pub fn is_request_complete(line: &str) -> bool {
+    match get_request_statement(line) {
+        Some(statement) => is_statement_complete(&statement),
+        None => true,
+    }
+}
+
+/// SQL of a query or command without the output redirect, None for macros and other input
+fn get_request_statement(line: &str) -> Option<String> {
+    let request = parse_request(line)?;
+    match request.request_type {
+        RequestType::Query => Some(split_output_redirect(&request.body).0),
+        RequestType::Command | RequestType::AtomicCommand => Some(request.body),
+        _ => None,
+    }
+}
+
+/// True for a "<SERVER_GROUP><SEPARATOR>..." line with a valid server group expression
+fn is_request_start(line: &str) -> bool {
+    parse_request(line)
+        .is_some_and(|request| ServerGroupExpression::parse(&request.server_group).is_ok())
+}
+
+/// Checks that the statement ends with ";" outside quotes, comments and dollar-quoted bodies
+pub fn is_statement_complete(statement: &str) -> bool {
+    matches!(scan_statement(statement), Some((true, _)))
+}
+
+/// State at the end of the statement: None inside a quote, a block comment or a dollar-quoted
+/// body, otherwise whether it ends with ";" and how many parentheses are open
+fn scan_statement(statement: &str) -> Option<(bool, usize)> {
+    let chars: Vec<char> = statement.chars().collect();
+    let mut terminated = false;
+    let mut depth = 0usize;
+    let mut index = 0usize;
+    while index < chars.len() {
+        let c = chars[index];
+        let next = chars.get(index + 1).copied();
+        match c {
+            '\'' | '"' => {
+                terminated = false;
+                match chars[index + 1..].iter().position(|&x| x == c) {
+                    // doubled quotes are just two quoted strings in a row
+                    Some(offset) => index += offset + 2,
+                    None => return None,
+                }
+                continue;
+            }
+            '-' if next == Some('-') => {
+                match chars[index..].iter().position(|&x| x == '\n') {
+                    Some(offset) => index += offset + 1,
+                    None => index = chars.len(),
+                }
+                continue;
+            }
+            '/' if next == Some('*') => {
+                let mut depth = 1;
+                index += 2;
+                while depth > 0 {
+                    match (chars.get(index), chars.get(index + 1)) {
+                        (Some('*'), Some('/')) => {
+                            depth -= 1;
+                            index += 2;
+                        }
+                        (Some('/'), Some('*')) => {
+                            depth += 1;
+                            index += 2;
+                        }
+                        (Some(_), _) => index += 1,
+                        (None, _) => return None,
+                    }
+                }
+                continue;
+            }
+            '$' => {
+                terminated = false;
+                // "$" inside identifiers like "a$b" does not open a dollar quote
+                let is_after_identifier =
+                    index > 0 && (chars[index - 1].is_alphanumeric() || chars[index - 1] == '_');
+                if !is_after_identifier && let Some(tag) = get_dollar_quote_tag(&chars[index..]) {
+                    let body_start = index + tag.len();
+                    match find_subsequence(&chars[body_start..], &tag) {
+                        Some(offset) => index = body_start + offset + tag.len(),
+                        None => return None,
+                    }
+                    continue;
+                }
+            }
+            '(' => {
+                terminated = false;
+                depth += 1;
+            }
+            ')' => {
+                terminated = false;
+                depth = depth.saturating_sub(1);
+            }
+            ';' => terminated = true,
+            c if c.is_whitespace() => {}
+            _ => terminated = false,
+        }
+        index += 1;
+    }
+    Some((terminated, depth))
+}
+
+/// Splits "> ./file.ext" or ">> /dir/file.ext" off the end of a query, before or after the final
+/// ";". Only paths starting with "./", "../" or "/" with known output extensions make a redirect,
+/// SQL can not have them, so "select a > b" and "where a > t.csv" stay comparisons
+pub fn split_output_redirect(query: &str) -> (String, Option<OutputRedirect>) {
+    let trimmed_query = query.trim_end();
+    let without_semicolon = trimmed_query.trim_end_matches(';').trim_end();
+    let has_semicolon = without_semicolon.len() != trimmed_query.len();
+    let Some(redirect_index) = without_semicolon.rfind('>') else {
+        return (query.to_string(), None);
+    };
+    let path = without_semicolon[redirect_index + 1..].trim();
+    let is_file_path = ["./", "../", "/"]
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+        && !path.contains(|c: char| c.is_whitespace() || c == '\'' || c == '"' || c == '$')
+        && Path::new(path)
+            .extension()
+            .and_then(|extension| OutputFormat::from_extension(&extension.to_string_lossy()))
+            .is_some();
+    if !is_file_path {
+        return (query.to_string(), None);
+    }
+    let mut statement = &without_semicolon[..redirect_index];
+    let append = statement.ends_with('>');
+    if append {
+        statement = &statement[..statement.len() - 1];
+    }
+    // "->" and "->>" are json operators
+    if statement.ends_with('-') {
+        return (query.to_string(), None);
+    }
+    let mut statement = statement.trim_end().to_string();
+    if has_semicolon && !statement.ends_with(';') {
+        statement.push(';');
+    }
+    (
+        statement,
+        Some(OutputRedirect {
+            path: path.to_string(),
+            append,
+        }),
+    )
+}
+
+/// Splits script content into requests with their first line numbers.
+/// Blank and comment lines between requests are skipped. A request is one line, unless
+/// the line leaves a quote, a comment, a dollar-quoted body or a parenthesis open,
+/// then it continues on the next lines until it ends with ";". A request which is not
+/// terminated before a line with a new request or the end of the script is an error
+pub fn split_script(content: &str) -> Vec<(usize, Result<String, String>)> {
+    let mut requests: Vec<(usize, Result<String, String>)> = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+    for (line_index, line) in content.lines().enumerate() {
+        if let Some((line_number, mut request)) = pending.take() {
+            // request lines inside quoted text and function bodies are just text
+            let is_in_text = get_request_statement(&request)
+                .is_some_and(|statement| scan_statement(&statement).is_none());
+            if is_in_text || !is_request_start(line) {
+                request.push('\n');
+                request.push_str(line);
+                if is_request_complete(&request) {
+                    requests.push((line_number, Ok(request)));
+                } else {
+                    pending = Some((line_number, request));
+                }
+                continue;
+            }
+            requests.push((
+                line_number,
+                Err(format!(
+                    "REQUEST IS NOT TERMINATED BY \";\" BEFORE LINE {}",
+                    line_index + 1
+                )),
+            ));
+        }
+        let trimmed_line = line.trim();
+        if trimmed_line.is_empty()
+            || trimmed_line.starts_with("--")
+            || trimmed_line.starts_with('#')
+        {
+            continue;
+        }
+        let is_open = get_request_statement(line)
+            .is_some_and(|statement| scan_statement(&statement).is_none_or(|(_, depth)| depth > 0));
+        if is_open && !is_request_complete(line) {
+            pending = Some((line_index + 1, line.to_string()));
+        } else {
+            requests.push((line_index + 1, Ok(line.to_string())));
+        }
+    }
+    if let Some((line_number, _)) = pending {
+        requests.push((
+            line_number,
+            Err("REQUEST IS NOT TERMINATED BY \";\" AT THE END OF THE SCRIPT".to_string()),
+        ));
+    }
+    requests
+}
+
+/// Returns "$tag$" when the slice starts with a dollar quote opening,
+/// "$1" style positional parameters are not dollar quotes
+pub(super) fn get_dollar_quote_tag(chars: &[char]) -> Option<Vec<char>> {
+    let end = chars[1..].iter().position(|&c| c == '$')? + 1;
+    let tag = &chars[1..end];
+    let is_valid_tag = match tag.first() {
+        None => true,
+        Some(first) => {
+            (first.is_alphabetic() || *first == '_')
+                && tag.iter().all(|c| c.is_alphanumeric() || *c == '_')
+        }
+    };
+    if is_valid_tag {
+        Some(chars[..=end].to_vec())
+    } else {
+        None
+    }
+}
+
+pub(super) fn find_subsequence(haystack: &[char], needle: &[char]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Lowercases the server group expression except for quoted parts
+pub fn normalize_server_group(raw_server_group: &str) -> String {
+    let mut result = String::with_capacity(raw_server_group.len());
+    let mut quote: Option<char> = None;
+    for c in raw_server_group.trim().chars() {
+        match quote {
+            Some(quote_char) => {
+                if c == quote_char {
+                    quote = None;
+                }
+                result.push(c);
+            }
+            None => {
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                }
+                result.extend(c.to_lowercase());
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> (String, RequestType, String) {
+        let request = parse_request(line).unwrap();
+        (request.server_group, request.request_type, request.body)
+    }
+
+    #[test]
+    fn test_parse_request_keeps_sql_as_typed() {
+        let (server_group, request_type, body) =
+            parse("PGL ? SELECT 'Mixed Case' AS \"Quoted Id\" FROM t;");
+        assert_eq!(server_group, "pgl");
+        assert_eq!(request_type, RequestType::Query);
+        assert_eq!(body, "SELECT 'Mixed Case' AS \"Quoted Id\" FROM t;");
+    }
+
+    #[test]
+    fn test_parse_request_ignores_separators_in_sql() {
+        let (_, request_type, body) = parse("caw ! update t set a = $$x!$$ where b != 'y?'");
+        assert_eq!(request_type, RequestType::Command);
+        assert_eq!(body, "update t set a = $$x!$$ where b != 'y?'");
+
+        let (_, request_type, body) = parse("all ? select data ? 'key', data ?| array['a'] from t");
+        assert_eq!(request_type, RequestType::Query);
+        assert_eq!(body, "select data ? 'key', data ?| array['a'] from t");
+    }
+
+    #[test]
+    fn test_parse_request_atomic_command() {
+        let (server_group, request_type, body) = parse("pgl !! alter table t add column c int;");
+        assert_eq!(server_group, "pgl");
+        assert_eq!(request_type, RequestType::AtomicCommand);
+        assert_eq!(body, "alter table t add column c int;");
+    }
+
+    #[test]
+    fn test_parse_request_macro_name_is_lowercased() {
+        let (server_group, request_type, body) = parse("prw $ DROP_DB");
+        assert_eq!(server_group, "prw");
+        assert_eq!(request_type, RequestType::Macro);
+        assert_eq!(body, "drop_db");
+    }
+
+    #[test]
+    fn test_parse_request_skips_quoted_server_group_parts() {
+        let (server_group, request_type, _) = parse("Host~\"^DB?\" ! vacuum");
+        assert_eq!(server_group, "host~\"^DB?\"");
+        assert_eq!(request_type, RequestType::Command);
+    }
+
+    #[test]
+    fn test_is_statement_complete() {
+        assert!(is_statement_complete("select 1;"));
+        assert!(is_statement_complete("select 1; -- trailing comment"));
+        assert!(!is_statement_complete("select 1"));
+        assert!(!is_statement_complete("select ';'"));
+        assert!(!is_statement_complete("select 1 -- ;"));
+        assert!(!is_statement_complete("select 1 /* ; */"));
+        assert!(is_statement_complete("select 'it''s';"));
+        assert!(!is_statement_complete(
+            "create function f() returns int as $body$ select 1;"
+        ));
+        assert!(is_statement_complete(
+            "create function f() returns int as $body$ select 1; $body$ language sql;"
+        ));
+        assert!(is_statement_complete("select $1::int;"));
+        assert!(is_statement_complete("select a$b$c from t;"));
+    }
+
+    #[test]
+    fn test_split_output_redirect() {
+        assert_eq!(
+            split_output_redirect("select 1 > ./report.csv;"),
+            (
+                "select 1;".to_string(),
+                Some(OutputRedirect {
+                    path: "./report.csv".to_string(),
+                    append: false
+                })
+            )
+        );
+        assert_eq!(
+            split_output_redirect("select 1; >> /tmp/out/report.jsonl"),
+            (
+                "select 1;".to_string(),
+                Some(OutputRedirect {
+                    path: "/tmp/out/report.jsonl".to_string(),
+                    append: true
+                })
+            )
+        );
+        assert_eq!(split_output_redirect("select a > b;").1, None);
+        assert_eq!(split_output_redirect("select a > 'b.csv';").1, None);
+        assert_eq!(split_output_redirect("select data ->> ./a.csv;").1, None);
+        assert!(is_request_complete("all ? select 1; > ../report.csv"));
+    }
+
+    #[test]
+    fn test_split_output_redirect_keeps_comparisons() {
+        for query in [
+            "select * from t where a > t.csv",
+            "select * from t where x > s.md;",
+            "select * from t where a > t/s.csv",
+            "select * from t where a >> t.jsonl",
+        ] {
+            assert_eq!(
+                split_output_redirect(query),
+                (query.to_string(), None),
+                "{}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_split_script() {
+        let content = "-- comment\nall ? select 1;\n\nuse constellation\npgl ! create table t (\n  a int\n);\nall ? select 2\nprw $ drop_db";
+        let requests = split_script(content);
+        assert_eq!(
+            requests,
+            vec![
+                (2, Ok("all ? select 1;".to_string())),
+                (4, Ok("use constellation".to_string())),
+                (5, Ok("pgl ! create table t (\n  a int\n);".to_string())),
+                (8, Ok("all ? select 2".to_string())),
+                (9, Ok("prw $ drop_db".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_script_unterminated_request() {
+        let content = "all ? select count(*) from (\n  select 1\npgl ? select 2;\nall ! do $$\nbegin\npgl ? select 3;\nend $$;\nall ? select '\n";
+        let requests = split_script(content);
+        assert_eq!(
+            requests,
+            vec![
+                (
+                    1,
+                    Err("REQUEST IS NOT TERMINATED BY \";\" BEFORE LINE 3".to_string())
+                ),
+                (3, Ok("pgl ? select 2;".to_string())),
+                (
+                    4,
+                    Ok("all ! do $$\nbegin\npgl ? select 3;\nend $$;".to_string())
+                ),
+                (
+                    8,
+                    Err("REQUEST IS NOT TERMINATED BY \";\" AT THE END OF THE SCRIPT".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_request_without_separator_or_group() {
+        assert!(parse_request("select 1").is_none());
+        assert!(parse_request(" ? select 1").is_none());
+    }
+}
@@ -0,0 +1,2 @@
+pub mod input_parser;
+pub mod statement_classifier;
\ No newline at end of file
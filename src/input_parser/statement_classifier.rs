@@ -0,0 +1,277 @@
+use crate::input_parser::input_parser::{find_subsequence, get_dollar_quote_tag};
+
+/// Returns descriptions of destructive statements in the SQL text, like "DROP DATABASE",
+/// "DELETE WITHOUT WHERE" or "pg_terminate_backend". Empty when nothing destructive is found
+pub fn get_destructive_statements(sql: &str) -> Vec<String> {
+    let mut destructive_statements: Vec<String> = Vec::new();
+    for tokens in get_statement_tokens(sql) {
+        for description in classify_statement(&tokens) {
+            if !destructive_statements.contains(&description) {
+                destructive_statements.push(description);
+            }
+        }
+    }
+    destructive_statements
+}
+
+/// Words of PL/pgSQL blocks which are followed by a statement
+const BLOCK_KEYWORDS: [&str; 4] = ["BEGIN", "THEN", "ELSE", "LOOP"];
+
+fn classify_statement(tokens: &[String]) -> Vec<String> {
+    if tokens.first().is_some_and(|token| token == "EXPLAIN") {
+        // only EXPLAIN ANALYZE runs the statement
+        return match get_explained_statement(&tokens[1..]) {
+            (true, statement) => classify_statement(statement),
+            (false, _) => Vec::new(),
+        };
+    }
+    let mut descriptions = Vec::new();
+    let is_statement_start =
+        |index: usize| index == 0 || BLOCK_KEYWORDS.contains(&tokens[index - 1].as_str());
+    for start in (0..tokens.len()).filter(|&index| is_statement_start(index)) {
+        let first = tokens[start].as_str();
+        let second = tokens.get(start + 1).map(String::as_str).unwrap_or("");
+        match first {
+            "DROP" => descriptions.push(format!("DROP {}", second).trim_end().to_string()),
+            "TRUNCATE" => descriptions.push("TRUNCATE".to_string()),
+            "ALTER" if second == "SYSTEM" => descriptions.push("ALTER SYSTEM".to_string()),
+            "ALTER" if tokens[start..].iter().any(|token| token == "DROP") => {
+                descriptions.push(format!("ALTER {} ... DROP", second))
+            }
+            _ => {}
+        }
+    }
+    for (index, token) in tokens.iter().enumerate() {
+        if token != "DELETE" && token != "UPDATE" {
+            continue;
+        }
+        // only statements, "ON DELETE CASCADE", "FOR UPDATE" or "GRANT UPDATE" are fine
+        let is_statement =
+            is_statement_start(index) || tokens[index - 1] == "(" || tokens[index - 1] == ")";
+        if is_statement && !has_where_clause(&tokens[index + 1..]) {
+            descriptions.push(format!("{} WITHOUT WHERE", token));
+        }
+    }
+    if tokens.iter().any(|token| token == "PG_TERMINATE_BACKEND") {
+        descriptions.push("pg_terminate_backend".to_string());
+    }
+    descriptions
+}
+
+/// Skips options of EXPLAIN, true when the explained statement is run by ANALYZE
+fn get_explained_statement(tokens: &[String]) -> (bool, &[String]) {
+    if tokens.first().is_some_and(|token| token == "(") {
+        let end = tokens
+            .iter()
+            .position(|token| token == ")")
+            .unwrap_or(tokens.len());
+        let is_analyze = tokens[1..end].iter().any(|token| token == "ANALYZE");
+        return (is_analyze, tokens.get(end + 1..).unwrap_or_default());
+    }
+    let start = tokens
+        .iter()
+        .position(|token| token != "ANALYZE" && token != "VERBOSE")
+        .unwrap_or(tokens.len());
+    let is_analyze = tokens[..start].iter().any(|token| token == "ANALYZE");
+    (is_analyze, &tokens[start..])
+}
+
+/// Looks for WHERE on the same parenthesis level until the statement ends,
+/// WHERE of a subquery does not limit the outer DELETE or UPDATE
+fn has_where_clause(tokens: &[String]) -> bool {
+    let mut depth = 0;
+    for token in tokens {
+        match token.as_str() {
+            "(" => depth += 1,
+            ")" if depth == 0 => return false,
+            ")" => depth -= 1,
+            "WHERE" if depth == 0 => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Splits SQL into statements of uppercased words and parentheses.
+/// String literals, quoted identifiers and comments are skipped, dollar quoted bodies of DO
+/// and of "AS $$" functions are split into statements of their own, other ones are skipped
+fn get_statement_tokens(sql: &str) -> Vec<Vec<String>> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut statements: Vec<Vec<String>> = Vec::new();
+    let mut tokens: Vec<String> = Vec::new();
+    let mut word = String::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        let next = chars.get(index + 1).copied();
+        if c.is_alphanumeric() || c == '_' || (c == '$' && !word.is_empty()) {
+            word.extend(c.to_uppercase());
+            index += 1;
+            continue;
+        }
+        if !word.is_empty() {
+            tokens.push(std::mem::take(&mut word));
+        }
+        match c {
+            '\'' | '"' => match chars[index + 1..].iter().position(|&x| x == c) {
+                Some(offset) => index += offset + 2,
+                None => index = chars.len(),
+            },
+            '-' if next == Some('-') => match chars[index..].iter().position(|&x| x == '\n') {
+                Some(offset) => index += offset + 1,
+                None => index = chars.len(),
+            },
+            '/' if next == Some('*') => {
+                let mut depth = 1;
+                index += 2;
+                while depth > 0 && index < chars.len() {
+                    match (chars[index], chars.get(index + 1)) {
+                        ('*', Some('/')) => {
+                            depth -= 1;
+                            index += 2;
+                        }
+                        ('/', Some('*')) => {
+                            depth += 1;
+                            index += 2;
+                        }
+                        _ => index += 1,
+                    }
+                }
+            }
+            '$' => match get_dollar_quote_tag(&chars[index..]) {
+                Some(tag) => {
+                    let body_start = index + tag.len();
+                    let body_end = find_subsequence(&chars[body_start..], &tag)
+                        .map_or(chars.len(), |offset| body_start + offset);
+                    let is_code = tokens.first().is_some_and(|token| token == "DO")
+                        || tokens.last().is_some_and(|token| token == "AS");
+                    if is_code {
+                        let body: String = chars[body_start..body_end].iter().collect();
+                        statements.extend(get_statement_tokens(&body));
+                    }
+                    index = (body_end + tag.len()).min(chars.len());
+                }
+                None => index += 1,
+            },
+            '(' | ')' => {
+                tokens.push(c.to_string());
+                index += 1;
+            }
+            ';' => {
+                if !tokens.is_empty() {
+                    statements.push(std::mem::take(&mut tokens));
+                }
+                index += 1;
+            }
+            _ => index += 1,
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    if !tokens.is_empty() {
+        statements.push(tokens);
+    }
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_destructive_statements() {
+        assert_eq!(
+            get_destructive_statements("drop table t; truncate t2; alter system set a = 1;"),
+            vec!["DROP TABLE", "TRUNCATE", "ALTER SYSTEM"]
+        );
+        assert_eq!(
+            get_destructive_statements("alter table t drop column c"),
+            vec!["ALTER TABLE ... DROP"]
+        );
+        assert_eq!(get_destructive_statements("delete from t"), vec!["DELETE WITHOUT WHERE"]);
+        assert_eq!(
+            get_destructive_statements("update t set a = (select b from s where s.id = 1)"),
+            vec!["UPDATE WITHOUT WHERE"]
+        );
+        assert_eq!(
+            get_destructive_statements("with x as (select 1) delete from t"),
+            vec!["DELETE WITHOUT WHERE"]
+        );
+        assert_eq!(
+            get_destructive_statements(
+                "select pg_terminate_backend(pid) from pg_stat_activity where datname = 'a'"
+            ),
+            vec!["pg_terminate_backend"]
+        );
+    }
+
+    #[test]
+    fn test_get_destructive_statements_ignores_safe_sql() {
+        assert!(get_destructive_statements("delete from t where id = 1").is_empty());
+        assert!(get_destructive_statements("update t set a = 1 where id = 2;").is_empty());
+        assert!(get_destructive_statements("select * from t for update").is_empty());
+        assert!(get_destructive_statements("grant update, delete on t to u").is_empty());
+        assert!(
+            get_destructive_statements("create table t (id int references s on delete cascade)")
+                .is_empty()
+        );
+        assert!(
+            get_destructive_statements(
+                "insert into t values ('drop table t') on conflict do update set a = 1"
+            )
+            .is_empty()
+        );
+        assert!(get_destructive_statements("select 1 -- drop table t\n").is_empty());
+        assert!(get_destructive_statements("do $$ begin null; end $$ /* truncate t */").is_empty());
+        assert!(get_destructive_statements("select $$drop table t$$ as text").is_empty());
+        assert!(get_destructive_statements("explain delete from t").is_empty());
+        assert!(get_destructive_statements("explain (costs off) update t set a = 1").is_empty());
+    }
+
+    #[test]
+    fn test_get_destructive_statements_in_bodies() {
+        assert_eq!(
+            get_destructive_statements("do $$ begin drop table t; delete from s; end $$"),
+            vec!["DROP TABLE", "DELETE WITHOUT WHERE"]
+        );
+        assert_eq!(
+            get_destructive_statements(
+                "do language plpgsql $body$ begin if true then truncate t; end if; end $body$"
+            ),
+            vec!["TRUNCATE"]
+        );
+        assert_eq!(
+            get_destructive_statements(
+                "create function f() returns void language sql as $$ truncate t $$"
+            ),
+            vec!["TRUNCATE"]
+        );
+        assert!(
+            get_destructive_statements(
+                "do $$ begin update t set a = 1 where id = 2; delete from s where id = 3; end $$"
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_get_destructive_statements_in_explain_analyze() {
+        assert_eq!(
+            get_destructive_statements("explain analyze delete from t"),
+            vec!["DELETE WITHOUT WHERE"]
+        );
+        assert_eq!(
+            get_destructive_statements("explain (analyze, buffers) update t set a = 1"),
+            vec!["UPDATE WITHOUT WHERE"]
+        );
+        assert_eq!(
+            get_destructive_statements("explain analyze verbose delete from t"),
+            vec!["DELETE WITHOUT WHERE"]
+        );
+        assert!(
+            get_destructive_statements("explain analyze delete from t where id = 1").is_empty()
+        );
+        assert!(get_destructive_statements("explain (analyze").is_empty());
+    }
+}
@@ -1,42 +1,44 @@
+use crate::inventory::dynamic_group::DynamicGroup;
+use crate::inventory::server_defaults::ServerDefaults;
 use crate::inventory::server_group::ServerGroup;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct Cluster {
     pub name: String,
-    pub default_port: Option<i32>,
-    pub default_db_name: Option<String>,
-    pub default_user: Option<String>,
-    pub default_password: Option<String>,
-    pub default_connect_timeout_sec: Option<i32>,
+    #[serde(flatten)]
+    pub defaults: ServerDefaults,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub citus_db_name: Option<String>,
+    /// Every command on the cluster needs a typed confirmation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protected: Option<bool>,
     pub server_groups: Vec<ServerGroup>,
+    /// Server groups built from facts, a group of a nearer level replaces one with the same name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dynamic_groups: Vec<DynamicGroup>,
 }
 
 impl Cluster {
     pub fn new() -> Self {
         Self {
             name: String::new(),
-            default_port: None,
-            default_db_name: None,
-            default_user: None,
-            default_password: None,
-            default_connect_timeout_sec: None,
+            defaults: ServerDefaults::default(),
             citus_db_name: None,
+            protected: None,
             server_groups: Vec::new(),
+            dynamic_groups: Vec::new(),
         }
     }
 
     pub fn from(other: &Cluster) -> Self {
         Self {
             name: other.name.clone(),
-            default_port: other.default_port,
-            default_db_name: other.default_db_name.clone(),
-            default_user: other.default_user.clone(),
-            default_password: other.default_password.clone(),
-            default_connect_timeout_sec: other.default_connect_timeout_sec,
+            defaults: other.defaults.clone(),
             citus_db_name: other.citus_db_name.clone(),
+            protected: other.protected,
             server_groups: other.server_groups.clone(),
+            dynamic_groups: other.dynamic_groups.clone(),
         }
     }
 }
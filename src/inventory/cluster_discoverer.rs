@@ -0,0 +1,386 @@
+use crate::facts_collector::citus_facts_collector::CitusFactsCollector;
+use crate::facts_collector::patroni_facts_collector::PatroniFactsCollector;
+use crate::inventory::cluster::Cluster;
+use crate::inventory::password_resolver::PasswordResolver;
+use crate::inventory::server::Server;
+use crate::inventory::server_defaults::ServerDefaults;
+use crate::inventory::server_group::ServerGroup;
+use crate::session_manager::session_manager::SessionManager;
+use std::collections::BTreeMap;
+
+/// Postgres node of a Patroni cluster
+#[derive(Clone)]
+struct Member {
+    host: String,
+    port: i32,
+    is_leader: bool,
+}
+
+impl Member {
+    fn new(host: &str, port: i32, is_leader: bool) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            is_leader,
+        }
+    }
+}
+
+/// Builds a cluster of the inventory from one running node. Members come from the Patroni
+/// cluster of the node, for Citus every group of pg_dist_node adds the members of its Patroni cluster
+pub struct ClusterDiscoverer {
+    /// Settings and citus_db_name of the discovered cluster
+    template: Cluster,
+    /// Defaults of the template with the defaults of its environment, for connections
+    defaults: ServerDefaults,
+    password_resolver: PasswordResolver,
+    /// Sessions of the discovered nodes, closed with the discoverer
+    session_manager: SessionManager,
+    warnings: Vec<String>,
+}
+
+impl ClusterDiscoverer {
+    pub fn new(template: Cluster, defaults: ServerDefaults) -> Self {
+        Self {
+            template,
+            defaults,
+            password_resolver: PasswordResolver::new(),
+            session_manager: SessionManager::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Patroni clusters which were not available, their nodes come from the seed or pg_dist_node
+    pub fn get_warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Seed is "host:port" or "host" with the default port, IPv6 hosts with a port are in brackets
+    pub async fn discover(&mut self, seed: &str) -> anyhow::Result<Cluster> {
+        let default_port = self.defaults.default_port.unwrap_or(5432);
+        let (seed_host, seed_port) = parse_seed(seed, default_port)?;
+        let (scope, seed_members) = match self.get_patroni_members(&seed_host, seed_port).await {
+            Some((scope, members)) => (scope, members),
+            None => (None, vec![Member::new(&seed_host, seed_port, true)]),
+        };
+
+        let mut coordinators: Vec<Member> = Vec::new();
+        let mut workers: Vec<Member> = Vec::new();
+        if let Some(citus_db_name) = self.template.citus_db_name.clone() {
+            let leader = seed_members
+                .iter()
+                .find(|member| member.is_leader)
+                .unwrap_or(&seed_members[0]);
+            let citus_groups = self
+                .get_citus_groups(&leader.host, leader.port, &citus_db_name)
+                .await?;
+            for (group_id, nodes) in citus_groups {
+                let primary = &nodes[0];
+                let members = if seed_members
+                    .iter()
+                    .any(|member| member.host == primary.host)
+                {
+                    seed_members.clone()
+                } else {
+                    match self.get_patroni_members(&primary.host, primary.port).await {
+                        Some((_, members)) => members,
+                        None => nodes,
+                    }
+                };
+                add_group_members(&mut coordinators, &mut workers, group_id, members);
+            }
+        }
+        let all_members: Vec<&Member> = if self.template.citus_db_name.is_some() {
+            coordinators.iter().chain(workers.iter()).collect()
+        } else {
+            seed_members.iter().collect()
+        };
+
+        let mut cluster = Cluster::from(&self.template);
+        cluster.name = scope.unwrap_or(seed_host);
+        cluster.server_groups =
+            get_server_groups(&coordinators, &workers, &all_members, default_port);
+        Ok(cluster)
+    }
+
+    /// Scope and members of the Patroni cluster of the node, None without Patroni
+    async fn get_patroni_members(
+        &mut self,
+        host: &str,
+        port: i32,
+    ) -> Option<(Option<String>, Vec<Member>)> {
+        let base_url = match host.contains(':') {
+            true => format!("http://[{}]:8008", host),
+            false => format!("http://{}:8008", host),
+        };
+        let patroni_facts_collector = PatroniFactsCollector::new(&base_url);
+        let cluster_info = patroni_facts_collector.get_cluster_info().await;
+        let members: Vec<Member> = cluster_info
+            .as_ref()
+            .ok()
+            .and_then(|cluster_info| cluster_info["members"].as_array())
+            .map(|members| {
+                members
+                    .iter()
+                    .filter_map(|member| {
+                        Some(Member {
+                            host: member["host"].as_str()?.to_string(),
+                            port: member["port"].as_i64().map_or(port, |port| port as i32),
+                            is_leader: matches!(
+                                member["role"].as_str(),
+                                Some("leader" | "standby_leader")
+                            ),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        if members.is_empty() {
+            let reason = match &cluster_info {
+                Ok(_) => "no members".to_string(),
+                Err(e) => e.to_string(),
+            };
+            self.warnings.push(format!(
+                "Patroni cluster of {}:{} is not available: {}",
+                host, port, reason
+            ));
+            return None;
+        }
+        let scope = cluster_info
+            .ok()
+            .and_then(|cluster_info| cluster_info["scope"].as_str().map(str::to_string));
+        Some((scope, members))
+    }
+
+    /// Nodes of every group of pg_dist_node with the primary node first,
+    /// group 0 is the coordinator group
+    async fn get_citus_groups(
+        &mut self,
+        host: &str,
+        port: i32,
+        citus_db_name: &str,
+    ) -> anyhow::Result<BTreeMap<i32, Vec<Member>>> {
+        let server = Server {
+            host: host.to_string(),
+            port: Some(port),
+            db_name: Some(citus_db_name.to_string()),
+            ..Default::default()
+        };
+        let mut server = Server::from(&server, &self.defaults);
+        self.password_resolver.resolve_server(&mut server)?;
+        let read_error = |e: anyhow::Error| {
+            anyhow::anyhow!(
+                "CAN NOT READ pg_dist_node OF {}:{}/{}: {:#}",
+                host,
+                port,
+                citus_db_name,
+                e
+            )
+        };
+        let client = self
+            .session_manager
+            .checkout(&server)
+            .await
+            .map_err(|e| read_error(e.into()))?;
+        let pg_dist_node_info = CitusFactsCollector::new(&client)
+            .get_pg_dist_node_info()
+            .await
+            .map_err(read_error)?;
+        self.session_manager.checkin(&server, client);
+        let mut citus_groups: BTreeMap<i32, Vec<Member>> = BTreeMap::new();
+        for node_info in pg_dist_node_info {
+            let (Some(group_id), Some(node_name)) = (node_info.groupid, node_info.nodename) else {
+                continue;
+            };
+            let is_primary = node_info.noderole.as_deref() == Some("primary");
+            let member = Member::new(&node_name, node_info.nodeport.unwrap_or(port), is_primary);
+            let nodes = citus_groups.entry(group_id).or_default();
+            if is_primary {
+                nodes.insert(0, member);
+            } else {
+                nodes.push(member);
+            }
+        }
+        // a group without a primary node has no leader to ask Patroni
+        citus_groups.retain(|_, nodes| nodes[0].is_leader);
+        Ok(citus_groups)
+    }
+}
+
+/// The port is left out when it is the default port of the cluster
+fn get_server(member: &Member, default_port: i32) -> Server {
+    Server {
+        host: member.host.clone(),
+        port: Some(member.port).filter(|port| *port != default_port),
+        ..Default::default()
+    }
+}
+
+/// Members of Citus group 0 are coordinators, others are workers. Patroni of a node can list
+/// members which are already added
+fn add_group_members(
+    coordinators: &mut Vec<Member>,
+    workers: &mut Vec<Member>,
+    group_id: i32,
+    members: Vec<Member>,
+) {
+    for member in members {
+        if coordinators
+            .iter()
+            .chain(workers.iter())
+            .any(|other| other.host == member.host && other.port == member.port)
+        {
+            continue;
+        }
+        if group_id == 0 {
+            coordinators.push(member);
+        } else {
+            workers.push(member);
+        }
+    }
+}
+
+/// Seed "host:port", "[ipv6]:port", "host" or a bare IPv6 address, without a port
+/// the default port is used
+fn parse_seed(seed: &str, default_port: i32) -> anyhow::Result<(String, i32)> {
+    let wrong_seed = || anyhow::anyhow!("WRONG SEED <{}>, EXPECTED <host>:<port>", seed);
+    let (host, port) = if let Some(bracketed) = seed.strip_prefix('[') {
+        let (host, rest) = bracketed.split_once(']').ok_or_else(wrong_seed)?;
+        match rest {
+            "" => (host, None),
+            _ => (host, Some(rest.strip_prefix(':').ok_or_else(wrong_seed)?)),
+        }
+    } else {
+        match seed.split_once(':') {
+            // more than one ":" is an IPv6 address without a port
+            Some((host, port)) if !port.contains(':') => (host, Some(port)),
+            _ => (seed, None),
+        }
+    };
+    let port = match port {
+        Some(port) => port.parse::<i32>().map_err(|_| wrong_seed())?,
+        None => default_port,
+    };
+    if host.is_empty() {
+        return Err(wrong_seed());
+    }
+    Ok((host.to_string(), port))
+}
+
+/// Server groups of the discovered members, groups without members are left out
+fn get_server_groups(
+    coordinators: &[Member],
+    workers: &[Member],
+    all_members: &[&Member],
+    default_port: i32,
+) -> Vec<ServerGroup> {
+    let server_groups = [
+        ("coordinators", coordinators.iter().collect()),
+        ("workers", workers.iter().collect()),
+        (
+            "primary",
+            all_members
+                .iter()
+                .copied()
+                .filter(|member| member.is_leader)
+                .collect(),
+        ),
+        (
+            "dr",
+            all_members
+                .iter()
+                .copied()
+                .filter(|member| !member.is_leader)
+                .collect(),
+        ),
+    ];
+    server_groups
+        .into_iter()
+        .filter(|(_, members): &(&str, Vec<&Member>)| !members.is_empty())
+        .map(|(name, members)| ServerGroup {
+            name: name.to_string(),
+            defaults: ServerDefaults::default(),
+            servers: members
+                .iter()
+                .map(|member| get_server(member, default_port))
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_seed() {
+        for (seed, expected) in [
+            ("10.0.0.1:5433", ("10.0.0.1", 5433)),
+            ("db-1", ("db-1", 5432)),
+            ("[fd00::1]:5433", ("fd00::1", 5433)),
+            ("[fd00::1]", ("fd00::1", 5432)),
+            ("fd00::1", ("fd00::1", 5432)),
+        ] {
+            let (host, port) = parse_seed(seed, 5432).unwrap();
+            assert_eq!((host.as_str(), port), expected, "{}", seed);
+        }
+        for seed in [":5432", "db-1:port", "[fd00::1", "[fd00::1]5433", ""] {
+            assert!(parse_seed(seed, 5432).is_err(), "{}", seed);
+        }
+    }
+
+    #[test]
+    fn test_get_server_groups() {
+        let mut coordinators = Vec::new();
+        let mut workers = Vec::new();
+        add_group_members(
+            &mut coordinators,
+            &mut workers,
+            0,
+            vec![
+                Member::new("c1", 5432, true),
+                Member::new("c2", 5432, false),
+            ],
+        );
+        add_group_members(
+            &mut coordinators,
+            &mut workers,
+            1,
+            vec![Member::new("w1", 5433, true), Member::new("c2", 5432, true)],
+        );
+        let all_members: Vec<&Member> = coordinators.iter().chain(workers.iter()).collect();
+        let server_groups = get_server_groups(&coordinators, &workers, &all_members, 5432);
+        let groups: Vec<String> = server_groups
+            .iter()
+            .map(|server_group| {
+                let servers: Vec<String> = server_group
+                    .servers
+                    .iter()
+                    .map(|server| match server.port {
+                        Some(port) => format!("{}:{}", server.host, port),
+                        None => server.host.clone(),
+                    })
+                    .collect();
+                format!("{}: {}", server_group.name, servers.join(", "))
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                "coordinators: c1, c2",
+                "workers: w1:5433",
+                "primary: c1, w1:5433",
+                "dr: c2",
+            ]
+        );
+
+        let seed_members = [Member::new("p1", 5432, true)];
+        let all_members: Vec<&Member> = seed_members.iter().collect();
+        let server_groups = get_server_groups(&[], &[], &all_members, 5432);
+        let names: Vec<&str> = server_groups
+            .iter()
+            .map(|group| group.name.as_str())
+            .collect();
+        assert_eq!(names, ["primary"]);
+    }
+}
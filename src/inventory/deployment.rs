@@ -1,9 +1,20 @@
+use crate::inventory::dynamic_group::DynamicGroup;
 use crate::inventory::environment::Environment;
+use crate::inventory::server_defaults::ServerDefaults;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct Deployment {
     pub name: String,
     pub default_environment_name: String,
+    /// Files with more environments, relative to the file which includes them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(flatten)]
+    pub defaults: ServerDefaults,
+    /// Server groups built from facts, a group of a nearer level replaces one with the same name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dynamic_groups: Vec<DynamicGroup>,
+    #[serde(default)]
     pub environments: Vec<Environment>,
 }
@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+/// Server group built from facts like the built-in dynamic groups
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct DynamicGroup {
+    pub name: String,
+    /// Condition on facts of a server, "patroni_is_replica && citus_group_id > 0"
+    pub when: String,
+}
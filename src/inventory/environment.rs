@@ -1,9 +1,19 @@
 use crate::inventory::cluster::Cluster;
+use crate::inventory::dynamic_group::DynamicGroup;
+use crate::inventory::server_defaults::ServerDefaults;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct Environment {
     pub name: String,
     pub default_cluster_name: String,
+    /// Every command in the environment needs a typed confirmation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protected: Option<bool>,
+    #[serde(flatten)]
+    pub defaults: ServerDefaults,
+    /// Server groups built from facts, a group of a nearer level replaces one with the same name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dynamic_groups: Vec<DynamicGroup>,
     pub clusters: Vec<Cluster>,
 }
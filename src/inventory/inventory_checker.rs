@@ -0,0 +1,539 @@
+use crate::inventory::cluster::Cluster;
+use crate::inventory::deployment::Deployment;
+use crate::inventory::dynamic_group::DynamicGroup;
+use crate::inventory::inventory_loader::{InventoryFile, interpolate_text};
+use crate::server_provider::fact_predicate::FactPredicate;
+use crate::server_provider::server_provider::DYNAMIC_SERVER_GROUP_NAMES;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+
+/// A mistake in the inventory, line numbers start with 1.
+/// The file is only set for files included by the inventory file
+pub struct InventoryProblem {
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub message: String,
+    file_index: usize,
+}
+
+impl InventoryProblem {
+    fn new(
+        inventory_files: &[InventoryFile],
+        location: Option<(usize, usize)>,
+        message: String,
+    ) -> Self {
+        let file_index = location.map_or(0, |(file_index, _)| file_index);
+        Self {
+            file: Some(file_index)
+                .filter(|file_index| *file_index > 0)
+                .map(|file_index| inventory_files[file_index].path.display().to_string()),
+            line: location.map(|(_, line)| line),
+            message,
+            file_index,
+        }
+    }
+}
+
+impl fmt::Display for InventoryProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "Line {} of {}: {}", line, file, self.message),
+            (None, Some(line)) => write!(f, "Line {}: {}", line, self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+type PathStep<'a> = (&'a str, Option<&'a str>);
+
+/// Checks the deployment loaded from the inventory files and returns every problem
+/// sorted by file and line
+pub fn check_inventory(
+    deployment: &Deployment,
+    inventory_files: &[InventoryFile],
+    collect_citus_facts: bool,
+) -> Vec<InventoryProblem> {
+    let lines = InventoryLines::new(inventory_files);
+    let problem = |location: Option<(usize, usize)>, message: String| {
+        InventoryProblem::new(inventory_files, location, message)
+    };
+    let mut problems: Vec<InventoryProblem> = Vec::new();
+    if !deployment
+        .environments
+        .iter()
+        .any(|environment| environment.name == deployment.default_environment_name)
+    {
+        problems.push(problem(
+            lines.find_in_file(0, &[("default_environment_name", None)]),
+            format!(
+                "default_environment_name <{}> is not an environment of the inventory",
+                deployment.default_environment_name
+            ),
+        ));
+    }
+    let deployment_clusters: Vec<(String, &Cluster)> = deployment
+        .environments
+        .iter()
+        .flat_map(|environment| {
+            environment
+                .clusters
+                .iter()
+                .map(|cluster| (format!("{}/{}", environment.name, cluster.name), cluster))
+        })
+        .collect();
+    problems.extend(check_dynamic_groups(
+        &deployment.dynamic_groups,
+        &[],
+        "the deployment",
+        &deployment_clusters,
+        &lines,
+        inventory_files,
+    ));
+    // "host:port" of every server with the first cluster it is in
+    let mut server_clusters: HashMap<(String, i32), String> = HashMap::new();
+    let mut reported_servers: HashSet<(String, i32, String)> = HashSet::new();
+    for environment in &deployment.environments {
+        let environment_path = [
+            ("environments", None),
+            ("name", Some(environment.name.as_str())),
+        ];
+        if !environment.default_cluster_name.is_empty()
+            && !environment
+                .clusters
+                .iter()
+                .any(|cluster| cluster.name == environment.default_cluster_name)
+        {
+            problems.push(problem(
+                lines.find(&[&environment_path[..], &[("default_cluster_name", None)]].concat()),
+                format!(
+                    "default_cluster_name <{}> is not a cluster of environment <{}>",
+                    environment.default_cluster_name, environment.name
+                ),
+            ));
+        }
+        let environment_clusters: Vec<(String, &Cluster)> = environment
+            .clusters
+            .iter()
+            .map(|cluster| (format!("{}/{}", environment.name, cluster.name), cluster))
+            .collect();
+        problems.extend(check_dynamic_groups(
+            &environment.dynamic_groups,
+            &environment_path,
+            &format!("environment <{}>", environment.name),
+            &environment_clusters,
+            &lines,
+            inventory_files,
+        ));
+        let environment_defaults = environment.defaults.merge(&deployment.defaults);
+        for cluster in &environment.clusters {
+            let cluster_defaults = cluster.defaults.merge(&environment_defaults);
+            let cluster_label = format!("{}/{}", environment.name, cluster.name);
+            let cluster_path: Vec<PathStep> = [
+                &environment_path[..],
+                &[("clusters", None), ("name", Some(cluster.name.as_str()))],
+            ]
+            .concat();
+            let has_servers = cluster
+                .server_groups
+                .iter()
+                .any(|server_group| !server_group.servers.is_empty());
+            if collect_citus_facts && has_servers && cluster.citus_db_name.is_none() {
+                problems.push(problem(
+                    lines.find(&cluster_path),
+                    format!(
+                        "Cluster <{}> has servers but no citus_db_name, citus facts are not collected for it",
+                        cluster_label
+                    ),
+                ));
+            }
+            problems.extend(check_dynamic_groups(
+                &cluster.dynamic_groups,
+                &cluster_path,
+                &format!("cluster <{}>", cluster_label),
+                &[(cluster_label.clone(), cluster)],
+                &lines,
+                inventory_files,
+            ));
+            for server_group in &cluster.server_groups {
+                let server_group_path: Vec<PathStep> = [
+                    &cluster_path[..],
+                    &[
+                        ("server_groups", None),
+                        ("name", Some(server_group.name.as_str())),
+                    ],
+                ]
+                .concat();
+                if DYNAMIC_SERVER_GROUP_NAMES.contains(&server_group.name.as_str()) {
+                    problems.push(problem(
+                        lines.find(&server_group_path),
+                        format!(
+                            "Server group <{}> of cluster <{}> has the name of a built-in group",
+                            server_group.name, cluster_label
+                        ),
+                    ));
+                }
+                let server_group_defaults = server_group.defaults.merge(&cluster_defaults);
+                let mut hosts: Vec<&str> = Vec::new();
+                for server in &server_group.servers {
+                    let host_locations = lines.find_all(
+                        &[
+                            &server_group_path[..],
+                            &[("servers", None), ("host", Some(server.host.as_str()))],
+                        ]
+                        .concat(),
+                    );
+                    let occurrence = hosts.iter().filter(|host| **host == server.host).count();
+                    let location = host_locations.get(occurrence).copied();
+                    if occurrence > 0 {
+                        problems.push(problem(
+                            location,
+                            format!(
+                                "Host <{}> is listed more than once in server group <{}> of cluster <{}>",
+                                server.host, server_group.name, cluster_label
+                            ),
+                        ));
+                    }
+                    hosts.push(&server.host);
+
+                    let port = server
+                        .port
+                        .or(server_group_defaults.default_port)
+                        .unwrap_or(5432);
+                    let first_cluster_label = server_clusters
+                        .entry((server.host.clone(), port))
+                        .or_insert_with(|| cluster_label.clone());
+                    if *first_cluster_label != cluster_label
+                        && reported_servers.insert((
+                            server.host.clone(),
+                            port,
+                            cluster_label.clone(),
+                        ))
+                    {
+                        problems.push(problem(
+                            location,
+                            format!(
+                                "Host <{}:{}> of cluster <{}> is also in cluster <{}>",
+                                server.host, port, cluster_label, first_cluster_label
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    problems.sort_by_key(|problem| (problem.file_index, problem.line));
+    problems
+}
+
+/// Dynamic groups with names of built-in groups or server groups of their clusters
+/// or with wrong conditions,
+/// the path is the path of the deployment, environment or cluster which has them
+fn check_dynamic_groups(
+    dynamic_groups: &[DynamicGroup],
+    path: &[PathStep],
+    owner: &str,
+    clusters: &[(String, &Cluster)],
+    lines: &InventoryLines,
+    inventory_files: &[InventoryFile],
+) -> Vec<InventoryProblem> {
+    let mut problems: Vec<InventoryProblem> = Vec::new();
+    for dynamic_group in dynamic_groups {
+        let dynamic_group_path: Vec<PathStep> = [
+            path,
+            &[
+                ("dynamic_groups", None),
+                ("name", Some(dynamic_group.name.as_str())),
+            ],
+        ]
+        .concat();
+        // keys of the deployment are only in the inventory file
+        let find = |dynamic_group_path: &[PathStep]| {
+            if path.is_empty() {
+                lines.find_in_file(0, dynamic_group_path)
+            } else {
+                lines.find(dynamic_group_path)
+            }
+        };
+        if DYNAMIC_SERVER_GROUP_NAMES.contains(&dynamic_group.name.as_str()) {
+            problems.push(InventoryProblem::new(
+                inventory_files,
+                find(&dynamic_group_path),
+                format!(
+                    "Dynamic group <{}> of {} has the name of a built-in group",
+                    dynamic_group.name, owner
+                ),
+            ));
+        }
+        let clashing_clusters: Vec<String> = clusters
+            .iter()
+            .filter(|(_, cluster)| {
+                cluster
+                    .server_groups
+                    .iter()
+                    .any(|server_group| server_group.name == dynamic_group.name)
+            })
+            .map(|(cluster_label, _)| format!("<{}>", cluster_label))
+            .collect();
+        if !clashing_clusters.is_empty() {
+            problems.push(InventoryProblem::new(
+                inventory_files,
+                find(&dynamic_group_path),
+                format!(
+                    "Dynamic group <{}> of {} has the name of a server group of cluster {}",
+                    dynamic_group.name,
+                    owner,
+                    clashing_clusters.join(", ")
+                ),
+            ));
+        }
+        if let Err(e) = FactPredicate::parse(&dynamic_group.when) {
+            problems.push(InventoryProblem::new(
+                inventory_files,
+                find(&[&dynamic_group_path[..], &[("when", None)]].concat()),
+                format!(
+                    "Condition of dynamic group <{}> of {} is wrong: {}",
+                    dynamic_group.name, owner, e
+                ),
+            ));
+        }
+    }
+    problems
+}
+
+/// Lines of every inventory file, a path is looked up in the files one after another
+struct InventoryLines {
+    files: Vec<YamlLines>,
+}
+
+impl InventoryLines {
+    fn new(inventory_files: &[InventoryFile]) -> Self {
+        Self {
+            files: inventory_files
+                .iter()
+                .map(|inventory_file| YamlLines::new(&inventory_file.content))
+                .collect(),
+        }
+    }
+
+    /// File index and line of the first match
+    fn find(&self, path: &[PathStep]) -> Option<(usize, usize)> {
+        self.find_all(path).first().copied()
+    }
+
+    fn find_in_file(&self, file_index: usize, path: &[PathStep]) -> Option<(usize, usize)> {
+        let line = self.files.get(file_index)?.find(path)?;
+        Some((file_index, line))
+    }
+
+    /// Matches of the first file which has the path
+    fn find_all(&self, path: &[PathStep]) -> Vec<(usize, usize)> {
+        for (file_index, yaml_lines) in self.files.iter().enumerate() {
+            let lines = yaml_lines.find_all(path);
+            if !lines.is_empty() {
+                return lines.into_iter().map(|line| (file_index, line)).collect();
+            }
+        }
+        Vec::new()
+    }
+}
+
+struct YamlLine {
+    number: usize,
+    /// Column of the key, after the "-" of a sequence item
+    column: usize,
+    is_item: bool,
+    key: String,
+    value: String,
+}
+
+/// Keys of a block style YAML file with their lines, serde_yaml does not keep positions
+struct YamlLines {
+    lines: Vec<YamlLine>,
+}
+
+impl YamlLines {
+    fn new(content: &str) -> Self {
+        let mut lines = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let mut rest = line.trim_start();
+            if rest.is_empty() || rest.starts_with('#') || rest.starts_with("---") || rest == "..."
+            {
+                continue;
+            }
+            let mut column = line.len() - rest.len();
+            let mut is_item = false;
+            while rest == "-" || rest.starts_with("- ") {
+                let item = rest[1..].trim_start();
+                column += rest.len() - item.len();
+                rest = item;
+                is_item = true;
+            }
+            let (key, value) = rest.split_once(':').unwrap_or(("", rest));
+            let value = value.split(" #").next().unwrap_or_default().trim();
+            let value = value.trim_matches(['\'', '"']);
+            // names can come from variables
+            let interpolated_value = interpolate_text(value).ok().flatten();
+            lines.push(YamlLine {
+                number: index + 1,
+                column,
+                is_item,
+                key: key.trim().to_string(),
+                value: interpolated_value.unwrap_or_else(|| value.to_string()),
+            });
+        }
+        Self { lines }
+    }
+
+    fn find(&self, path: &[PathStep]) -> Option<usize> {
+        self.find_all(path).first().copied()
+    }
+
+    /// Line numbers of the last step of the path. A step with a value goes into the mapping
+    /// which has the key with this value, a step without a value goes into the block of the key
+    fn find_all(&self, path: &[PathStep]) -> Vec<usize> {
+        let mut range = 0..self.lines.len();
+        for (position, (key, value)) in path.iter().enumerate() {
+            let indexes: Vec<usize> = self
+                .get_keys(range)
+                .filter(|&index| {
+                    let line = &self.lines[index];
+                    line.key == *key && value.is_none_or(|value| line.value == value)
+                })
+                .collect();
+            if position == path.len() - 1 {
+                return indexes
+                    .iter()
+                    .map(|&index| self.lines[index].number)
+                    .collect();
+            }
+            let Some(&index) = indexes.first() else {
+                return Vec::new();
+            };
+            range = match value {
+                Some(_) => self.get_mapping(index),
+                None => self.get_block(index),
+            };
+        }
+        Vec::new()
+    }
+
+    /// Keys on the first level of the range
+    fn get_keys(&self, range: Range<usize>) -> impl Iterator<Item = usize> + '_ {
+        let column = self.lines.get(range.start).map(|line| line.column);
+        range.filter(move |&index| Some(self.lines[index].column) == column)
+    }
+
+    /// Lines nested under the key
+    fn get_block(&self, index: usize) -> Range<usize> {
+        let column = self.lines[index].column;
+        let end = (index + 1..self.lines.len())
+            .find(|&next| self.lines[next].column <= column)
+            .unwrap_or(self.lines.len());
+        index + 1..end
+    }
+
+    /// The mapping which has the key, a mapping in a sequence starts on its "-" line
+    fn get_mapping(&self, index: usize) -> Range<usize> {
+        let column = self.lines[index].column;
+        let is_item_start = |line: &YamlLine| line.is_item && line.column == column;
+        let mut start = index;
+        while start > 0
+            && !is_item_start(&self.lines[start])
+            && self.lines[start - 1].column >= column
+        {
+            start -= 1;
+        }
+        let end = (index + 1..self.lines.len())
+            .find(|&next| self.lines[next].column < column || is_item_start(&self.lines[next]))
+            .unwrap_or(self.lines.len());
+        start..end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_inventory() {
+        let content = "\
+name: organization
+default_environment_name: qa
+include: [test.yml]
+environments:
+  - name: dev
+    default_cluster_name: cloud
+    default_port: 5433
+    clusters:
+      - name: cloud
+        citus_db_name: stampede
+        dynamic_groups:
+          - name: caw
+            when: citus_is_active_worker_node
+          - name: lagging
+            when: \"patroni_is_replica &&\"
+        server_groups:
+          - servers:
+              - host: db1
+              - host: db2
+              - host: db1
+            name: primary
+          - name: pgl
+            servers:
+              - host: db2
+";
+        let included_content = "\
+environments:
+  - name: test
+    default_cluster_name: cloud2
+    clusters:
+      - name: cloud2
+        server_groups:
+          - name: primary
+            servers:
+              - host: db2
+                port: 5433
+    dynamic_groups:
+      - name: primary
+        when: patroni_is_primary
+";
+        let mut deployment: Deployment = serde_yaml::from_str(content).unwrap();
+        let included_deployment: Deployment = serde_yaml::from_str(&format!(
+            "name: a\ndefault_environment_name: b\n{included_content}"
+        ))
+        .unwrap();
+        deployment
+            .environments
+            .extend(included_deployment.environments);
+        let inventory_files = [
+            InventoryFile {
+                path: "inventory.taco.yml".into(),
+                content: content.to_string(),
+                has_variables: false,
+            },
+            InventoryFile {
+                path: "test.yml".into(),
+                content: included_content.to_string(),
+                has_variables: false,
+            },
+        ];
+        let problems: Vec<String> = check_inventory(&deployment, &inventory_files, true)
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                "Line 2: default_environment_name <qa> is not an environment of the inventory",
+                "Line 12: Dynamic group <caw> of cluster <dev/cloud> has the name of a built-in group",
+                "Line 15: Condition of dynamic group <lagging> of cluster <dev/cloud> is wrong: EXPECTED FACT NAME AT POSITION 22",
+                "Line 20: Host <db1> is listed more than once in server group <primary> of cluster <dev/cloud>",
+                "Line 22: Server group <pgl> of cluster <dev/cloud> has the name of a built-in group",
+                "Line 5 of test.yml: Cluster <test/cloud2> has servers but no citus_db_name, citus facts are not collected for it",
+                "Line 9 of test.yml: Host <db2:5433> of cluster <test/cloud2> is also in cluster <dev/cloud>",
+                "Line 12 of test.yml: Dynamic group <primary> of environment <test> has the name of a server group of cluster <test/cloud2>",
+            ]
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use crate::inventory::deployment::Deployment;
+use crate::inventory::environment::Environment;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_yaml::Value;
+use std::collections::VecDeque;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// File of the include list, it has more environments and can include other files
+#[derive(Deserialize)]
+struct IncludedInventory {
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    environments: Vec<Environment>,
+}
+
+/// Content of an inventory file as it is written, ${...} are replaced in the parsed values
+pub struct InventoryFile {
+    pub path: PathBuf,
+    pub content: String,
+    /// The content has ${...} which were replaced
+    pub has_variables: bool,
+}
+
+/// Reads the inventory file and every file it includes, the inventory file comes first.
+/// Environments of included files are added after the environments of the inventory file
+pub async fn load_inventory(path: &Path) -> Result<(Deployment, Vec<InventoryFile>)> {
+    let (inventory_file, value) = read_inventory_file(path).await?;
+    let mut deployment: Deployment = deserialize(&inventory_file, value)
+        .with_context(|| format!("Failed to deserialize inventory file: {}", path.display()))?;
+    let mut include_paths: VecDeque<PathBuf> = get_include_paths(path, &deployment.include);
+    let mut canonical_paths = vec![tokio::fs::canonicalize(path).await?];
+    let mut inventory_files = vec![inventory_file];
+    while let Some(include_path) = include_paths.pop_front() {
+        let (inventory_file, value) = read_inventory_file(&include_path).await?;
+        let canonical_path = tokio::fs::canonicalize(&include_path).await?;
+        if canonical_paths.contains(&canonical_path) {
+            return Err(anyhow::anyhow!(
+                "Inventory file {} is included more than once",
+                include_path.display()
+            ));
+        }
+        let included_inventory: IncludedInventory = deserialize(&inventory_file, value)
+            .with_context(|| {
+                format!(
+                    "Failed to deserialize included inventory file: {}",
+                    include_path.display()
+                )
+            })?;
+        include_paths.extend(get_include_paths(
+            &include_path,
+            &included_inventory.include,
+        ));
+        deployment
+            .environments
+            .extend(included_inventory.environments);
+        canonical_paths.push(canonical_path);
+        inventory_files.push(inventory_file);
+    }
+    Ok((deployment, inventory_files))
+}
+
+fn get_include_paths(path: &Path, include: &[String]) -> VecDeque<PathBuf> {
+    let directory = path.parent().unwrap_or(Path::new(""));
+    include
+        .iter()
+        .map(|include_path| directory.join(include_path))
+        .collect()
+}
+
+async fn read_inventory_file(path: &Path) -> Result<(InventoryFile, Value)> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read inventory file: {}", path.display()))?;
+    let mut value: Value = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to deserialize inventory file: {}", path.display()))?;
+    let has_variables = interpolate_variables(&mut value, "")
+        .with_context(|| format!("Failed to read inventory file: {}", path.display()))?;
+    let inventory_file = InventoryFile {
+        path: path.to_path_buf(),
+        content,
+        has_variables,
+    };
+    Ok((inventory_file, value))
+}
+
+/// Errors of a file without variables are deserialized from the text to keep line numbers
+fn deserialize<T: DeserializeOwned>(
+    inventory_file: &InventoryFile,
+    value: Value,
+) -> serde_yaml::Result<T> {
+    if inventory_file.has_variables {
+        serde_yaml::from_value(value)
+    } else {
+        serde_yaml::from_str(&inventory_file.content)
+    }
+}
+
+/// Replaces ${NAME} in string values with the environment variable NAME, so values never change
+/// the structure of the inventory. A value which is only ${NAME} becomes a number or a boolean
+/// when the variable has one, e.g. a port. Returns true when something was replaced
+fn interpolate_variables(value: &mut Value, path: &str) -> Result<bool> {
+    let mut has_variables = false;
+    match value {
+        Value::String(text) => {
+            let Some(interpolated) =
+                interpolate_text(text).with_context(|| format!("Wrong value of {}", path))?
+            else {
+                return Ok(false);
+            };
+            let is_one_variable = text.starts_with("${") && text.find('}') == Some(text.len() - 1);
+            *value = match serde_yaml::from_str::<Value>(&interpolated) {
+                Ok(typed @ (Value::Number(_) | Value::Bool(_))) if is_one_variable => typed,
+                _ => Value::String(interpolated),
+            };
+            has_variables = true;
+        }
+        Value::Sequence(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                has_variables |= interpolate_variables(item, &format!("{}[{}]", path, index))?;
+            }
+        }
+        Value::Mapping(mapping) => {
+            for (key, item) in mapping.iter_mut() {
+                let key = key.as_str().unwrap_or_default();
+                let item_path = match path {
+                    "" => key.to_string(),
+                    _ => format!("{}.{}", path, key),
+                };
+                has_variables |= interpolate_variables(item, &item_path)?;
+            }
+        }
+        Value::Tagged(tagged) => has_variables = interpolate_variables(&mut tagged.value, path)?,
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+    Ok(has_variables)
+}
+
+/// Text with ${NAME} replaced, $${ is a literal ${ for shell commands. None without variables
+pub fn interpolate_text(text: &str) -> Result<Option<String>> {
+    if !text.contains("${") {
+        return Ok(None);
+    }
+    let mut interpolated = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        interpolated.push_str(&rest[..start]);
+        let variable = &rest[start..];
+        if let Some(after) = variable.strip_prefix("$${") {
+            interpolated.push_str("${");
+            rest = after;
+        } else if let Some(after) = variable.strip_prefix("${") {
+            let end = after.find('}').context("Variable has no closing brace")?;
+            let name = &after[..end];
+            let value = env::var(name)
+                .map_err(|_| anyhow::anyhow!("Environment variable <{}> is not set", name))?;
+            interpolated.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            interpolated.push('$');
+            rest = &variable[1..];
+        }
+    }
+    interpolated.push_str(rest);
+    Ok(Some(interpolated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate_variables() {
+        let path = env::var("PATH").unwrap();
+        let content = "# ${NOT_REPLACED}\n\
+            default_passfile: \"${PATH}\"\n\
+            default_password_command: echo $${SECRET} $HOME\n\
+            default_user: \"a: ${PATH}\"\n";
+        let mut value: Value = serde_yaml::from_str(content).unwrap();
+        assert!(interpolate_variables(&mut value, "").unwrap());
+        let expected: Value = serde_yaml::from_str(&format!(
+            "default_passfile: '{0}'\n\
+            default_password_command: echo ${{SECRET}} $HOME\n\
+            default_user: 'a: {0}'\n",
+            path
+        ))
+        .unwrap();
+        assert_eq!(value, expected);
+
+        let mut value: Value =
+            serde_yaml::from_str("environments:\n- name: a\n  default_port: ${TACO_TEST_PORT}\n")
+                .unwrap();
+        let error = interpolate_variables(&mut value, "").unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "Wrong value of environments[0].default_port: \
+            Environment variable <TACO_TEST_PORT> is not set"
+        );
+        unsafe { env::set_var("TACO_TEST_PORT", "5433") };
+        interpolate_variables(&mut value, "").unwrap();
+        assert_eq!(value["environments"][0]["default_port"], Value::from(5433));
+    }
+}
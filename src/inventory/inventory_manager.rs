@@ -1,7 +1,12 @@
 use crate::inventory::cluster::Cluster;
 use crate::inventory::deployment::Deployment;
+use crate::inventory::dynamic_group::DynamicGroup;
 use crate::inventory::environment::Environment;
+use crate::inventory::inventory_checker::{InventoryProblem, check_inventory};
+use crate::inventory::inventory_loader::{InventoryFile, load_inventory};
 pub(crate) use crate::inventory::server::Server;
+use crate::inventory::server_defaults::ServerDefaults;
+use crate::shared::cluster_server_groups::ClusterServerGroups;
 use anyhow::{Context, Result};
 use std::collections::{HashMap, HashSet};
 use std::path::Path;
@@ -11,6 +16,11 @@ use tokio::io::AsyncWriteExt;
 pub struct InventoryManager<'a> {
     inventory_file_name: &'a String,
     deployment: Option<Deployment>,
+    /// The inventory file and the files it includes
+    inventory_files: Vec<InventoryFile>,
+    /// Selected environment and cluster, None is the default one
+    environment_name: Option<String>,
+    cluster_name: Option<String>,
 }
 
 impl<'a> InventoryManager<'a> {
@@ -18,38 +28,47 @@ impl<'a> InventoryManager<'a> {
         Self {
             inventory_file_name,
             deployment: None,
+            inventory_files: Vec::new(),
+            environment_name: None,
+            cluster_name: None,
         }
     }
 
     pub async fn load_inventory_from_file(&mut self) -> Result<()> {
-        let content = tokio::fs::read_to_string(self.inventory_file_name)
-            .await
-            .with_context(|| {
-                format!(
-                    "Failed to read inventory file: {}",
-                    self.inventory_file_name
-                )
-            })?;
+        let (deployment, inventory_files) =
+            load_inventory(Path::new(self.inventory_file_name)).await?;
+        self.deployment = Some(deployment);
+        self.inventory_files = inventory_files;
 
-        self.deployment = Some(serde_yaml::from_str(&content).with_context(|| {
-            format!(
-                "Failed to deserialize inventory file: {}",
-                self.inventory_file_name
-            )
-        })?);
+        Ok(())
+    }
 
-        if self.deployment.is_none() {
-            return Err(anyhow::anyhow!(
-                "Failed to load inventory: Deployment is missing"
-            ));
+    /// Problems of the loaded inventory files with their lines
+    pub fn check_inventory(&self, collect_citus_facts: bool) -> Vec<InventoryProblem> {
+        match &self.deployment {
+            Some(deployment) => {
+                check_inventory(deployment, &self.inventory_files, collect_citus_facts)
+            }
+            None => Vec::new(),
         }
-
-        Ok(())
     }
 
     pub async fn save_inventory_to_file(&self, inventory_file_name: &str) -> Result<()> {
         let path = Path::new(&inventory_file_name);
 
+        // included environments and values of variables would be written into one file
+        if self.inventory_files.len() > 1
+            || self
+                .inventory_files
+                .iter()
+                .any(|inventory_file| inventory_file.has_variables)
+        {
+            return Err(anyhow::anyhow!(
+                "Inventory file {} has includes or variables, it can not be saved",
+                self.inventory_file_name
+            ));
+        }
+
         let mut output = tokio::fs::File::create(&path).await.with_context(|| {
             format!("Failed to create output inventory file: {inventory_file_name}")
         })?;
@@ -78,85 +97,331 @@ impl<'a> InventoryManager<'a> {
         Ok(())
     }
 
-    fn get_default_environment(&self) -> Result<&Environment> {
+    fn get_current_environment(&self) -> Result<&Environment> {
         if let Some(deployment) = &self.deployment {
-            let default_environment = deployment
+            let environment_name = self
+                .environment_name
+                .as_ref()
+                .unwrap_or(&deployment.default_environment_name);
+            let environment = deployment
                 .environments
                 .iter()
-                .find(|env| env.name == deployment.default_environment_name);
+                .find(|env| &env.name == environment_name);
 
-            Ok(default_environment.context("No default environment found")?)
+            Ok(environment.with_context(|| format!("Environment <{environment_name}> not found"))?)
         } else {
             Err(anyhow::anyhow!("No deployment loaded"))
         }
     }
 
-    fn get_default_cluster<'b>(&self, environment: &'b Environment) -> Result<&'b Cluster> {
-        let default_cluster = environment
+    fn get_current_cluster<'b>(&self, environment: &'b Environment) -> Result<&'b Cluster> {
+        let cluster_name = self
+            .cluster_name
+            .as_ref()
+            .unwrap_or(&environment.default_cluster_name);
+        if cluster_name.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Environment <{}> has no default cluster",
+                environment.name
+            ));
+        }
+        let cluster = environment
             .clusters
             .iter()
-            .find(|cluster| cluster.name == environment.default_cluster_name);
-        default_cluster.ok_or_else(|| anyhow::anyhow!("No default cluster found"))
+            .find(|cluster| &cluster.name == cluster_name);
+        cluster.ok_or_else(|| {
+            anyhow::anyhow!(
+                "Cluster <{}> not found in environment <{}>",
+                cluster_name,
+                environment.name
+            )
+        })
+    }
+
+    /// Switches to the environment and the cluster, None selects the default one.
+    /// The selection stays as it was when the environment or the cluster is not found
+    pub fn select(
+        &mut self,
+        environment_name: Option<String>,
+        cluster_name: Option<String>,
+    ) -> Result<()> {
+        let previous_environment_name = self.environment_name.take();
+        let previous_cluster_name = self.cluster_name.take();
+        self.environment_name = environment_name;
+        self.cluster_name = cluster_name;
+        let selection_result = self
+            .get_current_environment()
+            .and_then(|environment| self.get_current_cluster(environment).map(|_| ()));
+        if selection_result.is_err() {
+            self.environment_name = previous_environment_name;
+            self.cluster_name = previous_cluster_name;
+        }
+        selection_result
+    }
+
+    pub fn get_selection(&self) -> (Option<String>, Option<String>) {
+        (self.environment_name.clone(), self.cluster_name.clone())
+    }
+
+    /// Names of the current environment and cluster for the prompt
+    pub fn get_current_names(&self) -> Option<(String, String)> {
+        let environment = self.get_current_environment().ok()?;
+        let cluster = self.get_current_cluster(environment).ok()?;
+        Some((environment.name.clone(), cluster.name.clone()))
+    }
+
+    pub fn get_environment_names(&self) -> Vec<String> {
+        self.deployment
+            .iter()
+            .flat_map(|deployment| deployment.environments.iter())
+            .map(|environment| environment.name.clone())
+            .collect()
+    }
+
+    /// Cluster names of the current environment
+    pub fn get_cluster_names(&self) -> Vec<String> {
+        self.get_current_environment()
+            .map(|environment| {
+                environment
+                    .clusters
+                    .iter()
+                    .map(|cluster| cluster.name.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
     }
 
     pub fn get_static_server_groups(
         &self,
     ) -> Option<(HashMap<String, Vec<Server>>, Option<String>)> {
-        if let Ok(default_environment) = self.get_default_environment() {
-            if let Ok(default_cluster) = self.get_default_cluster(default_environment) {
-                let mut server_groups: HashMap<String, Vec<Server>> = default_cluster
-                    .server_groups
+        let environment = self.get_current_environment().ok()?;
+        let cluster = self.get_current_cluster(environment).ok()?;
+        let defaults = self.get_cluster_defaults(environment, cluster);
+        Some((
+            Self::get_server_groups(cluster, &defaults),
+            cluster.citus_db_name.clone(),
+        ))
+    }
+
+    /// Dynamic groups of the inventory for the current cluster
+    pub fn get_dynamic_groups(&self) -> Vec<DynamicGroup> {
+        let Ok(environment) = self.get_current_environment() else {
+            return Vec::new();
+        };
+        match self.get_current_cluster(environment) {
+            Ok(cluster) => self.get_cluster_dynamic_groups(environment, cluster),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Static server groups of every cluster in every environment
+    pub fn get_all_cluster_server_groups(&self) -> Vec<ClusterServerGroups> {
+        let Some(deployment) = &self.deployment else {
+            return Vec::new();
+        };
+        deployment
+            .environments
+            .iter()
+            .flat_map(|environment| {
+                environment
+                    .clusters
                     .iter()
-                    .map(|server_group| {
-                        (
-                            server_group.name.clone(),
-                            server_group
-                                .servers
-                                .iter()
-                                .map(|server| {
-                                    Server::from(
-                                        server,
-                                        (
-                                            &default_cluster.default_port,
-                                            &default_cluster.default_db_name,
-                                            &default_cluster.default_user,
-                                            &default_cluster.default_password,
-                                            &default_cluster.default_connect_timeout_sec,
-                                        ),
-                                    )
-                                })
-                                .collect(),
-                        )
+                    .map(move |cluster| ClusterServerGroups {
+                        environment_name: environment.name.clone(),
+                        cluster_name: cluster.name.clone(),
+                        server_groups: Self::get_server_groups(
+                            cluster,
+                            &self.get_cluster_defaults(environment, cluster),
+                        ),
+                        dynamic_groups: self.get_cluster_dynamic_groups(environment, cluster),
+                        citus_db_name: cluster.citus_db_name.clone(),
+                        protected: environment.protected == Some(true)
+                            || cluster.protected == Some(true),
                     })
-                    .collect();
+            })
+            .collect()
+    }
+
+    /// Defaults of the deployment, the environment and the cluster, the cluster wins
+    fn get_cluster_defaults(&self, environment: &Environment, cluster: &Cluster) -> ServerDefaults {
+        let environment_defaults = match &self.deployment {
+            Some(deployment) => environment.defaults.merge(&deployment.defaults),
+            None => environment.defaults.clone(),
+        };
+        cluster.defaults.merge(&environment_defaults)
+    }
+
+    /// Dynamic groups of the deployment, the environment and the cluster,
+    /// a group of the cluster replaces the group with the same name of the environment
+    fn get_cluster_dynamic_groups(
+        &self,
+        environment: &Environment,
+        cluster: &Cluster,
+    ) -> Vec<DynamicGroup> {
+        let mut dynamic_groups: Vec<DynamicGroup> = Vec::new();
+        for dynamic_group in self
+            .deployment
+            .iter()
+            .flat_map(|deployment| deployment.dynamic_groups.iter())
+            .chain(environment.dynamic_groups.iter())
+            .chain(cluster.dynamic_groups.iter())
+        {
+            dynamic_groups.retain(|other| other.name != dynamic_group.name);
+            dynamic_groups.push(dynamic_group.clone());
+        }
+        dynamic_groups
+    }
 
-                // We use HashSet here to filter out duplicates
-                let all_servers: HashSet<Server> = server_groups
-                    .values()
-                    .flat_map(|servers| {
-                        servers.iter().map(|server| {
-                            Server::from(
-                                server,
-                                (
-                                    &default_cluster.default_port,
-                                    &default_cluster.default_db_name,
-                                    &default_cluster.default_user,
-                                    &default_cluster.default_password,
-                                    &default_cluster.default_connect_timeout_sec,
-                                ),
-                            )
+    fn get_server_groups(
+        cluster: &Cluster,
+        defaults: &ServerDefaults,
+    ) -> HashMap<String, Vec<Server>> {
+        let mut server_groups: HashMap<String, Vec<Server>> = cluster
+            .server_groups
+            .iter()
+            .map(|server_group| {
+                let server_group_defaults = server_group.defaults.merge(defaults);
+                (
+                    server_group.name.clone(),
+                    server_group
+                        .servers
+                        .iter()
+                        .map(|server| {
+                            let mut server = Server::from(server, &server_group_defaults);
+                            server.citus_db_name = cluster.citus_db_name.clone();
+                            server
                         })
-                    })
-                    .collect();
+                        .collect(),
+                )
+            })
+            .collect();
 
-                server_groups.insert("all".to_string(), Vec::from_iter(all_servers));
+        // We use HashSet here to filter out duplicates, servers of groups with different
+        // defaults are the same server when host and port are the same. Groups are taken in
+        // inventory order, so the first group of a server decides its defaults
+        let mut server_addresses: HashSet<(String, Option<i32>)> = HashSet::new();
+        let all_servers: Vec<Server> = cluster
+            .server_groups
+            .iter()
+            .flat_map(|server_group| &server_groups[&server_group.name])
+            .filter(|server| server_addresses.insert((server.host.clone(), server.port)))
+            .cloned()
+            .collect();
+
+        server_groups.insert("all".to_string(), all_servers);
+        server_groups
+    }
 
-                let citus_db_name = default_cluster.citus_db_name.clone();
+    /// Settings of the cluster without servers and its defaults merged with the defaults of the
+    /// environment and the deployment. None selects the default one, settings are empty when
+    /// the environment has no default cluster
+    pub fn get_cluster_template(
+        &self,
+        environment_name: Option<&String>,
+        cluster_name: Option<&String>,
+    ) -> Result<(Cluster, ServerDefaults)> {
+        let deployment = self
+            .deployment
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No deployment loaded"))?;
+        let environment_name = environment_name.unwrap_or(&deployment.default_environment_name);
+        let environment = deployment
+            .environments
+            .iter()
+            .find(|environment| &environment.name == environment_name)
+            .with_context(|| format!("Environment <{environment_name}> not found"))?;
+        let cluster_name = cluster_name.unwrap_or(&environment.default_cluster_name);
+        if cluster_name.is_empty() {
+            let defaults = environment.defaults.merge(&deployment.defaults);
+            return Ok((Cluster::new(), defaults));
+        }
+        let cluster = environment
+            .clusters
+            .iter()
+            .find(|cluster| &cluster.name == cluster_name)
+            .with_context(|| {
+                format!("Cluster <{cluster_name}> not found in environment <{environment_name}>")
+            })?;
+        let defaults = self.get_cluster_defaults(environment, cluster);
+        let mut cluster = Cluster::from(cluster);
+        cluster.name = String::new();
+        cluster.server_groups.clear();
+        Ok((cluster, defaults))
+    }
 
-                return Some((server_groups, citus_db_name));
+    /// Adds the cluster to the environment. A cluster with the same name keeps its settings and
+    /// its other server groups, servers of its groups with discovered names are replaced and
+    /// servers which are still found keep their settings, then the result is true.
+    /// The cluster becomes the default one of an environment without a default cluster
+    pub fn add_cluster(
+        &mut self,
+        environment_name: Option<&String>,
+        cluster: Cluster,
+    ) -> Result<bool> {
+        let deployment = self
+            .deployment
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("No deployment loaded"))?;
+        let environment_name = environment_name.unwrap_or(&deployment.default_environment_name);
+        let environment = deployment
+            .environments
+            .iter_mut()
+            .find(|environment| &environment.name == environment_name)
+            .with_context(|| format!("Environment <{environment_name}> not found"))?;
+        if environment.default_cluster_name.is_empty() {
+            environment.default_cluster_name = cluster.name.clone();
+        }
+        match environment
+            .clusters
+            .iter_mut()
+            .find(|other| other.name == cluster.name)
+        {
+            Some(other) => {
+                for server_group in cluster.server_groups {
+                    let Some(other_server_group) = other
+                        .server_groups
+                        .iter_mut()
+                        .find(|other_server_group| other_server_group.name == server_group.name)
+                    else {
+                        other.server_groups.push(server_group);
+                        continue;
+                    };
+                    let servers: Vec<Server> = server_group
+                        .servers
+                        .into_iter()
+                        .map(|server| {
+                            other_server_group
+                                .servers
+                                .iter()
+                                .find(|other_server| {
+                                    other_server.host == server.host
+                                        && other_server.port == server.port
+                                })
+                                .cloned()
+                                .unwrap_or(server)
+                        })
+                        .collect();
+                    other_server_group.servers = servers;
+                }
+                Ok(true)
+            }
+            None => {
+                environment.clusters.push(cluster);
+                Ok(false)
             }
         }
-        None
+    }
+
+    /// True when the current environment or cluster is protected
+    pub fn is_protected(&self) -> bool {
+        let Ok(environment) = self.get_current_environment() else {
+            return false;
+        };
+        let is_cluster_protected = self
+            .get_current_cluster(environment)
+            .map(|cluster| cluster.protected == Some(true))
+            .unwrap_or(false);
+        environment.protected == Some(true) || is_cluster_protected
     }
 }
 impl Drop for InventoryManager<'_> {
@@ -164,3 +429,134 @@ impl Drop for InventoryManager<'_> {
         // println!("Dropping InventoryManager!");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let content = "\
+name: organization
+default_environment_name: dev
+environments:
+  - name: dev
+    default_cluster_name: cloud
+    clusters:
+      - name: cloud
+        server_groups: []
+      - name: cloud2
+        server_groups: []
+  - name: prod
+    default_cluster_name: p1
+    clusters:
+      - name: p1
+        server_groups: []
+";
+        let inventory_file_name = "inventory.taco.yml".to_string();
+        let mut inventory_manager = InventoryManager::new(&inventory_file_name);
+        inventory_manager.deployment = Some(serde_yaml::from_str(content).unwrap());
+        let get_names = |inventory_manager: &InventoryManager| {
+            let (environment_name, cluster_name) =
+                inventory_manager.get_current_names().unwrap_or_default();
+            format!("{}/{}", environment_name, cluster_name)
+        };
+        assert_eq!(get_names(&inventory_manager), "dev/cloud");
+
+        inventory_manager
+            .select(None, Some("cloud2".to_string()))
+            .unwrap();
+        assert_eq!(get_names(&inventory_manager), "dev/cloud2");
+
+        // unknown names keep the previous selection
+        let error = inventory_manager
+            .select(Some("qa".to_string()), None)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Environment <qa> not found");
+        assert_eq!(get_names(&inventory_manager), "dev/cloud2");
+        let error = inventory_manager
+            .select(Some("prod".to_string()), Some("cloud".to_string()))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cluster <cloud> not found in environment <prod>"
+        );
+        assert_eq!(get_names(&inventory_manager), "dev/cloud2");
+        assert_eq!(
+            inventory_manager.get_selection(),
+            (None, Some("cloud2".to_string()))
+        );
+
+        inventory_manager
+            .select(Some("prod".to_string()), None)
+            .unwrap();
+        assert_eq!(get_names(&inventory_manager), "prod/p1");
+    }
+
+    #[test]
+    fn test_add_cluster() {
+        let content = "\
+name: organization
+default_environment_name: dev
+environments:
+  - name: dev
+    default_cluster_name: cloud
+    clusters:
+      - name: cloud
+        server_groups:
+          - name: coordinators
+            servers:
+              - host: db1
+                sslmode: require
+              - host: db2
+          - name: reports
+            servers:
+              - host: db9
+";
+        let discovered_content = "\
+name: cloud
+server_groups:
+  - name: coordinators
+    servers:
+      - host: db1
+      - host: db3
+  - name: workers
+    servers:
+      - host: db4
+";
+        let inventory_file_name = "inventory.taco.yml".to_string();
+        let mut inventory_manager = InventoryManager::new(&inventory_file_name);
+        inventory_manager.deployment = Some(serde_yaml::from_str(content).unwrap());
+        let discovered_cluster: Cluster = serde_yaml::from_str(discovered_content).unwrap();
+        assert!(
+            inventory_manager
+                .add_cluster(None, discovered_cluster)
+                .unwrap()
+        );
+
+        let deployment = inventory_manager.deployment.as_ref().unwrap();
+        let server_groups: Vec<String> = deployment.environments[0].clusters[0]
+            .server_groups
+            .iter()
+            .map(|server_group| {
+                let servers: Vec<String> = server_group
+                    .servers
+                    .iter()
+                    .map(|server| match &server.sslmode {
+                        Some(sslmode) => format!("{} {}", server.host, sslmode),
+                        None => server.host.clone(),
+                    })
+                    .collect();
+                format!("{}: {}", server_group.name, servers.join(", "))
+            })
+            .collect();
+        assert_eq!(
+            server_groups,
+            vec![
+                "coordinators: db1 require, db3",
+                "reports: db9",
+                "workers: db4"
+            ]
+        );
+    }
+}
@@ -1,6 +1,12 @@
 pub mod cluster;
+pub mod cluster_discoverer;
 mod deployment;
+pub mod dynamic_group;
 mod environment;
+pub mod inventory_checker;
+pub mod inventory_loader;
 pub(crate) mod inventory_manager;
+pub mod password_resolver;
 pub mod server;
+pub mod server_defaults;
 mod server_group;
@@ -0,0 +1,167 @@
+use crate::inventory::server::Server;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Command;
+use std::{env, fs};
+
+/// Fills passwords of servers from password_env, password_command or a passfile.
+/// A password set in the inventory is used as is, every password command runs only once
+#[derive(Default)]
+pub struct PasswordResolver {
+    command_passwords: HashMap<String, String>,
+}
+
+impl PasswordResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn resolve_server_groups(
+        &mut self,
+        server_groups: &mut HashMap<String, Vec<Server>>,
+    ) -> anyhow::Result<()> {
+        for server in server_groups.values_mut().flatten() {
+            self.resolve_server(server)?;
+        }
+        Ok(())
+    }
+
+    pub fn resolve_server(&mut self, server: &mut Server) -> anyhow::Result<()> {
+        if server.password.is_none() {
+            server.password = self.get_password(server)?;
+        }
+        Ok(())
+    }
+
+    fn get_password(&mut self, server: &Server) -> anyhow::Result<Option<String>> {
+        if let Some(password_env) = &server.password_env {
+            return match env::var(password_env) {
+                Ok(password) => Ok(Some(password)),
+                Err(_) => Err(anyhow::anyhow!(
+                    "PASSWORD ENVIRONMENT VARIABLE {} OF SERVER {} IS NOT SET",
+                    password_env,
+                    server.host
+                )),
+            };
+        }
+        if let Some(password_command) = &server.password_command {
+            if !self.command_passwords.contains_key(password_command) {
+                let password = run_password_command(password_command, server)?;
+                self.command_passwords
+                    .insert(password_command.clone(), password);
+            }
+            return Ok(self.command_passwords.get(password_command).cloned());
+        }
+        let passfile = match &server.passfile {
+            Some(passfile) => PathBuf::from(passfile),
+            None => match get_default_passfile() {
+                Some(passfile) if passfile.exists() => passfile,
+                _ => return Ok(None),
+            },
+        };
+        let content = fs::read_to_string(&passfile).map_err(|e| {
+            anyhow::anyhow!("CAN NOT READ PASSFILE {}: {}", passfile.display(), e)
+        })?;
+        Ok(find_passfile_password(&content, server))
+    }
+}
+
+fn get_default_passfile() -> Option<PathBuf> {
+    if let Ok(passfile) = env::var("PGPASSFILE") {
+        return Some(PathBuf::from(passfile));
+    }
+    env::var("HOME")
+        .ok()
+        .map(|home| PathBuf::from(home).join(".pgpass"))
+}
+
+/// The command is not part of the error, it can contain secrets
+fn run_password_command(password_command: &str, server: &Server) -> anyhow::Result<String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(password_command)
+        .output()
+        .map_err(|e| {
+            anyhow::anyhow!("CAN NOT RUN PASSWORD COMMAND OF SERVER {}: {}", server.host, e)
+        })?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "PASSWORD COMMAND OF SERVER {} FAILED WITH {} {}",
+            server.host,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Lines are "hostname:port:database:username:password" like in ~/.pgpass,
+/// "*" matches any value, ":" and "\" are escaped with "\", the first matching line wins
+fn find_passfile_password(content: &str, server: &Server) -> Option<String> {
+    let port = server.port.unwrap_or(5432).to_string();
+    let values = [
+        server.host.as_str(),
+        port.as_str(),
+        server.db_name.as_deref().unwrap_or_default(),
+        server.user.as_deref().unwrap_or_default(),
+    ];
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .map(split_passfile_line)
+        .find(|fields| {
+            fields.len() == 5
+                && fields
+                    .iter()
+                    .zip(values)
+                    .all(|(field, value)| field == "*" || field == value)
+        })
+        .and_then(|mut fields| fields.pop())
+}
+
+/// The password is the rest of the line, so it can have unescaped colons
+fn split_passfile_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => fields.last_mut().unwrap().extend(chars.next()),
+            ':' if fields.len() < 5 => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::server_defaults::ServerDefaults;
+
+    #[test]
+    fn test_find_passfile_password() {
+        let defaults = ServerDefaults {
+            default_port: Some(5433),
+            default_db_name: Some("postgres".to_string()),
+            default_user: Some("taco".to_string()),
+            ..Default::default()
+        };
+        let server: Server = serde_yaml::from_str("host: db1").unwrap();
+        let server = Server::from(&server, &defaults);
+        let content = "# comment\n\
+            db2:*:*:*:wrong\n\
+            db1:5432:*:taco:wrong_port\n\
+            db1:5433:*:taco:pa\\:ss:word\n\
+            *:*:*:*:fallback";
+        assert_eq!(
+            find_passfile_password(content, &server).as_deref(),
+            Some("pa:ss:word")
+        );
+        assert_eq!(
+            find_passfile_password("*:*:*:*:fallback", &server).as_deref(),
+            Some("fallback")
+        );
+        assert_eq!(find_passfile_password("db2:*:*:*:wrong", &server), None);
+    }
+}
@@ -1,17 +1,51 @@
+use crate::inventory::server_defaults::ServerDefaults;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
 // https://docs.rs/postgres/latest/postgres/config/struct.Config.html#
-#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Debug)]
+#[derive(Serialize, Deserialize, Eq, PartialEq, Hash, Clone, Default)]
 pub struct Server {
     pub host: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub port: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub db_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub password: Option<String>,
+    /// Environment variable with the password
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+    /// Shell command which prints the password, like a secret manager CLI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password_command: Option<String>,
+    /// Password file in the ~/.pgpass format, ~/.pgpass or PGPASSFILE by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passfile: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub connect_timeout_sec: Option<i32>,
+    /// disable, prefer, require, verify-ca or verify-full, prefer by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sslmode: Option<String>,
+    /// Root certificates for verify-ca and verify-full
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sslrootcert: Option<String>,
+    /// Client certificate and key for certificate authentication
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sslcert: Option<String>,
+    /// PKCS#8 key ("BEGIN PRIVATE KEY"), traditional "BEGIN RSA PRIVATE KEY" keys are not read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sslkey: Option<String>,
 
     //region Runtime Information
+    /// "<environment>/<cluster>" of servers of targets across clusters
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub cluster_name: Option<String>,
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub citus_db_name: Option<String>,
     #[serde(skip_serializing)]
     #[serde(skip_deserializing)]
     pub is_node_online: Option<bool>,
@@ -69,26 +103,45 @@ pub struct Server {
 }
 
 impl Server {
-    pub fn from(
-        from: &Server,
-        defaults: (
-            &Option<i32>,
-            &Option<String>,
-            &Option<String>,
-            &Option<String>,
-            &Option<i32>,
-        ),
-    ) -> Self {
-        let (port, db_name, user, password, connect_timeout_sec) = defaults;
+    /// Fills settings which are not set on the server with the defaults of its server group
+    pub fn from(from: &Server, defaults: &ServerDefaults) -> Self {
+        // a password source of the server replaces all password sources of the cluster
+        let (password, password_env, password_command, passfile) = if from.has_password_source() {
+            (
+                from.password.clone(),
+                from.password_env.clone(),
+                from.password_command.clone(),
+                from.passfile.clone(),
+            )
+        } else {
+            (
+                defaults.default_password.clone(),
+                defaults.default_password_env.clone(),
+                defaults.default_password_command.clone(),
+                defaults.default_passfile.clone(),
+            )
+        };
         Self {
             host: from.host.clone(),
-            port: from.port.or_else(|| port.clone()),
-            db_name: from.db_name.clone().or_else(|| db_name.clone()),
-            user: from.user.clone().or_else(|| user.clone()),
-            password: from.password.clone().or_else(|| password.clone()),
+            port: from.port.or(defaults.default_port),
+            db_name: from.db_name.clone().or_else(|| defaults.default_db_name.clone()),
+            user: from.user.clone().or_else(|| defaults.default_user.clone()),
+            password,
+            password_env,
+            password_command,
+            passfile,
             connect_timeout_sec: from
                 .connect_timeout_sec
-                .or_else(|| connect_timeout_sec.clone()),
+                .or(defaults.default_connect_timeout_sec),
+            sslmode: from.sslmode.clone().or_else(|| defaults.default_sslmode.clone()),
+            sslrootcert: from
+                .sslrootcert
+                .clone()
+                .or_else(|| defaults.default_sslrootcert.clone()),
+            sslcert: from.sslcert.clone().or_else(|| defaults.default_sslcert.clone()),
+            sslkey: from.sslkey.clone().or_else(|| defaults.default_sslkey.clone()),
+            cluster_name: None,
+            citus_db_name: from.citus_db_name.clone(),
             is_node_online: None,
             is_node_consistent: None,
             postgres_is_leader: None,
@@ -109,19 +162,61 @@ impl Server {
     pub fn set_db_name(&mut self, db_name: String) {
         self.db_name = Some(db_name);
     }
-}
 
-impl fmt::Display for Server {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "host={} port={} dbname={} user={} password={} connect_timeout={} application_name=taco",
+    /// Host with "<environment>/<cluster>/" in front for servers of targets across clusters
+    pub fn get_display_host(&self) -> String {
+        match &self.cluster_name {
+            Some(cluster_name) => format!("{}/{}", cluster_name, self.host),
+            None => self.host.clone(),
+        }
+    }
+
+    fn has_password_source(&self) -> bool {
+        self.password.is_some()
+            || self.password_env.is_some()
+            || self.password_command.is_some()
+            || self.passfile.is_some()
+    }
+
+    /// Connection string with the password, never print it
+    pub fn get_connection_string(&self) -> String {
+        self.format_connection_string(self.password.as_deref().unwrap_or("default_password"))
+    }
+
+    fn format_connection_string(&self, password: &str) -> String {
+        format!(
+            "host={} port={} dbname={} user={} password='{}' connect_timeout={} sslmode={} application_name=taco",
             self.host,
             self.port.unwrap_or_default(),
             self.db_name.as_deref().unwrap_or("default_dbname"),
             self.user.as_deref().unwrap_or("default_user"),
-            self.password.as_deref().unwrap_or("default_password"),
-            self.connect_timeout_sec.unwrap_or_default()
+            // quoted, passwords of secret managers can have spaces and quotes
+            password.replace('\\', "\\\\").replace('\'', "\\'"),
+            self.connect_timeout_sec.unwrap_or_default(),
+            self.get_connection_sslmode()
         )
     }
+
+    /// tokio-postgres only knows disable, prefer and require,
+    /// certificates of verify-ca and verify-full are checked by the TLS connector
+    fn get_connection_sslmode(&self) -> &str {
+        match self.sslmode.as_deref() {
+            Some("verify-ca" | "verify-full") => "require",
+            Some(sslmode) => sslmode,
+            None => "prefer",
+        }
+    }
+}
+
+/// Connection string with a masked password
+impl fmt::Display for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format_connection_string("********"))
+    }
+}
+
+impl fmt::Debug for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Server({})", self)
+    }
 }
@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+
+/// Settings of servers which do not set them. The deployment, environments, clusters
+/// and server groups can have them, the nearest level wins
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct ServerDefaults {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_port: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_db_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_password_env: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_password_command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_passfile: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_connect_timeout_sec: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_sslmode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_sslrootcert: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_sslcert: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_sslkey: Option<String>,
+}
+
+impl ServerDefaults {
+    /// These defaults with the missing ones taken from the parent level.
+    /// A password source replaces all password sources of the parent
+    pub fn merge(&self, parent: &ServerDefaults) -> Self {
+        let password_defaults = if self.has_password_source() {
+            self
+        } else {
+            parent
+        };
+        Self {
+            default_port: self.default_port.or(parent.default_port),
+            default_db_name: self
+                .default_db_name
+                .clone()
+                .or_else(|| parent.default_db_name.clone()),
+            default_user: self
+                .default_user
+                .clone()
+                .or_else(|| parent.default_user.clone()),
+            default_password: password_defaults.default_password.clone(),
+            default_password_env: password_defaults.default_password_env.clone(),
+            default_password_command: password_defaults.default_password_command.clone(),
+            default_passfile: password_defaults.default_passfile.clone(),
+            default_connect_timeout_sec: self
+                .default_connect_timeout_sec
+                .or(parent.default_connect_timeout_sec),
+            default_sslmode: self
+                .default_sslmode
+                .clone()
+                .or_else(|| parent.default_sslmode.clone()),
+            default_sslrootcert: self
+                .default_sslrootcert
+                .clone()
+                .or_else(|| parent.default_sslrootcert.clone()),
+            default_sslcert: self
+                .default_sslcert
+                .clone()
+                .or_else(|| parent.default_sslcert.clone()),
+            default_sslkey: self
+                .default_sslkey
+                .clone()
+                .or_else(|| parent.default_sslkey.clone()),
+        }
+    }
+
+    fn has_password_source(&self) -> bool {
+        self.default_password.is_some()
+            || self.default_password_env.is_some()
+            || self.default_password_command.is_some()
+            || self.default_passfile.is_some()
+    }
+}
@@ -1,8 +1,11 @@
 use crate::inventory::server::Server;
+use crate::inventory::server_defaults::ServerDefaults;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct ServerGroup {
     pub name: String,
+    #[serde(flatten)]
+    pub defaults: ServerDefaults,
     pub servers: Vec<Server>,
 }
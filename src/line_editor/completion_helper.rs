@@ -0,0 +1,131 @@
+use crate::input_parser::input_parser::find_request_separator;
+use crate::output_formatter::output_formatter::OutputFormat;
+use crate::shared::request_type::RequestType;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+const BUILT_IN_COMMANDS: [&str; 18] = [
+    "help", "exit", "history", "use", "env", "cluster", "resolve", "show", "\\i", "\\format",
+    "\\out-dir", "\\merge", "\\compare", "\\atomic", "\\rolling", "\\parallel", "\\timeout",
+    "\\reset",
+];
+const SHOW_COMMANDS: [&str; 2] = ["datatypes", "macro"];
+const BOOLEAN_VALUES: [&str; 2] = ["true", "false"];
+const ON_OFF_VALUES: [&str; 2] = ["on", "off"];
+const MERGE_COMMANDS: [&str; 3] = ["on", "off", "order"];
+const WORD_BREAK_CHARS: &str = ",&|-()";
+
+/// Completes server groups, macros, databases, environments, clusters
+/// and built-in commands in the prompt
+pub struct CompletionHelper {
+    pub server_group_names: Vec<String>,
+    pub macro_names: Vec<String>,
+    pub database_names: Vec<String>,
+    pub environment_names: Vec<String>,
+    pub cluster_names: Vec<String>,
+    filename_completer: FilenameCompleter,
+}
+
+impl CompletionHelper {
+    pub fn new() -> Self {
+        Self {
+            server_group_names: Vec::new(),
+            macro_names: Vec::new(),
+            database_names: Vec::new(),
+            environment_names: Vec::new(),
+            cluster_names: Vec::new(),
+            filename_completer: FilenameCompleter::new(),
+        }
+    }
+
+    fn get_candidates(&self, line_before_word: &str) -> Vec<String> {
+        let preprocessed_line = line_before_word.trim().to_lowercase();
+        let words: Vec<&str> = preprocessed_line.split_whitespace().collect();
+
+        if let Some((separator_index, request_type)) = find_request_separator(&preprocessed_line)
+        {
+            let after_separator = &preprocessed_line[separator_index + 1..];
+            if request_type == RequestType::Macro && after_separator.trim().is_empty() {
+                return self.macro_names.clone();
+            }
+            // queries and commands are not completed
+            return Vec::new();
+        }
+
+        match words.as_slice() {
+            [] => self
+                .server_group_names
+                .iter()
+                .cloned()
+                .chain(BUILT_IN_COMMANDS.iter().map(|c| c.to_string()))
+                .collect(),
+            ["use"] => self.database_names.clone(),
+            ["env"] => self.environment_names.clone(),
+            ["cluster"] => self.cluster_names.clone(),
+            ["show"] => SHOW_COMMANDS.iter().map(|c| c.to_string()).collect(),
+            ["show", "datatypes"] => BOOLEAN_VALUES.iter().map(|c| c.to_string()).collect(),
+            ["\\format"] => OutputFormat::NAMES.iter().map(|c| c.to_string()).collect(),
+            ["\\compare"] | ["\\atomic"] => ON_OFF_VALUES.iter().map(|c| c.to_string()).collect(),
+            ["\\merge"] => MERGE_COMMANDS.iter().map(|c| c.to_string()).collect(),
+            ["\\merge", "order"] => vec!["by".to_string()],
+            ["\\rolling"] | ["\\parallel"] | ["\\timeout"] => vec!["off".to_string()],
+            ["resolve", ..] => self.server_group_names.clone(),
+            [first, ..] if BUILT_IN_COMMANDS.contains(first) => Vec::new(),
+            // still typing the server group
+            _ => self.server_group_names.clone(),
+        }
+    }
+}
+
+impl Completer for CompletionHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line_before_cursor = &line[..pos];
+        let trimmed_line = line_before_cursor.trim_start();
+        if trimmed_line.starts_with("\\i ") || trimmed_line.starts_with("\\out-dir ") {
+            return self.filename_completer.complete(line, pos, ctx);
+        }
+
+        let word_start = line_before_cursor
+            .rfind(|c: char| c.is_whitespace() || WORD_BREAK_CHARS.contains(c))
+            .map(|index| index + 1)
+            .unwrap_or(0);
+        let word = line_before_cursor[word_start..].to_lowercase();
+
+        let mut candidates: Vec<String> = self
+            .get_candidates(&line_before_cursor[..word_start])
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(&word))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+
+        let pairs = candidates
+            .into_iter()
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        Ok((word_start, pairs))
+    }
+}
+
+impl Hinter for CompletionHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CompletionHelper {}
+
+impl Validator for CompletionHelper {}
+
+impl Helper for CompletionHelper {}
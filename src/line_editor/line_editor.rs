@@ -0,0 +1,144 @@
+use crate::line_editor::completion_helper::CompletionHelper;
+use anyhow::{Context, Result};
+use rustyline::config::{CompletionType, Config};
+use rustyline::error::ReadlineError;
+use rustyline::history::{FileHistory, History};
+use rustyline::Editor;
+use std::path::{Path, PathBuf};
+
+pub enum LineEditorInput {
+    Line(String),
+    Interrupted,
+    Eof,
+}
+
+pub struct LineEditor {
+    editor: Editor<CompletionHelper, FileHistory>,
+    history_file_name: Option<PathBuf>,
+}
+
+impl LineEditor {
+    pub fn new(inventory_file_name: &str) -> Result<Self> {
+        let config = Config::builder()
+            .max_history_size(1000)?
+            .history_ignore_dups(true)?
+            .history_ignore_space(true)
+            .auto_add_history(false)
+            .completion_type(CompletionType::List)
+            .build();
+        let mut editor: Editor<CompletionHelper, FileHistory> =
+            Editor::with_config(config).context("Failed to initialize line editor")?;
+        editor.set_helper(Some(CompletionHelper::new()));
+
+        let history_file_name = Self::get_history_file_name(inventory_file_name);
+        if let Some(history_file_name) = &history_file_name {
+            if let Some(history_dir) = history_file_name.parent() {
+                let _ = std::fs::create_dir_all(history_dir);
+            }
+            if history_file_name.exists() {
+                let _ = editor.load_history(history_file_name);
+            }
+        }
+
+        Ok(Self {
+            editor,
+            history_file_name,
+        })
+    }
+
+    /// Reads one line from the terminal
+    pub fn read_line(&mut self, prompt: &str) -> LineEditorInput {
+        match self.editor.readline(prompt) {
+            Ok(line) => LineEditorInput::Line(line),
+            Err(ReadlineError::Interrupted) => LineEditorInput::Interrupted,
+            Err(_) => LineEditorInput::Eof,
+        }
+    }
+
+    pub fn add_history(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        if let Ok(true) = self.editor.add_history_entry(line)
+            && let Some(history_file_name) = &self.history_file_name
+        {
+            let _ = self.editor.append_history(history_file_name);
+        }
+    }
+
+    pub fn set_server_group_names(&mut self, server_group_names: Vec<String>) {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.server_group_names = server_group_names;
+        }
+    }
+
+    pub fn set_macro_names(&mut self, macro_names: Vec<String>) {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.macro_names = macro_names;
+        }
+    }
+
+    pub fn set_database_names(&mut self, database_names: Vec<String>) {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.database_names = database_names;
+        }
+    }
+
+    pub fn set_environment_names(&mut self, environment_names: Vec<String>) {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.environment_names = environment_names;
+        }
+    }
+
+    pub fn set_cluster_names(&mut self, cluster_names: Vec<String>) {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.cluster_names = cluster_names;
+        }
+    }
+
+    pub fn get_history(&self) -> Vec<String> {
+        self.editor.history().iter().cloned().collect()
+    }
+
+    pub fn get_history_entry(&self, index: usize) -> Option<String> {
+        if index >= self.editor.history().len() {
+            return None;
+        }
+        self.editor.history().iter().nth(index).cloned()
+    }
+
+    /// History is kept per inventory file in $XDG_DATA_HOME/taco/history
+    /// (or ~/.local/share/taco/history when XDG_DATA_HOME is not set)
+    fn get_history_file_name(inventory_file_name: &str) -> Option<PathBuf> {
+        let data_dir = match std::env::var_os("XDG_DATA_HOME") {
+            Some(data_home) if !data_home.is_empty() => PathBuf::from(data_home),
+            _ => PathBuf::from(std::env::var_os("HOME")?)
+                .join(".local")
+                .join("share"),
+        };
+
+        let inventory_path = Path::new(inventory_file_name);
+        let canonical_path = inventory_path
+            .canonicalize()
+            .unwrap_or_else(|_| inventory_path.to_path_buf());
+        // FNV-1a keeps the file name stable between builds, unlike DefaultHasher
+        let inventory_hash = canonical_path
+            .to_string_lossy()
+            .bytes()
+            .fold(0xcbf29ce484222325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            });
+        let inventory_name = inventory_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "inventory".to_string());
+
+        Some(
+            data_dir
+                .join("taco")
+                .join("history")
+                .join(format!("{}-{:016x}", inventory_name, inventory_hash)),
+        )
+    }
+}
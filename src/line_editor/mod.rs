@@ -0,0 +1,2 @@
+mod completion_helper;
+pub mod line_editor;
\ No newline at end of file
@@ -5,45 +5,75 @@ mod cluster_consistency_checker;
 mod facts_collector;
 mod input_parser;
 mod inventory;
+mod line_editor;
 mod macro_provider;
+mod output_formatter;
 mod server_provider;
+mod session_manager;
 mod settings_provider;
 mod shared;
+mod tls_connector;
+mod value_renderer;
 
-use crate::clap_parser::Args;
+use crate::clap_parser::{Args, Commands, InventoryCommands};
 use crate::cluster_consistency_checker::cluster_consistency_checker::ClusterConsistencyChecker;
 use crate::facts_collector::facts_collector::FactsCollector;
+use crate::input_parser::input_parser::{
+    OutputRedirect, ParsedRequest, is_request_complete, normalize_server_group, parse_request,
+    split_output_redirect, split_script,
+};
+use crate::input_parser::statement_classifier::get_destructive_statements;
+use crate::inventory::cluster_discoverer::ClusterDiscoverer;
 use crate::inventory::inventory_manager::{InventoryManager, Server};
+use crate::inventory::password_resolver::PasswordResolver;
+use crate::line_editor::line_editor::{LineEditor, LineEditorInput};
 use crate::macro_provider::macro_provider::MacroProvider;
+use crate::output_formatter::output_formatter::OutputFormat;
+use crate::output_formatter::output_writer::{OutputTarget, OutputWriter};
+use crate::output_formatter::result_comparer::{ResultComparer, ResultGroup};
+use crate::output_formatter::result_merger::{
+    OrderByColumn, ResultMerger, parse_order_by, sort_rows,
+};
 use crate::server_provider::server_provider::ServerProvider;
+use crate::session_manager::session_manager::SessionManager;
 use crate::settings_provider::settings_provider::SettingsProvider;
+use crate::shared::printer_message::PrinterMessage;
+use crate::shared::query_result::{QueryResult, QueryResultColumn};
+use crate::shared::request_summary::RequestSummary;
 use crate::shared::request_type::RequestType;
+use crate::shared::server_outcome::ServerOutcome;
+use crate::tls_connector::tls_connector::TlsConnector;
+use crate::value_renderer::value_renderer::{is_rendered_from_binary, render_value};
 use crate::version::{
     COPYRIGHT, COPYRIGHT_YEARS, LICENSE, LINK, PRODUCT_NAME, VERSION_ALIAS, VERSION_MAJOR,
     VERSION_MINOR, VERSION_PATCH,
 };
-use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use anyhow::Context;
 use clap::Parser;
 use colored::Colorize;
 use prettytable::{Cell, Row, Table};
-use rust_decimal::Decimal;
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fs;
 use std::io::{self, Write};
-use std::net::IpAddr;
+use std::path::PathBuf;
 use std::process;
 use std::sync::LazyLock;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::watch;
 use tokio::task::JoinSet;
-use tokio_postgres::types::{FromSql, Oid, Type};
-use tokio_postgres::{Error, NoTls};
+use tokio_postgres::{CancelToken, Client, Error, SimpleQueryMessage, Statement};
 use uuid::Uuid;
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
+    tokio::spawn(exit_on_unhandled_ctrl_c());
     print_separator();
     print_banner();
     print_separator();
@@ -66,50 +96,283 @@ async fn main() {
     println!("Loading Inventory File: <{}> ", inventory_file_name);
     let mut inventory_manager = InventoryManager::new(inventory_file_name);
     let file_load_result = inventory_manager.load_inventory_from_file().await;
-    if file_load_result.is_err() {
-        eprintln!("{}", file_load_result.err().unwrap().to_string().red());
+    if let Err(e) = file_load_result {
+        // the source has the line of the deserialization error
+        eprintln!("{}", format!("{:#}", e).red());
         process::exit(1);
     }
-    let static_server_groups = inventory_manager.get_static_server_groups();
-    if static_server_groups.is_none() {
-        eprintln!("{}", "Static server groups not defined".red());
+    let collect_citus_facts = {
+        // this block for mutex release
+        let settings_lock = settings.lock().unwrap();
+        settings_lock.get("collect_citus_facts") == Some(&"true".to_string())
+    };
+    let inventory_problems = inventory_manager.check_inventory(collect_citus_facts);
+    if let Some(Commands::Inventory {
+        command: InventoryCommands::Check,
+    }) = &args.subcommand
+    {
+        if inventory_problems.is_empty() {
+            println!("{}", "NO PROBLEMS FOUND IN THE INVENTORY".green());
+            process::exit(0);
+        }
+        for inventory_problem in &inventory_problems {
+            println!("{}", inventory_problem.to_string().red());
+        }
+        println!(
+            "{}",
+            format!("PROBLEMS FOUND IN THE INVENTORY: {}", inventory_problems.len()).red()
+        );
         process::exit(1);
     }
-    drop(inventory_manager);
-    let (server_groups, citus_db_name) = static_server_groups.unwrap();
+    if let Some(Commands::Inventory {
+        command:
+            InventoryCommands::Discover {
+                seed,
+                name,
+                citus_db,
+                output,
+            },
+    }) = &args.subcommand
+    {
+        // comments and the layout of the inventory file would be lost
+        let is_inventory_file = fs::canonicalize(output).is_ok_and(|output_path| {
+            fs::canonicalize(inventory_file_name).is_ok_and(|path| path == output_path)
+        });
+        if is_inventory_file {
+            eprintln!(
+                "{}",
+                format!(
+                    "OUTPUT FILE <{}> IS THE INVENTORY FILE, USE ANOTHER FILE AND REVIEW THE CHANGES",
+                    output
+                )
+                .red()
+            );
+            process::exit(1);
+        }
+        let discover_result = discover_cluster(
+            &mut inventory_manager,
+            args.environment.as_ref(),
+            args.cluster.as_ref(),
+            seed,
+            name,
+            citus_db,
+            output,
+        )
+        .await;
+        if let Err(e) = discover_result {
+            eprintln!("{}", e.to_string().red());
+            process::exit(1);
+        }
+        process::exit(0);
+    }
+    for inventory_problem in &inventory_problems {
+        println!("{}", format!("INVENTORY WARNING: {}", inventory_problem).yellow());
+    }
+    if args.environment.is_some() || args.cluster.is_some() {
+        let select_result =
+            inventory_manager.select(args.environment.clone(), args.cluster.clone());
+        if select_result.is_err() {
+            eprintln!("{}", select_result.err().unwrap().to_string().red());
+            process::exit(1);
+        }
+    }
     println!("{}", "DONE Loading Inventory File".green());
     print_separator();
 
-    println!("Collecting Facts");
-    let mut server_provider = ServerProvider::new(server_groups).await;
-    let mut servers_to_check = server_provider.get_servers_in_group("all").unwrap();
-    let facts_collector = FactsCollector::new(&settings);
-    facts_collector
-        .collect_facts(&mut servers_to_check, citus_db_name)
+    let session_manager = Arc::new(SessionManager::new());
+    let mut server_provider =
+        match load_server_provider(&inventory_manager, &settings, &session_manager).await {
+            Ok(server_provider) => server_provider,
+            Err(e) => {
+                eprintln!("{}", e.to_string().red());
+                process::exit(1);
+            }
+        };
+
+    let macro_provider = MacroProvider::new();
+
+    if let Some(output_format) = &args.format {
+        // this block for mutex release
+        let mut settings_lock = settings.lock().unwrap();
+        settings_lock.insert("output_format".to_string(), output_format.to_string());
+    }
+    if let Some(out_dir) = &args.out_dir {
+        // this block for mutex release
+        let mut settings_lock = settings.lock().unwrap();
+        settings_lock.insert("out_dir".to_string(), out_dir.clone());
+    }
+    if args.atomic {
+        // this block for mutex release
+        let mut settings_lock = settings.lock().unwrap();
+        settings_lock.insert("atomic_commands".to_string(), "true".to_string());
+    }
+    if let Some(timeout) = args.timeout {
+        // this block for mutex release
+        let mut settings_lock = settings.lock().unwrap();
+        settings_lock.insert("request_timeout_sec".to_string(), timeout.to_string());
+    }
+    if let Some(parallel) = args.parallel {
+        // this block for mutex release
+        let mut settings_lock = settings.lock().unwrap();
+        settings_lock.insert("parallel".to_string(), parallel.to_string());
+    }
+    if let Some(rolling_batch_size) = args.rolling {
+        // this block for mutex release
+        let mut settings_lock = settings.lock().unwrap();
+        settings_lock.insert("rolling_batch_size".to_string(), rolling_batch_size.to_string());
+    }
+    if args.yes {
+        // this block for mutex release
+        let mut settings_lock = settings.lock().unwrap();
+        settings_lock.insert("assume_yes".to_string(), "true".to_string());
+    }
+    if args.compare {
+        // this block for mutex release
+        let mut settings_lock = settings.lock().unwrap();
+        settings_lock.insert("compare_results".to_string(), "true".to_string());
+    }
+    if args.merge || args.order_by.is_some() {
+        // this block for mutex release
+        let mut settings_lock = settings.lock().unwrap();
+        settings_lock.insert("merge_results".to_string(), "true".to_string());
+        if let Some(order_by) = &args.order_by {
+            settings_lock.insert("merge_order_by".to_string(), order_by.clone());
+        }
+    }
+
+    if let Some(Commands::Run {
+        file,
+        stop_on_error,
+        continue_on_error,
+    }) = &args.subcommand
+    {
+        if let Some(db_name) = &args.db {
+            // this block for mutex release
+            let mut settings_lock = settings.lock().unwrap();
+            settings_lock.insert("current_db".to_string(), db_name.clone());
+        }
+        let macro_values = match get_macro_values_from_args(&args.params) {
+            Ok(macro_values) => macro_values,
+            Err(e) => {
+                eprintln!("{}", e.to_string().red());
+                process::exit(2);
+            }
+        };
+        // clap rejects both flags, stopping is the default
+        let script_result = run_script(
+            file,
+            *stop_on_error || !continue_on_error,
+            &server_provider,
+            &macro_provider,
+            &settings,
+            &session_manager,
+            Some(macro_values),
+        )
         .await;
-    drop(facts_collector);
-    println!("{}", "DONE Collecting Facts".green());
-    print_separator();
+        match script_result {
+            Ok(0) => process::exit(0),
+            Ok(_) => process::exit(1),
+            Err(e) => {
+                eprintln!("{}", e.to_string().red());
+                process::exit(1);
+            }
+        }
+    }
 
-    println!("Checking Cluster Consistency");
-    let mut consistency_checker = ClusterConsistencyChecker::new(&settings);
-    if consistency_checker.check_cluster_consistency(&mut servers_to_check) {
-        println!("{}", "CLUSTER IS CONSISTENT".green());
-    } else {
-        println!("{}", "CLUSTER IS NOT CONSISTENT".red());
+    if let Some(Commands::Resolve { expression }) = &args.subcommand {
+        let expression = normalize_server_group(expression);
+        if !resolve_server_group(&expression, &server_provider, &settings, &session_manager).await {
+            process::exit(1);
+        }
+        process::exit(0);
     }
-    drop(consistency_checker);
-    server_provider.update_server_groups(servers_to_check);
-    println!("{}", "DONE Checking Cluster Consistency".green());
-    print_separator();
 
-    let servers = server_provider.get_servers_in_group("all").unwrap();
-    println!("Found {} servers", servers.len());
-    render_severs_table(servers);
-    print_separator();
+    if let Some(raw_server_group) = &args.group {
+        let request = if let Some(query) = &args.query {
+            Some((RequestType::Query, query.clone()))
+        } else if let Some(command) = &args.command {
+            Some((RequestType::Command, command.clone()))
+        } else {
+            args.macro_name
+                .as_ref()
+                .map(|macro_name| (RequestType::Macro, macro_name.to_lowercase()))
+        };
+        let Some((request_type, raw_command)) = request else {
+            eprintln!("{}", "ONE-SHOT MODE REQUIRES --query, --command OR --macro".red());
+            process::exit(2);
+        };
+        if let Some(db_name) = &args.db {
+            // this block for mutex release
+            let mut settings_lock = settings.lock().unwrap();
+            settings_lock.insert("current_db".to_string(), db_name.clone());
+        }
+        let macro_values = match get_macro_values_from_args(&args.params) {
+            Ok(macro_values) => macro_values,
+            Err(e) => {
+                eprintln!("{}", e.to_string().red());
+                process::exit(2);
+            }
+        };
+        let request = ParsedRequest {
+            server_group: normalize_server_group(raw_server_group),
+            request_type,
+            body: raw_command,
+        };
+        let run_result = run_request(
+            request,
+            &server_provider,
+            &macro_provider,
+            &settings,
+            &session_manager,
+            Some(macro_values),
+        )
+        .await;
+        match run_result {
+            Ok(summary) if summary.failed_servers == 0 && summary.skipped_servers == 0 => {
+                process::exit(0)
+            }
+            Ok(summary) => {
+                if summary.failed_servers > 0 {
+                    eprintln!(
+                        "{}",
+                        format!("REQUEST FAILED ON {} SERVER(S)", summary.failed_servers).red()
+                    );
+                }
+                if summary.skipped_servers > 0 {
+                    eprintln!(
+                        "{}",
+                        format!("REQUEST SKIPPED {} SERVER(S)", summary.skipped_servers).red()
+                    );
+                }
+                process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("{}", e.to_string().red());
+                process::exit(1);
+            }
+        }
+    }
 
-    let mut history: Vec<String> = Vec::new();
-    let macro_provider = MacroProvider::new();
+    let mut line_editor = match LineEditor::new(inventory_file_name) {
+        Ok(line_editor) => line_editor,
+        Err(e) => {
+            eprintln!("{}", e.to_string().red());
+            process::exit(1);
+        }
+    };
+    line_editor.set_server_group_names(server_provider.get_server_group_names());
+    line_editor.set_macro_names(
+        macro_provider
+            .get_macro_names()
+            .into_iter()
+            .map(|macro_name| macro_name.0)
+            .collect(),
+    );
+    line_editor.set_database_names(
+        get_database_names(&server_provider, &settings, &session_manager).await,
+    );
+    line_editor.set_environment_names(inventory_manager.get_environment_names());
+    line_editor.set_cluster_names(inventory_manager.get_cluster_names());
     loop {
         let mut current_db: Option<String> = None;
         {
@@ -122,17 +385,71 @@ async fn main() {
                 _ => {}
             }
         }
-        let _ =
-            io::stdout().write(format!("[{}] > ", current_db.unwrap_or("".to_string())).as_ref());
-        let _ = io::stdout().flush();
-        let mut command = String::new();
-        io::stdin().read_line(&mut command).unwrap();
+        let (environment_name, cluster_name) = inventory_manager
+            .get_current_names()
+            .unwrap_or_default();
+        let prompt = format!(
+            "[{}/{}] [{}] > ",
+            environment_name,
+            cluster_name,
+            current_db.unwrap_or("".to_string())
+        );
+        let mut command = match line_editor.read_line(&prompt) {
+            LineEditorInput::Line(line) => line,
+            LineEditorInput::Interrupted => continue,
+            LineEditorInput::Eof => {
+                println!("{}", "BYE-BYE!".yellow());
+                process::exit(0);
+            }
+        };
+        if let Some(history_index) = get_history_reference(&command) {
+            match line_editor.get_history_entry(history_index) {
+                Some(history_entry) => {
+                    println!("{}", history_entry.yellow());
+                    command = history_entry;
+                }
+                None => {
+                    println!(
+                        "{}",
+                        format!("NO HISTORY ENTRY <{}>", history_index).red()
+                    );
+                    continue;
+                }
+            }
+        }
+        let mut is_cancelled = false;
+        while !is_request_complete(&command) {
+            match line_editor.read_line("-> ") {
+                LineEditorInput::Line(line) => {
+                    command.push('\n');
+                    command.push_str(&line);
+                }
+                LineEditorInput::Interrupted => {
+                    is_cancelled = true;
+                    break;
+                }
+                LineEditorInput::Eof => {
+                    println!("{}", "BYE-BYE!".yellow());
+                    process::exit(0);
+                }
+            }
+        }
+        if is_cancelled {
+            println!("{}", "STATEMENT CANCELLED".yellow());
+            continue;
+        }
+        line_editor.add_history(&command);
         let preprocessed_command = command.to_lowercase().trim().to_string();
         if preprocessed_command.cmp(&"help".to_string()).is_eq() {
             println!("{}", "FORMAT: <SERVER_GROUP><SEPARATOR><COMMAND>".yellow());
             println!("\"?\" - separator for query");
             println!("\"!\" - separator for command");
             println!("\"$\" - separator for macro");
+            println!(
+                "{}",
+                "Queries and commands end with \";\" and can span multiple lines (Ctrl-C cancels)"
+                    .magenta()
+            );
             println!("{}", "Examples: ".green());
             println!(
                 "{}",
@@ -176,6 +493,13 @@ async fn main() {
             println!(
                 "haproxy_r - haproxy read-only worker node (effectively random active worker node)"
             );
+            println!(
+                "dynamic_groups of the inventory - servers which facts match \"when\", facts are fields like patroni_is_replica or citus_group_id"
+            );
+            println!(
+                "{}",
+                "Example: when: \"patroni_is_replica && citus_group_id > 0\" - replicas of citus workers".green()
+            );
             println!("{}", "Examples: ".green());
             println!("{}",
                      "caw ? select citus_version(); -- checks citus version on all active workers (switch to citus DB first)".green()
@@ -186,7 +510,47 @@ async fn main() {
                     .green()
             );
             println!("{}", "OTHER COMMANDS".yellow());
+            println!(
+                "{}",
+                "Connections stay open between requests, so SET, SET ROLE and temp tables carry over"
+                    .magenta()
+            );
+            println!("\\reset - closes all open connections, the next request connects again");
             println!("use <db_name> - switches DB to <db_name>. Default DB is postgres");
+            println!(
+                "env [<name>] - switches to the environment and its default cluster, lists environments without <name>"
+            );
+            println!(
+                "cluster [<name>] - switches to the cluster of the current environment, lists clusters without <name>"
+            );
+            println!(
+                "{}",
+                "Example: env prod, cluster cloud2 - reloads server groups and facts of prod/cloud2, DB goes back to postgres".green()
+            );
+            println!(
+                "<environment>/<cluster>:<group>[,...] - targets servers of other clusters, \"*\" matches every environment or cluster"
+            );
+            println!(
+                "{}",
+                "Example: dev/*:pgl ? select version(); - runs the query on leaders of every dev cluster".green()
+            );
+            println!(
+                "<group> & <group>, <group> | <group>, <group> - <group> - intersection, union and difference of server groups, \"&\" goes first, (...) groups"
+            );
+            println!(
+                "host(<host>,...), host~\"<regex>\" - servers of the cluster and of the clusters in the expression by host"
+            );
+            println!(
+                "{}",
+                "Example: pgl & workers - dr, host~\"^10\\.\" ? select 1; - combines server groups".green()
+            );
+            println!(
+                "resolve <expression> - prints servers of the server group expression without running anything"
+            );
+            println!(
+                "{}",
+                "Example: resolve all - host(192.168.4.111) - lists every server except one".green()
+            );
             println!(
                 "{}",
                 "Example: use constellation - switches DB to constellation".green()
@@ -195,11 +559,83 @@ async fn main() {
                 "show datatypes <true|false> - enable or disables data types in output tables. Default is true"
             );
             println!("show macro - show build-in macro commands");
+            println!(
+                "\\format <table|csv|tsv|json|jsonl|markdown|expanded> - sets output format for query results. Default is table"
+            );
+            println!(
+                "{}",
+                "Example: \\format expanded - shows every row as a vertical record".green()
+            );
+            println!(
+                "<group> ? <query> > <path>.<csv|tsv|json|jsonl|md|txt> - writes query results to the file, >> appends. Format follows the extension, the path starts with ./, ../ or /"
+            );
+            println!(
+                "{}",
+                "Example: pgl ? select * from pg_stat_activity; > ./activity.csv".green()
+            );
+            println!(
+                "\\out-dir [<dir>|off] - writes query results to one <host>_<db>.<ext> file per server in <dir>"
+            );
+            println!(
+                "\\merge <on|off|order by <column> [asc|desc], ...> - merges rows of all servers into one result with host, port and db columns"
+            );
+            println!(
+                "{}",
+                "Example: \\merge order by size desc - merges results and sorts them by size".green()
+            );
+            println!(
+                "<group> !! <command> - runs the command with two-phase commit: it is committed on all servers or on none"
+            );
+            println!(
+                "{}",
+                "Example: pgl !! alter table t add column c int; - adds the column on every node or on none".green()
+            );
+            println!("\\atomic <on|off> - runs every <group> ! <command> as <group> !! <command>");
+            println!(
+                "{}",
+                "DROP, TRUNCATE, DELETE/UPDATE without WHERE, ALTER SYSTEM and pg_terminate_backend commands ask to type the server group name".magenta()
+            );
+            println!(
+                "{}",
+                "commands on a protected environment or cluster always ask, --yes skips the confirmation".magenta()
+            );
+            println!(
+                "\\rolling <N|off> - runs commands, queries and macros on N servers at a time, the next batch starts only if the previous one succeeded and all servers are online and consistent"
+            );
+            println!(
+                "{}",
+                "Example: \\rolling 1, prw ! alter system set work_mem = '64MB'; - changes one node at a time".green()
+            );
+            println!("\\parallel <N|off> - runs requests on at most N servers at the same time");
+            println!(
+                "\\timeout <N|off> - cancels the request on servers which run it longer than N seconds"
+            );
+            println!(
+                "{}",
+                "Ctrl-C while a request runs cancels it on every server and prints which servers completed".magenta()
+            );
+            println!(
+                "\\compare <on|off> - prints every distinct result once with its servers and marks outlier servers"
+            );
+            println!(
+                "{}",
+                "Example: \\compare on, caw ? select extversion from pg_extension; - checks extension versions".green()
+            );
             println!(
                 "{}",
                 "Example: show false - disables data types to save space".green()
             );
-            println!("history - shows commands history");
+            println!(
+                "\\i <file_name> [--stop-on-error|--continue] - runs script file line by line. Stops on first error unless --continue"
+            );
+            println!(
+                "{}",
+                "Example: \\i upgrade.taco - runs requests from upgrade.taco".green()
+            );
+            println!("Tab - completes server groups, macro names, databases and commands");
+            println!("history - shows commands history (Ctrl-R searches history)");
+            println!("!<n> - runs command <n> from history");
+            println!("{}", "Example: !3 - runs command 3 from history".green());
             println!("exit - exits program");
 
             continue;
@@ -209,29 +645,126 @@ async fn main() {
             process::exit(0);
         }
         if preprocessed_command.cmp(&"history".to_string()).is_eq() {
+            let history = line_editor.get_history();
             if history.is_empty() {
                 println!("{}", "HISTORY IS EMPTY".yellow());
                 continue;
             }
             println!("{}", "HISTORY".yellow());
-            for (index, value) in history.iter_mut().enumerate() {
-                trim_newline(value);
+            for (index, value) in history.iter().enumerate() {
                 println!("{}: {}", index, value)
             }
             continue;
         }
-        if preprocessed_command.starts_with("use") {
-            let parts = preprocessed_command.split(" ");
-            let parts_vec: Vec<&str> = parts.collect();
-            if parts_vec.len() < 2usize {
-                println!("{}", "USE COMMAND FORMAT: use <db_name>".yellow());
+        if is_use_command(&preprocessed_command) {
+            process_use_command(&preprocessed_command, &settings);
+            continue;
+        }
+        if let Some((environment_name, cluster_name)) =
+            get_switch_command_names(&command, &inventory_manager)
+        {
+            match switch_cluster(
+                &mut inventory_manager,
+                environment_name,
+                cluster_name,
+                &settings,
+                &session_manager,
+            )
+            .await
+            {
+                Ok(new_server_provider) => {
+                    server_provider = new_server_provider;
+                    line_editor.set_server_group_names(server_provider.get_server_group_names());
+                    line_editor.set_cluster_names(inventory_manager.get_cluster_names());
+                    line_editor.set_database_names(
+                        get_database_names(&server_provider, &settings, &session_manager).await,
+                    );
+                }
+                Err(e) => println!("{}", e.to_string().red()),
+            }
+            continue;
+        }
+        if preprocessed_command == "resolve" || preprocessed_command.starts_with("resolve ") {
+            let expression = normalize_server_group(&command.trim()["resolve".len()..]);
+            if expression.is_empty() {
+                println!(
+                    "{}",
+                    "RESOLVE COMMAND FORMAT: resolve <server_group_expression>".yellow()
+                );
                 continue;
             }
-            println!("{}", format!("USING DB <{}>", parts_vec[1]).yellow());
+            resolve_server_group(&expression, &server_provider, &settings, &session_manager).await;
+            continue;
+        }
+        if preprocessed_command == "env" || preprocessed_command == "cluster" {
+            print_inventory_selection(&inventory_manager);
+            continue;
+        }
+        if preprocessed_command == "\\format" || preprocessed_command.starts_with("\\format ") {
+            process_format_command(&preprocessed_command, &settings);
+            continue;
+        }
+        if preprocessed_command == "\\out-dir" || preprocessed_command.starts_with("\\out-dir ") {
+            process_out_dir_command(&command, &settings);
+            continue;
+        }
+        if preprocessed_command == "\\atomic" || preprocessed_command.starts_with("\\atomic ") {
+            process_atomic_command_setting(&preprocessed_command, &settings);
+            continue;
+        }
+        if preprocessed_command == "\\rolling" || preprocessed_command.starts_with("\\rolling ") {
+            process_limit_command(&preprocessed_command, "rolling_batch_size", &settings);
+            continue;
+        }
+        if preprocessed_command == "\\reset" {
+            let sessions_count = session_manager.reset();
+            println!("{}", format!("CLOSED {} SESSION(S)", sessions_count).yellow());
+            continue;
+        }
+        if preprocessed_command == "\\timeout" || preprocessed_command.starts_with("\\timeout ") {
+            process_limit_command(&preprocessed_command, "request_timeout_sec", &settings);
+            continue;
+        }
+        if preprocessed_command == "\\parallel" || preprocessed_command.starts_with("\\parallel ") {
+            process_limit_command(&preprocessed_command, "parallel", &settings);
+            continue;
+        }
+        if preprocessed_command == "\\compare" || preprocessed_command.starts_with("\\compare ") {
+            process_compare_command(&preprocessed_command, &settings);
+            continue;
+        }
+        if preprocessed_command == "\\merge" || preprocessed_command.starts_with("\\merge ") {
+            process_merge_command(&command, &settings);
+            continue;
+        }
+        if preprocessed_command == "\\i" || preprocessed_command.starts_with("\\i ") {
+            let parts: Vec<&str> = command.split_whitespace().collect();
+            let options = parts.get(2..).unwrap_or_default();
+            if parts.len() < 2usize
+                || options.len() > 1
+                || options
+                    .iter()
+                    .any(|option| *option != "--stop-on-error" && *option != "--continue")
             {
-                // this block for mutex release
-                let mut settings_lock = settings.lock().unwrap();
-                settings_lock.insert("current_db".to_string(), parts_vec[1].to_string());
+                println!(
+                    "{}",
+                    "SCRIPT COMMAND FORMAT: \\i <file_name> [--stop-on-error|--continue]".yellow()
+                );
+                continue;
+            }
+            let stop_on_error = options != ["--continue"];
+            let script_result = run_script(
+                parts[1],
+                stop_on_error,
+                &server_provider,
+                &macro_provider,
+                &settings,
+                &session_manager,
+                None,
+            )
+            .await;
+            if let Err(e) = script_result {
+                println!("{}", e.to_string().red());
             }
             continue;
         }
@@ -267,84 +800,278 @@ async fn main() {
             println!("{}", "UNKNOWN REQUEST TYPE".red());
             continue;
         }
-        let request_type = get_request_type(&command);
-        match &request_type {
-            RequestType::Unknown => {
-                println!("{}", "UNKNOWN REQUEST TYPE".red());
-                continue;
-            }
-            RequestType::Macro => {
-                let get_raw_command_result = get_raw_command(&command, &request_type);
-                let raw_server_group = get_raw_command_result.0;
-                let raw_command = get_raw_command_result.1;
-
-                if !macro_provider.is_macro_exists(&raw_command) {
-                    println!("{}", "UNKNOWN MACRO NAME".red());
-                    continue;
-                }
+        let Some(request) = parse_request(&command) else {
+            println!("{}", "UNKNOWN REQUEST TYPE".red());
+            continue;
+        };
+        let is_query = matches!(request.request_type, RequestType::Query);
+        let run_result = run_request(
+            request,
+            &server_provider,
+            &macro_provider,
+            &settings,
+            &session_manager,
+            None,
+        )
+        .await;
+        if let Err(e) = run_result {
+            println!("{}", e.to_string().red());
+        }
+        if !is_query {
+            // commands and macros can create or drop databases
+            line_editor.set_database_names(
+                get_database_names(&server_provider, &settings, &session_manager).await,
+            );
+        }
+    }
+}
 
-                let macro_parameters = macro_provider.get_macro_parameters(&raw_command);
-                let mut macro_values = HashMap::<String, String>::new();
-                if let Some(macro_parameters) = &macro_parameters {
-                    if !macro_parameters.is_empty() {
-                        println!("{}", "INPUT PARAMETERS FOR MACRO".yellow());
-                    }
-                    for parameter in macro_parameters {
-                        let _ = io::stdout().write(format!("{} = ", &parameter[..]).as_bytes());
-                        let _ = io::stdout().flush();
-                        let mut parameter_value = String::new();
-                        io::stdin().read_line(&mut parameter_value).unwrap();
-                        macro_values
-                            .insert(parameter.to_string(), parameter_value.trim().to_string());
-                    }
-                }
-                let macro_commands =
-                    macro_provider.get_macro(&raw_command, macro_parameters, macro_values);
-
-                let macro_request_type =
-                    macro_provider.get_macro_request_type(&raw_command).unwrap();
-
-                for raw_command in macro_commands.unwrap() {
-                    let settings_clone = settings.clone();
-                    let servers = server_provider.get_servers_in_group(&raw_server_group);
-                    if servers.is_none() {
-                        println!("{}", "UNKNOWN SERVER GROUP NAME".red());
-                        continue;
-                    }
-                    let macro_request_type_clone = macro_request_type.clone();
-                    let handle = tokio::spawn(async move {
-                        process_request(
-                            raw_command,
-                            macro_request_type_clone,
-                            servers.unwrap(),
-                            settings_clone,
-                        )
-                        .await
-                    });
-                    handle.await.unwrap();
-                }
+/// Loads server groups of the current environment and cluster, collects facts
+/// and checks the cluster consistency
+async fn load_server_provider(
+    inventory_manager: &InventoryManager<'_>,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+    session_manager: &Arc<SessionManager>,
+) -> anyhow::Result<ServerProvider> {
+    let (environment_name, cluster_name) = inventory_manager
+        .get_current_names()
+        .unwrap_or_default();
+    println!("Loading Cluster: <{}/{}>", environment_name, cluster_name);
+    let Some((mut server_groups, citus_db_name)) = inventory_manager.get_static_server_groups()
+    else {
+        return Err(anyhow::anyhow!("Static server groups not defined"));
+    };
+    PasswordResolver::new().resolve_server_groups(&mut server_groups)?;
+    {
+        // this block for mutex release
+        let mut settings_lock = settings.lock().unwrap();
+        if inventory_manager.is_protected() {
+            settings_lock.insert("protected_inventory".to_string(), "true".to_string());
+        } else {
+            settings_lock.remove("protected_inventory");
+        }
+    }
+    println!("{}", "DONE Loading Cluster".green());
+    print_separator();
+
+    println!("Collecting Facts");
+    let mut server_provider = ServerProvider::new(
+        server_groups,
+        &inventory_manager.get_dynamic_groups(),
+        inventory_manager.get_all_cluster_server_groups(),
+    )
+    .await;
+    for warning in server_provider.get_warnings() {
+        println!("{}", warning.yellow());
+    }
+    let mut servers_to_check = server_provider.get_servers_in_group("all").unwrap();
+    let facts_collector = FactsCollector::new(settings, session_manager);
+    facts_collector
+        .collect_facts(&mut servers_to_check, citus_db_name)
+        .await;
+    drop(facts_collector);
+    println!("{}", "DONE Collecting Facts".green());
+    print_separator();
+
+    println!("Checking Cluster Consistency");
+    let mut consistency_checker = ClusterConsistencyChecker::new(settings);
+    if consistency_checker.check_cluster_consistency(&mut servers_to_check) {
+        println!("{}", "CLUSTER IS CONSISTENT".green());
+    } else {
+        println!("{}", "CLUSTER IS NOT CONSISTENT".red());
+    }
+    drop(consistency_checker);
+    server_provider.update_server_groups(servers_to_check);
+    println!("{}", "DONE Checking Cluster Consistency".green());
+    print_separator();
+
+    let servers = server_provider.get_servers_in_group("all").unwrap();
+    println!("Found {} servers", servers.len());
+    render_severs_table(servers);
+    print_separator();
+    Ok(server_provider)
+}
+
+/// Environment and cluster of "env <name>" and "cluster <name>",
+/// switching the environment selects its default cluster
+fn get_switch_command_names(
+    command: &str,
+    inventory_manager: &InventoryManager,
+) -> Option<(Option<String>, Option<String>)> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    match parts.as_slice() {
+        [first, name] if first.eq_ignore_ascii_case("env") => Some((Some(name.to_string()), None)),
+        [first, name] if first.eq_ignore_ascii_case("cluster") => {
+            let (environment_name, _) = inventory_manager.get_selection();
+            Some((environment_name, Some(name.to_string())))
+        }
+        _ => None,
+    }
+}
+
+/// Discovers the cluster from the seed node, adds it to the environment and saves the inventory
+async fn discover_cluster(
+    inventory_manager: &mut InventoryManager<'_>,
+    environment_name: Option<&String>,
+    cluster_name: Option<&String>,
+    seed: &str,
+    name: &Option<String>,
+    citus_db_name: &Option<String>,
+    output_file_name: &str,
+) -> anyhow::Result<()> {
+    // a cluster which is discovered again connects with its own settings
+    let existing_template = name.as_ref().and_then(|name| {
+        inventory_manager
+            .get_cluster_template(environment_name, Some(name))
+            .ok()
+    });
+    let (mut template, defaults) = match existing_template {
+        Some(existing_template) => existing_template,
+        None => inventory_manager.get_cluster_template(environment_name, cluster_name)?,
+    };
+    if citus_db_name.is_some() {
+        template.citus_db_name = citus_db_name.clone();
+    }
+    println!("Discovering Cluster From: <{}>", seed);
+    let mut cluster_discoverer = ClusterDiscoverer::new(template, defaults);
+    let discover_result = cluster_discoverer.discover(seed).await;
+    for warning in cluster_discoverer.get_warnings() {
+        println!("{}", warning.yellow());
+    }
+    let mut cluster = discover_result?;
+    if let Some(name) = name {
+        cluster.name = name.clone();
+    }
+    for server_group in &cluster.server_groups {
+        let hosts: Vec<String> = server_group
+            .servers
+            .iter()
+            .map(|server| match server.port {
+                Some(port) => format!("{}:{}", server.host, port),
+                None => server.host.clone(),
+            })
+            .collect();
+        println!("{}: {}", server_group.name.yellow(), hosts.join(", "));
+    }
+    let cluster_name = cluster.name.clone();
+    if inventory_manager.add_cluster(environment_name, cluster)? {
+        println!(
+            "{}",
+            format!(
+                "CLUSTER <{}> EXISTS, DISCOVERED SERVER GROUPS ARE MERGED INTO IT",
+                cluster_name
+            )
+            .yellow()
+        );
+    }
+    inventory_manager.save_inventory_to_file(output_file_name).await?;
+    println!(
+        "{}",
+        format!("CLUSTER <{}> IS SAVED TO <{}>", cluster_name, output_file_name).green()
+    );
+    Ok(())
+}
+
+fn print_inventory_selection(inventory_manager: &InventoryManager) {
+    let (environment_name, cluster_name) = inventory_manager
+        .get_current_names()
+        .unwrap_or_default();
+    println!("{}", "ENVIRONMENTS".yellow());
+    for name in inventory_manager.get_environment_names() {
+        if name == environment_name {
+            println!("{}", format!("* {}", name).green());
+        } else {
+            println!("  {}", name);
+        }
+    }
+    println!("{}", format!("CLUSTERS OF <{}>", environment_name).yellow());
+    for name in inventory_manager.get_cluster_names() {
+        if name == cluster_name {
+            println!("{}", format!("* {}", name).green());
+        } else {
+            println!("  {}", name);
+        }
+    }
+}
+
+/// Prints the servers selected by the server group expression, false on errors
+async fn resolve_server_group(
+    expression: &str,
+    server_provider: &ServerProvider,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+    session_manager: &Arc<SessionManager>,
+) -> bool {
+    match server_provider
+        .get_servers_in_expression(expression, settings, session_manager)
+        .await
+    {
+        Ok((servers, is_protected)) => {
+            if is_protected {
+                println!("{}", "INVENTORY IS PROTECTED".red());
             }
-            _ => {
-                let get_raw_command_result = get_raw_command(&command, &request_type);
-                let raw_server_group = get_raw_command_result.0;
-                let raw_command = get_raw_command_result.1;
-                let servers = server_provider.get_servers_in_group(&raw_server_group);
-                if servers.is_none() {
-                    println!("{}", "UNKNOWN SERVER GROUP NAME".red());
-                    continue;
-                }
-                history.push(command.clone());
-                let settings_clone = settings.clone();
-                let handle = tokio::spawn(async move {
-                    process_request(raw_command, request_type, servers.unwrap(), settings_clone)
-                        .await
-                });
-                handle.await.unwrap();
+            println!("{}", format!("TARGET SERVERS ({}):", servers.len()).yellow());
+            for server in &servers {
+                println!("{}", get_server_name(server, settings));
+            }
+            true
+        }
+        Err(e) => {
+            println!("{}", e.to_string().red());
+            false
+        }
+    }
+}
+
+/// Switches to the environment and cluster and loads its servers,
+/// the previous environment and cluster stay when the new one can not be loaded
+async fn switch_cluster(
+    inventory_manager: &mut InventoryManager<'_>,
+    environment_name: Option<String>,
+    cluster_name: Option<String>,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+    session_manager: &Arc<SessionManager>,
+) -> anyhow::Result<ServerProvider> {
+    let (previous_environment_name, previous_cluster_name) = inventory_manager.get_selection();
+    inventory_manager.select(environment_name, cluster_name)?;
+    // sessions belong to servers of the previous cluster, facts of the new one open new sessions
+    session_manager.reset();
+    let _ctrl_c_guard = CtrlCGuard::new();
+    let load_result = tokio::select! {
+        result = load_server_provider(inventory_manager, settings, session_manager) => result,
+        _ = tokio::signal::ctrl_c() => Err(anyhow::anyhow!("CLUSTER SWITCH CANCELLED")),
+    };
+    match load_result {
+        Ok(server_provider) => {
+            // the DB selected by "use" belongs to the previous cluster
+            let previous_db = {
+                // this block for mutex release
+                let mut settings_lock = settings.lock().unwrap();
+                settings_lock.insert("current_db".to_string(), "postgres".to_string())
+            };
+            if previous_db.as_deref() != Some("postgres") {
+                println!("{}", "USING DB <postgres>".yellow());
             }
+            Ok(server_provider)
+        }
+        Err(e) => {
+            inventory_manager.select(previous_environment_name, previous_cluster_name)?;
+            Err(e)
         }
     }
 }
 
+async fn get_database_names(
+    server_provider: &ServerProvider,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+    session_manager: &Arc<SessionManager>,
+) -> Vec<String> {
+    let servers = server_provider
+        .get_servers_in_group("online")
+        .unwrap_or_default();
+    let facts_collector = FactsCollector::new(settings, session_manager);
+    facts_collector.collect_database_names(&servers).await
+}
+
 fn print_banner() {
     println!(
         "{}",
@@ -460,442 +1187,1286 @@ fn render_severs_table(mut servers: Vec<Server>) {
     println!("{}", table.to_string());
 }
 
-fn trim_newline(s: &mut String) {
-    if s.ends_with('\n') {
-        s.pop();
-        if s.ends_with('\r') {
-            s.pop();
-        }
+/// Parses "!<n>" history references, e.g. "!3" re-runs entry 3 from the "history" listing
+fn get_history_reference(command: &str) -> Option<usize> {
+    let reference = command.trim().strip_prefix('!')?;
+    if reference.is_empty() || !reference.chars().all(|c| c.is_ascii_digit()) {
+        return None;
     }
+    reference.parse::<usize>().ok()
 }
 
-async fn process_request(
-    raw_command: String,
-    request_type: RequestType,
-    servers: Vec<Server>,
-    settings: Arc<Mutex<HashMap<String, String>>>,
-) {
-    print_separator();
-    println!("Processing: [{}]", &raw_command.green());
-    print_separator();
+fn is_use_command(preprocessed_command: &str) -> bool {
+    preprocessed_command == "use" || preprocessed_command.starts_with("use ")
+}
 
-    let (tx, mut rx) = mpsc::channel(32);
+fn process_use_command(
+    preprocessed_command: &str,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+) -> bool {
+    let parts_vec: Vec<&str> = preprocessed_command.split_whitespace().collect();
+    if parts_vec.len() < 2usize {
+        println!("{}", "USE COMMAND FORMAT: use <db_name>".yellow());
+        return false;
+    }
+    println!("{}", format!("USING DB <{}>", parts_vec[1]).yellow());
+    {
+        // this block for mutex release
+        let mut settings_lock = settings.lock().unwrap();
+        settings_lock.insert("current_db".to_string(), parts_vec[1].to_string());
+    }
+    true
+}
 
-    let mut set = JoinSet::new();
+/// Runs every request line of a script file and returns the number of failed lines
+async fn run_script(
+    file_name: &str,
+    stop_on_error: bool,
+    server_provider: &ServerProvider,
+    macro_provider: &MacroProvider,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+    session_manager: &Arc<SessionManager>,
+    macro_values: Option<HashMap<String, String>>,
+) -> anyhow::Result<usize> {
+    let content = tokio::fs::read_to_string(file_name)
+        .await
+        .with_context(|| format!("Failed to read script file: {}", file_name))?;
 
-    for server in servers {
-        let command_clone = raw_command.clone();
-        let request_type_clone = request_type.clone();
-        let settings_clone = settings.clone();
-        let tx_clone = tx.clone();
-        set.spawn(async move {
-            match request_type_clone {
-                RequestType::Query => {
-                    process_query(server, command_clone, settings_clone, tx_clone).await
-                }
-                RequestType::Command => {
-                    process_command(server, command_clone, settings_clone, tx_clone).await
-                }
-                RequestType::Macro => {
-                    process_macro(server, command_clone, settings_clone, tx_clone).await
-                }
-                _ => Ok(0u64),
+    print_separator();
+    println!("Running Script File: <{}>", file_name);
+
+    let mut failed_lines = 0usize;
+    for (line_number, request) in split_script(&content) {
+        let line = request.as_deref().unwrap_or_default();
+        let preprocessed_line = line.to_lowercase().trim().to_string();
+        let line_result = if let Err(message) = &request {
+            Err(anyhow::anyhow!("{}", message))
+        } else if is_use_command(&preprocessed_line) {
+            if process_use_command(&preprocessed_line, settings) {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("INVALID USE COMMAND"))
             }
-        });
-    }
-
-    tokio::spawn(async move {
-        while let result = rx.recv().await {
-            match result {
-                Some(printable_result) => {
-                    print!("{}", printable_result);
-                }
-                None => {}
+        } else {
+            run_script_line(
+                line,
+                server_provider,
+                macro_provider,
+                settings,
+                session_manager,
+                macro_values.clone(),
+            )
+            .await
+        };
+        if let Err(e) = line_result {
+            failed_lines += 1;
+            eprintln!("{}", format!("LINE {}: {}", line_number, e).red());
+            if stop_on_error {
+                eprintln!(
+                    "{}",
+                    format!("SCRIPT STOPPED AT LINE {}", line_number).red()
+                );
+                break;
             }
         }
-        Ok::<u64, Error>(0u64)
-    });
-
-    let mut total: u64 = 0;
-    while let Some(res) = set.join_next().await {
-        total += res.unwrap().unwrap(); // TODO
     }
 
-    let mut result = String::new();
-    result.push_str(&format!("\n{}\n", build_separator()));
-    result.push_str(&format!("Total rows: {}", total));
-    result.push_str(&format!("\n{}\n", build_separator()));
-    if tx.send(result).await.as_ref().is_err() {
-        eprintln!("{}", "ERROR SENDING RESULT TO PRINTER THREAD".red());
+    if failed_lines == 0 {
+        println!("{}", "DONE Running Script File".green());
+    } else {
+        println!(
+            "{}",
+            format!("SCRIPT FINISHED WITH {} FAILED LINE(S)", failed_lines).red()
+        );
     }
+    print_separator();
+    Ok(failed_lines)
 }
 
-fn get_request_type(command: &String) -> RequestType {
-    if command.contains("?") {
-        return RequestType::Query;
-    }
-    if command.contains("!") {
-        return RequestType::Command;
+async fn run_script_line(
+    line: &str,
+    server_provider: &ServerProvider,
+    macro_provider: &MacroProvider,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+    session_manager: &Arc<SessionManager>,
+    macro_values: Option<HashMap<String, String>>,
+) -> anyhow::Result<()> {
+    let Some(request) = parse_request(line) else {
+        return Err(anyhow::anyhow!("UNKNOWN REQUEST TYPE"));
+    };
+    let summary = run_request(
+        request,
+        server_provider,
+        macro_provider,
+        settings,
+        session_manager,
+        macro_values,
+    )
+    .await?;
+    if summary.failed_servers > 0 {
+        return Err(anyhow::anyhow!(
+            "REQUEST FAILED ON {} SERVER(S)",
+            summary.failed_servers
+        ));
     }
-    if command.contains("$") {
-        return RequestType::Macro;
+    if summary.skipped_servers > 0 {
+        return Err(anyhow::anyhow!(
+            "REQUEST SKIPPED {} SERVER(S)",
+            summary.skipped_servers
+        ));
     }
-    RequestType::Unknown
+    Ok(())
 }
 
-fn get_raw_command(command: &String, request_type: &RequestType) -> (String, String) {
-    let request_separator = match request_type {
-        RequestType::Query => "?".to_string(),
-        RequestType::Command => "!".to_string(),
-        RequestType::Macro => "$".to_string(),
-        _ => "".to_string(),
-    };
-
-    let parts = command.split(&request_separator);
-    let mut raw_parts: Vec<String> = parts
-        .into_iter()
-        .map(|x| x.to_string().trim().to_lowercase())
-        .collect();
+async fn run_request(
+    request: ParsedRequest,
+    server_provider: &ServerProvider,
+    macro_provider: &MacroProvider,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+    session_manager: &Arc<SessionManager>,
+    macro_values: Option<HashMap<String, String>>,
+) -> anyhow::Result<RequestSummary> {
+    let ParsedRequest {
+        server_group: raw_server_group,
+        request_type,
+        body: raw_command,
+    } = request;
+    let (servers, is_protected) = server_provider
+        .get_servers_in_expression(&raw_server_group, settings, session_manager)
+        .await?;
+    let mut summary = RequestSummary::default();
+    match request_type {
+        RequestType::Macro => {
+            if !macro_provider.is_macro_exists(&raw_command) {
+                return Err(anyhow::anyhow!("UNKNOWN MACRO NAME"));
+            }
 
-    (raw_parts.remove(0), raw_parts.remove(0))
-}
+            let macro_parameters = macro_provider.get_macro_parameters(&raw_command);
+            let macro_values = match macro_values {
+                Some(macro_values) => {
+                    for parameter in macro_parameters.iter().flatten() {
+                        if !macro_values.contains_key(parameter) {
+                            return Err(anyhow::anyhow!(
+                                "MISSING MACRO PARAMETER {}",
+                                parameter.trim_matches('$')
+                            ));
+                        }
+                    }
+                    macro_values
+                }
+                None => read_macro_values(&macro_parameters),
+            };
+            let macro_commands =
+                macro_provider.get_macro(&raw_command, macro_parameters, macro_values);
+
+            let macro_request_type = macro_provider.get_macro_request_type(&raw_command).unwrap();
+            let macro_commands = macro_commands.unwrap();
+            if macro_request_type == RequestType::Command {
+                confirm_commands(
+                    &raw_server_group,
+                    &servers,
+                    &macro_commands,
+                    is_protected,
+                    settings,
+                )?;
+            }
 
-async fn process_query(
-    mut server: Server,
-    query: String,
-    settings: Arc<Mutex<HashMap<String, String>>>,
-    tx: Sender<String>,
-) -> Result<u64, Error> {
-    let mut show_data_types = false;
-    {
-        // this block for mutex release
-        let settings_lock = settings.lock().unwrap();
-        match settings_lock.get(&"current_db".to_string()) {
-            Some(db_name) => {
-                server.set_db_name(db_name.clone());
+            for raw_command in macro_commands {
+                let settings_clone = settings.clone();
+                let session_manager_clone = session_manager.clone();
+                let servers_clone = servers.clone();
+                let macro_request_type_clone = macro_request_type.clone();
+                let handle = tokio::spawn(async move {
+                    process_request(
+                        raw_command,
+                        macro_request_type_clone,
+                        servers_clone,
+                        settings_clone,
+                        session_manager_clone,
+                        None,
+                    )
+                    .await
+                });
+                summary.merge(&handle.await?);
             }
-            _ => {}
+            Ok(summary)
         }
-        match settings_lock.get(&"show_data_types".to_string()) {
-            Some(show_dt) => {
-                if show_dt.eq("true") {
-                    show_data_types = true;
-                }
-                if show_dt.eq("false") {
-                    show_data_types = false;
-                }
+        _ => {
+            // shell commands keep their own redirects
+            let (raw_command, output_redirect) = match request_type {
+                RequestType::Query => split_output_redirect(&raw_command),
+                _ => (raw_command, None),
+            };
+            if let Some(output_redirect) = &output_redirect {
+                output_redirect.check()?;
             }
-            _ => {
-                show_data_types = true;
+            let request_type =
+                if request_type == RequestType::Command && is_atomic_mode(settings) {
+                    RequestType::AtomicCommand
+                } else {
+                    request_type
+                };
+            if matches!(request_type, RequestType::Command | RequestType::AtomicCommand) {
+                confirm_commands(
+                    &raw_server_group,
+                    &servers,
+                    std::slice::from_ref(&raw_command),
+                    is_protected,
+                    settings,
+                )?;
             }
+            let settings_clone = settings.clone();
+            let session_manager_clone = session_manager.clone();
+            let handle = tokio::spawn(async move {
+                process_request(
+                    raw_command,
+                    request_type,
+                    servers,
+                    settings_clone,
+                    session_manager_clone,
+                    output_redirect,
+                )
+                .await
+            });
+            summary.merge(&handle.await?);
+            Ok(summary)
         }
     }
-    let connection_string = &server.to_string();
-    let connect_result = tokio_postgres::connect(connection_string, NoTls).await;
-    if connect_result.as_ref().is_err() {
-        let mut result = String::new();
-        result.push_str(&format!(
-            "\n[{}:{}] \n",
-            &server.host,
-            &server.db_name.unwrap()
-        ));
-        result.push_str(&*connect_result.as_ref().err().unwrap().to_string());
-        result.push_str(&*"\n".to_string());
-        if tx.send(result.clone()).await.is_err() {
-            eprintln!("{}", result.red());
+}
+
+/// Asks to type the server group name before destructive commands
+/// and before every command on a protected environment or cluster
+fn confirm_commands(
+    raw_server_group: &str,
+    servers: &[Server],
+    commands: &[String],
+    is_protected: bool,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+) -> anyhow::Result<()> {
+    let mut destructive_statements: Vec<String> = Vec::new();
+    for command in commands {
+        for destructive_statement in get_destructive_statements(command) {
+            if !destructive_statements.contains(&destructive_statement) {
+                destructive_statements.push(destructive_statement);
+            }
         }
-        return Ok(0u64);
+    }
+    let assume_yes = {
+        // this block for mutex release
+        let settings_lock = settings.lock().unwrap();
+        settings_lock.get("assume_yes").map(String::as_str) == Some("true")
+    };
+    if destructive_statements.is_empty() && !is_protected {
+        return Ok(());
     }
 
-    let (client, connection) = connect_result.unwrap();
-    tokio::spawn(async move {
-        if connection.await.as_ref().is_err() {
-            eprintln!("{}", "ERROR OPEN CONNECTION".red());
-        }
-    });
+    if is_protected {
+        println!("{}", "INVENTORY IS PROTECTED".red());
+    }
+    if !destructive_statements.is_empty() {
+        println!(
+            "{}",
+            format!("DESTRUCTIVE COMMAND: {}", destructive_statements.join(", ")).red()
+        );
+    }
+    println!("{}", format!("TARGET SERVERS ({}):", servers.len()).yellow());
+    for server in servers {
+        println!("{}", get_server_name(server, settings));
+    }
+    if assume_yes {
+        println!("{}", "CONFIRMED BY --yes".yellow());
+        return Ok(());
+    }
+    let _ = io::stdout().write(
+        format!("TYPE SERVER GROUP NAME <{}> TO CONFIRM: ", raw_server_group).as_bytes(),
+    );
+    let _ = io::stdout().flush();
+    let mut confirmation = String::new();
+    io::stdin().read_line(&mut confirmation)?;
+    if confirmation.trim() != raw_server_group {
+        return Err(anyhow::anyhow!("COMMAND IS NOT CONFIRMED"));
+    }
+    Ok(())
+}
 
-    let rows_result = client.query(&query, &[]).await;
-    if rows_result.as_ref().is_err() {
-        let mut result = String::new();
-        result.push_str(&format!(
-            "\n[{}:{}] \n",
-            &server.host,
-            &server.db_name.unwrap()
-        ));
-        result.push_str(&*rows_result.as_ref().err().unwrap().to_string());
-        result.push_str(&*"\n".to_string());
-        if tx.send(result.clone()).await.is_err() {
-            eprintln!("{}", result.red());
+fn read_macro_values(macro_parameters: &Option<Vec<String>>) -> HashMap<String, String> {
+    let mut macro_values = HashMap::<String, String>::new();
+    if let Some(macro_parameters) = macro_parameters {
+        if !macro_parameters.is_empty() {
+            println!("{}", "INPUT PARAMETERS FOR MACRO".yellow());
+        }
+        for parameter in macro_parameters {
+            let _ = io::stdout().write(format!("{} = ", &parameter[..]).as_bytes());
+            let _ = io::stdout().flush();
+            let mut parameter_value = String::new();
+            io::stdin().read_line(&mut parameter_value).unwrap();
+            macro_values.insert(parameter.to_string(), parameter_value.trim().to_string());
         }
-        return Ok(0u64);
     }
+    macro_values
+}
 
-    let rows = rows_result.unwrap();
-
-    if rows.len() == 0 {
-        return Ok(0u64);
+/// Converts `--param NAME=VALUE` arguments into macro values keyed as `$NAME$`
+fn get_macro_values_from_args(params: &[String]) -> anyhow::Result<HashMap<String, String>> {
+    let mut macro_values = HashMap::<String, String>::new();
+    for param in params {
+        let Some((name, value)) = param.split_once('=') else {
+            return Err(anyhow::anyhow!(
+                "MACRO PARAMETER FORMAT: --param NAME=VALUE, GOT <{}>",
+                param
+            ));
+        };
+        let name = name.trim().trim_matches('$').to_uppercase();
+        macro_values.insert(format!("${}$", name), value.trim().to_string());
     }
+    Ok(macro_values)
+}
 
-    let mut table = Table::new();
-
-    let mut row_vec: Vec<Cell> = Vec::new();
-    row_vec.push(Cell::new(&""));
-    for column in rows[0].columns().iter() {
-        let mut column_header = String::new();
-        column_header.push_str(column.name());
-        if show_data_types {
-            column_header.push(':');
-            column_header.push_str(&*column.type_().to_string());
-        }
-        row_vec.push(Cell::new(&*column_header));
-    }
-    table.add_row(Row::new(row_vec));
-    for (row_index, row) in rows.iter().enumerate() {
-        let mut row_vec: Vec<Cell> = Vec::new();
-        row_vec.push(Cell::new(&*format!("{}", row_index)));
-        for (col_index, column) in row.columns().iter().enumerate() {
-            // https://www.postgresql.org/docs/current/datatype.html
-            let col_type: String = column.type_().to_string();
-
-            // TODO: Handle NULLs with Option
-            // Example: let value: Option<i16> = row.get(col_index); // this is null
-
-            // region Numeric Types
-            // https://www.postgresql.org/docs/current/datatype-numeric.html
-            if col_type == "int2" || col_type == "smallint" || col_type == "smallserial" {
-                let value: i16 = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
-            }
-            if col_type == "int4" || col_type == "int" || col_type == "serial" || col_type == "xid"
-            {
-                let value: i32 = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
-            }
-            if col_type == "int8" || col_type == "bigint" || col_type == "bigserial" {
-                let value: i64 = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
-            }
-            if col_type == "decimal" || col_type == "numeric" {
-                let value: Decimal = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
-            }
-            if col_type == "real" || col_type == "float4" {
-                let value: f32 = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
-            }
-            if col_type == "double precision" || col_type == "float8" {
-                let value: f64 = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
-            }
-            // endregion
-
-            // region Monetary Types
-            // https://www.postgresql.org/docs/current/datatype-money.html
-            if col_type == "money" {
-                // TODO:
-                //let value: Money = row.get(col_index);
-                //row_vec.push(Cell::new(&*value.to_string()));
-                row_vec.push(Cell::new("?money?"));
-                continue;
-            }
-            // endregion
-
-            // region Character Types
-            // https://www.postgresql.org/docs/current/datatype-character.html
-            if col_type == "varchar"
-                || col_type == "text"
-                || col_type == "bpchar"
-                || col_type == "character"
-                || col_type == "char"
-            {
-                // TODO: char type
-                // SELECT attalign FROM pg_attribute WHERE attrelid = 'test'::regclass;
-                let value: &str = row.get(col_index);
-                row_vec.push(Cell::new(value));
-                continue;
-            }
-            // endregion
+async fn process_request(
+    raw_command: String,
+    request_type: RequestType,
+    servers: Vec<Server>,
+    settings: Arc<Mutex<HashMap<String, String>>>,
+    session_manager: Arc<SessionManager>,
+    output_redirect: Option<OutputRedirect>,
+) -> RequestSummary {
+    print_separator();
+    println!("Processing: [{}]", &raw_command.green());
+    print_separator();
 
-            // region Binary Data Types
-            // https://www.postgresql.org/docs/current/datatype-binary.html
-            if col_type == "bytea" {
-                // TODO:
-                row_vec.push(Cell::new("?bytea?"));
-                continue;
-            }
-            // endregion
+    let (tx, mut rx) = mpsc::channel(32);
 
-            // region Date/Time Types
-            // https://www.postgresql.org/docs/current/datatype-datetime.html
-            if col_type == "timestamp" {
-                let value: NaiveDateTime = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
-            }
-            if col_type == "timestamptz" {
-                let value: DateTime<Local> = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
-            }
-            if col_type == "time" {
-                let value: NaiveTime = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
+    let mut output_writer = get_output_writer(&settings, output_redirect);
+    let mut result_comparer = get_result_comparer(&settings);
+    let mut merge_options = get_merge_options(&settings);
+    let printer = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            match message {
+                PrinterMessage::Text(text) => print!("{}", text),
+                PrinterMessage::QueryResult(query_result) => {
+                    match (&mut result_comparer, &mut merge_options) {
+                        (Some(result_comparer), _) => result_comparer.add(query_result),
+                        (None, Some((result_merger, _))) => result_merger.add(query_result),
+                        (None, None) => {
+                            if let Err(e) = output_writer.write(&query_result) {
+                                println!("{}", e.to_string().red());
+                            }
+                        }
+                    }
+                }
             }
-            if col_type == "timetz" {
-                let value: DateTime<Local> = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                // TODO:
-                //row_vec.push(Cell::new("?timetz?"));
-                continue;
+        }
+        if let Some(result_comparer) = result_comparer {
+            let result_groups = result_comparer.finish();
+            for (group_index, result_group) in result_groups.iter().enumerate() {
+                print_result_group(group_index, result_groups.len(), result_group);
+                if let Err(e) = output_writer.write_merged(&result_group.result) {
+                    println!("{}", e.to_string().red());
+                }
             }
-            if col_type == "date" {
-                let value: NaiveDate = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
+            print_compare_summary(&result_groups);
+        } else if let Some((result_merger, order_by)) = merge_options {
+            for mut merged_result in result_merger.finish() {
+                if let Err(e) = sort_rows(&mut merged_result, &order_by) {
+                    println!("{}", e.to_string().red());
+                }
+                if let Err(e) = output_writer.write_merged(&merged_result) {
+                    println!("{}", e.to_string().red());
+                }
             }
-            if col_type == "interval" {
-                // let value: IntervalWrapper = row.get(col_index);
-                // row_vec.push(Cell::new(&*value.to_string()));
-                // TODO:
-                row_vec.push(Cell::new("?interval?"));
-                continue;
+        }
+        match output_writer.finish() {
+            Ok(file_paths) => {
+                for file_path in file_paths {
+                    println!("{}", format!("SAVED TO {}", file_path.display()).green());
+                }
             }
-            // endregion
+            Err(e) => println!("{}", e.to_string().red()),
+        }
+        let _ = io::stdout().flush();
+    });
 
-            // region Boolean Type
-            // https://www.postgresql.org/docs/current/datatype-boolean.html
-            if col_type == "boolean" || col_type == "bool" {
-                let value: bool = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
-            }
-            // endregion
+    let (rolling_batch_size, _) = get_execution_limits(&settings);
+    let summary = if request_type == RequestType::AtomicCommand {
+        // two-phase commit prepares all servers before it commits any of them
+        process_atomic_command(raw_command, servers, settings, session_manager, tx.clone()).await
+    } else if let Some(rolling_batch_size) = rolling_batch_size {
+        process_rolling_request(
+            &raw_command,
+            &request_type,
+            servers,
+            rolling_batch_size,
+            &settings,
+            &session_manager,
+            &tx,
+        )
+        .await
+    } else {
+        process_servers(
+            &raw_command,
+            &request_type,
+            servers,
+            &settings,
+            &session_manager,
+            &tx,
+        )
+        .await
+    };
+    drop(tx);
+    let _ = printer.await;
 
-            // region UUID Type
-            //https://www.postgresql.org/docs/current/datatype-uuid.html
-            if col_type == "uuid" {
-                let value: Uuid = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
-            }
-            // endregion
+    println!("\n{}", build_separator());
+    println!("Total rows: {}", summary.total_rows);
+    if summary.failed_servers > 0 {
+        println!("Failed servers: {}", summary.failed_servers);
+    }
+    if summary.skipped_servers > 0 {
+        println!("Skipped servers: {}", summary.skipped_servers);
+    }
+    print_separator();
 
-            // region Object Identifier Types
-            // https://www.postgresql.org/docs/current/datatype-oid.html
-            if col_type == "oid" {
-                // SELECT attrelid,attname,atttypid,attlen,attnum,attcacheoff,atttypmod,attndims,attbyval,attnotnull,atthasdef,atthasmissing,attisdropped,attislocal,attinhcount,attstattarget,attcollation,attacl,attoptions,attfdwoptions,attmissingval FROM pg_attribute WHERE attrelid = 'test'::regclass;
-                let value: Oid = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
-            }
-            // endregion
+    summary
+}
 
-            // region Inet Types
-            // https://docs.rs/tokio-postgres/latest/tokio_postgres/types/trait.ToSql.html
-            if col_type == "inet" {
-                let value: IpAddr = row.get(col_index);
-                row_vec.push(Cell::new(&*value.to_string()));
-                continue;
-            }
+/// Runs the request on all servers at once, but on at most "parallel" servers at a time
+async fn process_servers(
+    raw_command: &str,
+    request_type: &RequestType,
+    servers: Vec<Server>,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+    session_manager: &Arc<SessionManager>,
+    tx: &Sender<PrinterMessage>,
+) -> RequestSummary {
+    let (_, parallel) = get_execution_limits(settings);
+    let semaphore = Arc::new(Semaphore::new(parallel.unwrap_or(Semaphore::MAX_PERMITS)));
+    let request_timeout = get_request_timeout(settings);
+    let (cancel_tx, cancel_rx) = watch::channel(false);
+    let mut set = JoinSet::new();
+    let mut server_names = HashMap::new();
 
-            // TODO: more types
-            row_vec.push(Cell::new("?")); //placeholder for unknown types
-        }
-        table.add_row(Row::new(row_vec));
+    for server in servers {
+        let server_name = get_server_name(&server, settings);
+        let command_clone = raw_command.to_string();
+        let request_type_clone = request_type.clone();
+        let settings_clone = settings.clone();
+        let session_manager_clone = session_manager.clone();
+        let semaphore_clone = semaphore.clone();
+        let cancel_rx_clone = cancel_rx.clone();
+        let tx_clone = tx.clone();
+        let abort_handle = set.spawn(async move {
+            let _permit = semaphore_clone.acquire_owned().await;
+            let cancel_token_slot = CancelTokenSlot::default();
+            let cancel_token_slot_clone = cancel_token_slot.clone();
+            let task = async move {
+                match request_type_clone {
+                    RequestType::Query => {
+                        process_query(
+                            server,
+                            command_clone,
+                            settings_clone,
+                            session_manager_clone,
+                            tx_clone,
+                            cancel_token_slot_clone,
+                        )
+                        .await
+                    }
+                    // atomic commands are not run server by server
+                    RequestType::Command | RequestType::AtomicCommand => {
+                        process_command(
+                            server,
+                            command_clone,
+                            settings_clone,
+                            session_manager_clone,
+                            tx_clone,
+                            cancel_token_slot_clone,
+                        )
+                        .await
+                    }
+                    RequestType::Macro => {
+                        process_macro(
+                            server,
+                            command_clone,
+                            settings_clone,
+                            session_manager_clone,
+                            tx_clone,
+                            cancel_token_slot_clone,
+                        )
+                        .await
+                    }
+                }
+            };
+            run_cancellable(task, cancel_token_slot, request_timeout, cancel_rx_clone).await
+        });
+        server_names.insert(abort_handle.id(), server_name);
     }
 
-    let mut result = String::new();
-    result.push_str(&format!(
-        "\n[{}:{}] \n",
-        &server.host,
-        &server.db_name.unwrap()
-    ));
-    result.push_str(&format!("{}\n", table.to_string()));
-    if tx.send(result).await.as_ref().is_err() {
+    let results = join_server_tasks(set, server_names, cancel_tx, tx).await;
+    print_server_report(&results, tx).await;
+    let mut summary = RequestSummary::default();
+    for (_, _, rows) in results {
+        match rows {
+            Some(rows) => summary.total_rows += rows,
+            None => summary.failed_servers += 1,
+        }
+    }
+    summary
+}
+
+/// Number of running tasks which handle Ctrl-C themselves. tokio keeps its Ctrl-C handler
+/// for the whole process once it is installed, so at other times Ctrl-C has to end taco
+static CTRL_C_HANDLERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Marks that Ctrl-C is handled by the caller while the guard lives
+struct CtrlCGuard;
+
+impl CtrlCGuard {
+    fn new() -> Self {
+        CTRL_C_HANDLERS.fetch_add(1, AtomicOrdering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for CtrlCGuard {
+    fn drop(&mut self) {
+        CTRL_C_HANDLERS.fetch_sub(1, AtomicOrdering::SeqCst);
+    }
+}
+
+/// Ends taco on Ctrl-C like the default handler, unless a request handles it
+async fn exit_on_unhandled_ctrl_c() {
+    while tokio::signal::ctrl_c().await.is_ok() {
+        if CTRL_C_HANDLERS.load(AtomicOrdering::SeqCst) == 0 {
+            process::exit(130);
+        }
+    }
+}
+
+/// Cancel token and TLS settings of the server connection, set once the task is connected
+type CancelTokenSlot = Arc<Mutex<Option<(CancelToken, TlsConnector)>>>;
+
+/// How long a cancelled task may clean up before it is dropped
+const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Runs the server task until it is done, the request timeout expires or Ctrl-C is pressed.
+/// A statement still running is cancelled on the server, so the task can roll back and finish
+async fn run_cancellable<T>(
+    task: impl Future<Output = Result<T, Error>>,
+    cancel_token_slot: CancelTokenSlot,
+    request_timeout: Option<Duration>,
+    mut cancel_rx: watch::Receiver<bool>,
+) -> (ServerOutcome, Option<T>) {
+    if *cancel_rx.borrow() {
+        return (ServerOutcome::Cancelled, None);
+    }
+    let timeout = async {
+        match request_timeout {
+            Some(request_timeout) => tokio::time::sleep(request_timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(task);
+    let outcome = tokio::select! {
+        result = &mut task => {
+            return match result {
+                Ok(value) => (ServerOutcome::Completed, Some(value)),
+                Err(_) => (ServerOutcome::Failed, None),
+            };
+        }
+        _ = timeout => ServerOutcome::TimedOut,
+        Ok(_) = cancel_rx.wait_for(|is_cancelled| *is_cancelled) => ServerOutcome::Cancelled,
+    };
+    let cancel_token = cancel_token_slot.lock().unwrap().take();
+    let Some((cancel_token, tls_connector)) = cancel_token else {
+        // not connected yet, nothing is running on the server
+        return (outcome, None);
+    };
+    let _ = cancel_token.cancel_query(tls_connector).await;
+    match tokio::time::timeout(CANCEL_GRACE_PERIOD, task).await {
+        // finished before the cancel arrived
+        Ok(Ok(value)) => (ServerOutcome::Completed, Some(value)),
+        _ => (outcome, None),
+    }
+}
+
+/// Waits for all server tasks, the first Ctrl-C cancels the request
+/// on every server still running it
+async fn join_server_tasks<T: 'static>(
+    mut set: JoinSet<(ServerOutcome, Option<T>)>,
+    server_names: HashMap<tokio::task::Id, String>,
+    cancel_tx: watch::Sender<bool>,
+    tx: &Sender<PrinterMessage>,
+) -> Vec<(String, ServerOutcome, Option<T>)> {
+    let _ctrl_c_guard = CtrlCGuard::new();
+    let mut results = Vec::new();
+    loop {
+        tokio::select! {
+            res = set.join_next_with_id() => match res {
+                Some(Ok((id, (outcome, value)))) => {
+                    results.push((server_names[&id].clone(), outcome, value));
+                }
+                Some(Err(e)) => {
+                    results.push((server_names[&e.id()].clone(), ServerOutcome::Failed, None));
+                }
+                None => break,
+            },
+            Ok(_) = tokio::signal::ctrl_c(), if !*cancel_tx.borrow() => {
+                let _ = cancel_tx.send(true);
+                let _ = tx
+                    .send(PrinterMessage::Text(format!(
+                        "\n{}\n",
+                        "CANCELLING REQUEST".yellow()
+                    )))
+                    .await;
+            }
+        }
+    }
+    results
+}
+
+/// Prints how the request ended on every server when it was cancelled or timed out
+async fn print_server_report<T>(
+    results: &[(String, ServerOutcome, Option<T>)],
+    tx: &Sender<PrinterMessage>,
+) {
+    let is_interrupted = results.iter().any(|(_, outcome, _)| {
+        matches!(outcome, ServerOutcome::Cancelled | ServerOutcome::TimedOut)
+    });
+    if !is_interrupted {
+        return;
+    }
+    let mut server_outcomes: Vec<(&String, &ServerOutcome)> = results
+        .iter()
+        .map(|(server_name, outcome, _)| (server_name, outcome))
+        .collect();
+    server_outcomes.sort_by_key(|(server_name, _)| *server_name);
+    let mut report = format!("\n{}\n", "SERVER REPORT".yellow());
+    for (server_name, outcome) in server_outcomes {
+        let outcome_text = match outcome {
+            ServerOutcome::Completed => outcome.to_string().green(),
+            ServerOutcome::Cancelled => outcome.to_string().yellow(),
+            ServerOutcome::Failed | ServerOutcome::TimedOut => outcome.to_string().red(),
+        };
+        report.push_str(&format!("[{}] {}\n", server_name, outcome_text));
+    }
+    if tx.send(PrinterMessage::Text(report)).await.is_err() {
         eprintln!("{}", "ERROR SENDING RESULT TO PRINTER THREAD".red());
     }
+}
 
-    Ok(rows.len() as u64)
+/// "<host>:<port>/<db>" of the server, the current DB wins over the inventory DB
+fn get_server_name(server: &Server, settings: &Arc<Mutex<HashMap<String, String>>>) -> String {
+    // this block for mutex release
+    let settings_lock = settings.lock().unwrap();
+    format!(
+        "{}:{}/{}",
+        server.get_display_host(),
+        server.port.unwrap_or_default(),
+        settings_lock
+            .get("current_db")
+            .map(String::as_str)
+            .or(server.db_name.as_deref())
+            .unwrap_or_default()
+    )
 }
 
-async fn process_command(
+fn get_request_timeout(settings: &Arc<Mutex<HashMap<String, String>>>) -> Option<Duration> {
+    // this block for mutex release
+    let settings_lock = settings.lock().unwrap();
+    settings_lock
+        .get("request_timeout_sec")
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|value| *value > 0)
+        .map(Duration::from_secs)
+}
+
+/// Runs the request batch by batch. The next batch starts only when every server
+/// of the previous batch succeeded and all servers are still online and consistent
+async fn process_rolling_request(
+    raw_command: &str,
+    request_type: &RequestType,
+    servers: Vec<Server>,
+    rolling_batch_size: usize,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+    session_manager: &Arc<SessionManager>,
+    tx: &Sender<PrinterMessage>,
+) -> RequestSummary {
+    // Ctrl-C between batches stops the request instead of ending taco
+    let _ctrl_c_guard = CtrlCGuard::new();
+    let batches: Vec<&[Server]> = servers.chunks(rolling_batch_size).collect();
+    let mut summary = RequestSummary::default();
+    let mut processed_servers = 0usize;
+    for (batch_index, batch) in batches.iter().enumerate() {
+        if batch_index > 0
+            && let Err(e) = tokio::select! {
+                result = check_servers_health(&servers, settings, session_manager) => result,
+                _ = tokio::signal::ctrl_c() => Err(anyhow::anyhow!("CANCELLED")),
+            }
+        {
+            let _ = tx
+                .send(PrinterMessage::Text(format!(
+                    "{}\n",
+                    format!("HEALTH CHECK FAILED: {}", e).red()
+                )))
+                .await;
+            break;
+        }
+        let hosts: Vec<String> = batch.iter().map(Server::get_display_host).collect();
+        let _ = tx
+            .send(PrinterMessage::Text(format!(
+                "\n{}\n",
+                format!(
+                    "BATCH {}/{}: {}",
+                    batch_index + 1,
+                    batches.len(),
+                    hosts.join(", ")
+                )
+                .yellow()
+            )))
+            .await;
+        let batch_summary = process_servers(
+            raw_command,
+            request_type,
+            batch.to_vec(),
+            settings,
+            session_manager,
+            tx,
+        )
+        .await;
+        summary.merge(&batch_summary);
+        processed_servers += batch.len();
+        if batch_summary.failed_servers > 0 {
+            break;
+        }
+    }
+    summary.skipped_servers = servers.len() - processed_servers;
+    if summary.skipped_servers > 0 {
+        let _ = tx
+            .send(PrinterMessage::Text(format!(
+                "\n{}\n",
+                format!(
+                    "ROLLING REQUEST STOPPED, {} SERVER(S) SKIPPED",
+                    summary.skipped_servers
+                )
+                .red()
+            )))
+            .await;
+    }
+    summary
+}
+
+/// Collects facts of the servers again, every server has to be online
+/// and consistent when the consistency check is on
+/// Servers of every cluster are checked on their own, a request can target several clusters
+async fn check_servers_health(
+    servers: &[Server],
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+    session_manager: &Arc<SessionManager>,
+) -> anyhow::Result<()> {
+    let check_cluster_consistency = {
+        // this block for mutex release
+        let settings_lock = settings.lock().unwrap();
+        settings_lock.get("check_cluster_consistency").map(String::as_str) == Some("true")
+    };
+    let mut cluster_servers: Vec<Vec<Server>> = Vec::new();
+    for server in servers {
+        match cluster_servers
+            .iter_mut()
+            .find(|cluster| cluster[0].cluster_name == server.cluster_name)
+        {
+            Some(cluster) => cluster.push(server.clone()),
+            None => cluster_servers.push(vec![server.clone()]),
+        }
+    }
+    for mut servers in cluster_servers {
+        let citus_db_name = servers[0].citus_db_name.clone();
+        let facts_collector = FactsCollector::new(settings, session_manager);
+        facts_collector.collect_facts(&mut servers, citus_db_name).await;
+        let offline_hosts: Vec<String> = servers
+            .iter()
+            .filter(|server| server.is_node_online != Some(true))
+            .map(Server::get_display_host)
+            .collect();
+        if !offline_hosts.is_empty() {
+            return Err(anyhow::anyhow!("OFFLINE SERVERS: {}", offline_hosts.join(", ")));
+        }
+        if check_cluster_consistency {
+            let mut consistency_checker = ClusterConsistencyChecker::new(settings);
+            if !consistency_checker.check_cluster_consistency(&mut servers) {
+                let inconsistent_hosts: Vec<String> = servers
+                    .iter()
+                    .filter(|server| server.is_node_consistent != Some(true))
+                    .map(Server::get_display_host)
+                    .collect();
+                return Err(anyhow::anyhow!(
+                    "INCONSISTENT SERVERS: {}",
+                    inconsistent_hosts.join(", ")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rolling batch size and parallel limit, None when the setting is off
+fn get_execution_limits(
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+) -> (Option<usize>, Option<usize>) {
+    // this block for mutex release
+    let settings_lock = settings.lock().unwrap();
+    let get_limit = |key: &str| {
+        settings_lock
+            .get(key)
+            .and_then(|value| value.parse::<usize>().ok())
+            .filter(|value| *value > 0)
+    };
+    (get_limit("rolling_batch_size"), get_limit("parallel"))
+}
+
+/// Handles "\rolling <N|off>", "\parallel <N|off>" and "\timeout <N|off>"
+fn process_limit_command(
+    preprocessed_command: &str,
+    setting_key: &str,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+) {
+    let parts_vec: Vec<&str> = preprocessed_command.split_whitespace().collect();
+    // this block for mutex release
+    let mut settings_lock = settings.lock().unwrap();
+    match parts_vec.get(1) {
+        Some(&"off") => {
+            settings_lock.remove(setting_key);
+        }
+        Some(value) if value.parse::<usize>().is_ok_and(|limit| limit > 0) => {
+            settings_lock.insert(setting_key.to_string(), value.to_string());
+        }
+        None => {}
+        _ => {
+            println!(
+                "{}",
+                format!(
+                    "{} COMMAND FORMAT: {} <N|off>",
+                    parts_vec[0].trim_start_matches('\\').to_uppercase(),
+                    parts_vec[0]
+                )
+                .yellow()
+            );
+            return;
+        }
+    }
+    let name = match setting_key {
+        "parallel" => "PARALLEL LIMIT",
+        "request_timeout_sec" => "REQUEST TIMEOUT SEC",
+        _ => "ROLLING BATCH SIZE",
+    };
+    match settings_lock.get(setting_key) {
+        Some(value) => println!("{}", format!("{} IS {}", name, value).yellow()),
+        None => println!("{}", format!("{} IS OFF", name).yellow()),
+    }
+}
+
+/// Redirect of the request wins over the out-dir setting
+fn get_output_writer(
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+    output_redirect: Option<OutputRedirect>,
+) -> OutputWriter {
+    // this block for mutex release
+    let settings_lock = settings.lock().unwrap();
+    let output_target = match (output_redirect, settings_lock.get("out_dir")) {
+        (Some(output_redirect), _) => OutputTarget::File {
+            path: PathBuf::from(output_redirect.path),
+            append: output_redirect.append,
+        },
+        (None, Some(out_dir)) => OutputTarget::Directory(PathBuf::from(out_dir)),
+        (None, None) => OutputTarget::Stdout,
+    };
+    let output_format = settings_lock
+        .get("output_format")
+        .and_then(|output_format| output_format.parse::<OutputFormat>().ok())
+        .unwrap_or(OutputFormat::Table);
+    let show_data_types = settings_lock
+        .get("show_data_types")
+        .map(|show_data_types| show_data_types != "false")
+        .unwrap_or(true);
+    OutputWriter::new(output_target, output_format, show_data_types)
+}
+
+fn get_result_comparer(settings: &Arc<Mutex<HashMap<String, String>>>) -> Option<ResultComparer> {
+    // this block for mutex release
+    let settings_lock = settings.lock().unwrap();
+    if settings_lock.get("compare_results").map(String::as_str) == Some("true") {
+        Some(ResultComparer::new())
+    } else {
+        None
+    }
+}
+
+fn print_result_group(group_index: usize, groups_count: usize, result_group: &ResultGroup) {
+    let header = format!(
+        "\nRESULT {} OF {} ({} SERVERS): {}",
+        group_index + 1,
+        groups_count,
+        result_group.servers.len(),
+        result_group.servers.join(", ")
+    );
+    if result_group.is_outlier {
+        println!("{} {}", header.red(), "OUTLIER".red().bold());
+    } else {
+        println!("{}", header.green());
+    }
+}
+
+fn print_compare_summary(result_groups: &[ResultGroup]) {
+    match result_groups.len() {
+        0 => println!("{}", "NO RESULTS TO COMPARE".yellow()),
+        1 => println!(
+            "{}",
+            format!(
+                "ALL {} SERVERS RETURNED THE SAME RESULT",
+                result_groups[0].servers.len()
+            )
+            .green()
+        ),
+        _ => {
+            let outliers: Vec<String> = result_groups
+                .iter()
+                .filter(|result_group| result_group.is_outlier)
+                .flat_map(|result_group| result_group.servers.clone())
+                .collect();
+            if outliers.is_empty() {
+                println!(
+                    "{}",
+                    format!("{} DISTINCT RESULTS, NO MAJORITY", result_groups.len()).red()
+                );
+            } else {
+                println!(
+                    "{}",
+                    format!(
+                        "{} DISTINCT RESULTS, OUTLIERS: {}",
+                        result_groups.len(),
+                        outliers.join(", ")
+                    )
+                    .red()
+                );
+            }
+        }
+    }
+}
+
+fn process_compare_command(
+    preprocessed_command: &str,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+) {
+    let parts_vec: Vec<&str> = preprocessed_command.split_whitespace().collect();
+    // this block for mutex release
+    let mut settings_lock = settings.lock().unwrap();
+    match parts_vec.get(1) {
+        Some(&"on") => {
+            settings_lock.insert("compare_results".to_string(), "true".to_string());
+        }
+        Some(&"off") => {
+            settings_lock.insert("compare_results".to_string(), "false".to_string());
+        }
+        None => {}
+        _ => {
+            println!("{}", "COMPARE COMMAND FORMAT: \\compare <on|off>".yellow());
+            return;
+        }
+    }
+    if settings_lock.get("compare_results").map(String::as_str) == Some("true") {
+        println!("{}", "COMPARE IS ON".yellow());
+    } else {
+        println!("{}", "COMPARE IS OFF".yellow());
+    }
+}
+
+/// Returns the merger and client-side ORDER BY when rows of all servers are merged
+fn get_merge_options(
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+) -> Option<(ResultMerger, Vec<OrderByColumn>)> {
+    // this block for mutex release
+    let settings_lock = settings.lock().unwrap();
+    if settings_lock.get("merge_results").map(String::as_str) != Some("true") {
+        return None;
+    }
+    let order_by = settings_lock
+        .get("merge_order_by")
+        .and_then(|order_by| parse_order_by(order_by).ok())
+        .unwrap_or_default();
+    Some((ResultMerger::new(), order_by))
+}
+
+fn process_merge_command(command: &str, settings: &Arc<Mutex<HashMap<String, String>>>) {
+    let argument = command.trim()["\\merge".len()..].trim();
+    let parts_vec: Vec<&str> = argument.split_whitespace().collect();
+    // this block for mutex release
+    let mut settings_lock = settings.lock().unwrap();
+    match parts_vec.as_slice() {
+        [] => {}
+        [on] if on.eq_ignore_ascii_case("on") => {
+            settings_lock.insert("merge_results".to_string(), "true".to_string());
+            settings_lock.remove("merge_order_by");
+        }
+        [off] if off.eq_ignore_ascii_case("off") => {
+            settings_lock.insert("merge_results".to_string(), "false".to_string());
+            settings_lock.remove("merge_order_by");
+        }
+        [order, by, ..]
+            if order.eq_ignore_ascii_case("order") && by.eq_ignore_ascii_case("by") =>
+        {
+            let order_by = argument[order.len()..].trim_start()[by.len()..].trim();
+            if let Err(e) = parse_order_by(order_by) {
+                println!("{}", e.to_string().red());
+                return;
+            }
+            settings_lock.insert("merge_results".to_string(), "true".to_string());
+            settings_lock.insert("merge_order_by".to_string(), order_by.to_string());
+        }
+        _ => {
+            println!(
+                "{}",
+                "MERGE COMMAND FORMAT: \\merge <on|off|order by <column> [asc|desc], ...>".yellow()
+            );
+            return;
+        }
+    }
+    match (
+        settings_lock.get("merge_results").map(String::as_str),
+        settings_lock.get("merge_order_by"),
+    ) {
+        (Some("true"), Some(order_by)) => {
+            println!("{}", format!("MERGE IS ON, ORDER BY <{}>", order_by).yellow())
+        }
+        (Some("true"), None) => println!("{}", "MERGE IS ON".yellow()),
+        _ => println!("{}", "MERGE IS OFF".yellow()),
+    }
+}
+
+fn process_out_dir_command(command: &str, settings: &Arc<Mutex<HashMap<String, String>>>) {
+    let out_dir = command.trim()["\\out-dir".len()..].trim();
+    // this block for mutex release
+    let mut settings_lock = settings.lock().unwrap();
+    if out_dir.is_empty() {
+        match settings_lock.get("out_dir") {
+            Some(out_dir) => println!("{}", format!("OUT DIR <{}>", out_dir).yellow()),
+            None => println!("{}", "OUT DIR IS OFF".yellow()),
+        }
+        return;
+    }
+    if out_dir.eq_ignore_ascii_case("off") {
+        settings_lock.remove("out_dir");
+        println!("{}", "OUT DIR IS OFF".yellow());
+        return;
+    }
+    settings_lock.insert("out_dir".to_string(), out_dir.to_string());
+    println!("{}", format!("OUT DIR <{}>", out_dir).yellow());
+}
+
+fn process_format_command(
+    preprocessed_command: &str,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+) -> bool {
+    let parts_vec: Vec<&str> = preprocessed_command.split_whitespace().collect();
+    if parts_vec.len() < 2usize {
+        println!(
+            "{}",
+            format!(
+                "FORMAT COMMAND FORMAT: \\format <{}>",
+                OutputFormat::NAMES.join("|")
+            )
+            .yellow()
+        );
+        return false;
+    }
+    match parts_vec[1].parse::<OutputFormat>() {
+        Ok(output_format) => {
+            println!("{}", format!("OUTPUT FORMAT <{}>", output_format).yellow());
+            // this block for mutex release
+            let mut settings_lock = settings.lock().unwrap();
+            settings_lock.insert("output_format".to_string(), output_format.to_string());
+            true
+        }
+        Err(e) => {
+            println!("{}", e.to_string().red());
+            false
+        }
+    }
+}
+
+async fn process_query(
     mut server: Server,
-    command: String,
+    query: String,
     settings: Arc<Mutex<HashMap<String, String>>>,
-    tx: Sender<String>,
+    session_manager: Arc<SessionManager>,
+    tx: Sender<PrinterMessage>,
+    cancel_token_slot: CancelTokenSlot,
 ) -> Result<u64, Error> {
     {
         // this block for mutex release
         let settings_lock = settings.lock().unwrap();
-        match settings_lock.get(&"current_db".to_string()) {
-            Some(db_name) => {
-                server.set_db_name(db_name.clone());
-            }
-            _ => {}
+        if let Some(db_name) = settings_lock.get("current_db") {
+            server.set_db_name(db_name.clone());
         }
     }
-
-    let connect_result = tokio_postgres::connect(&server.to_string(), NoTls).await;
+    let connect_result = session_manager.checkout(&server).await;
     if connect_result.as_ref().is_err() {
         let mut result = String::new();
         result.push_str(&format!(
             "\n[{}:{}] \n",
-            &server.host,
+            &server.get_display_host(),
             &server.db_name.unwrap()
         ));
-        result.push_str(&*connect_result.as_ref().err().unwrap().to_string());
-        result.push_str(&*"\n".to_string());
-        if tx.send(result.clone()).await.is_err() {
+        result.push_str(&get_error_message(connect_result.as_ref().err().unwrap()));
+        result.push('\n');
+        if tx.send(PrinterMessage::Text(result.clone())).await.is_err() {
             eprintln!("{}", result.red());
         }
-        return Ok(0u64);
+        return Err(connect_result.err().unwrap());
     }
 
-    let (client, connection) = connect_result.unwrap();
-    tokio::spawn(async move {
-        if connection.await.as_ref().is_err() {
-            eprintln!("{}", "ERROR OPEN CONNECTION".red());
+    let client = connect_result.unwrap();
+    *cancel_token_slot.lock().unwrap() =
+        Some((client.cancel_token(), TlsConnector::new(&server)));
+
+    let rows_result = match client.prepare(&query).await {
+        Ok(statement) => query_rendered_rows(&client, &query, &statement)
+            .await
+            .map(|rows| (statement, rows)),
+        Err(e) => Err(e),
+    };
+    session_manager.checkin(&server, client);
+    if rows_result.as_ref().is_err() {
+        let mut result = String::new();
+        result.push_str(&format!(
+            "\n[{}:{}] \n",
+            &server.get_display_host(),
+            &server.db_name.unwrap()
+        ));
+        result.push_str(&rows_result.as_ref().err().unwrap().to_string());
+        result.push('\n');
+        if tx.send(PrinterMessage::Text(result.clone())).await.is_err() {
+            eprintln!("{}", result.red());
         }
-    });
+        return Err(rows_result.err().unwrap());
+    }
 
-    let query = &command;
-    let statement_result = client.prepare(query).await;
-    if statement_result.as_ref().is_err() {
+    let (statement, rows) = rows_result.unwrap();
+    let query_result = QueryResult {
+        host: server.get_display_host(),
+        port: server.port.unwrap_or_default(),
+        db_name: server.db_name.clone().unwrap_or_default(),
+        columns: statement
+            .columns()
+            .iter()
+            .map(|column| QueryResultColumn {
+                name: column.name().to_string(),
+                type_name: column.type_().to_string(),
+            })
+            .collect(),
+        rows,
+    };
+    let rows_count = query_result.rows.len() as u64;
+    if tx
+        .send(PrinterMessage::QueryResult(query_result))
+        .await
+        .as_ref()
+        .is_err()
+    {
+        eprintln!("{}", "ERROR SENDING RESULT TO PRINTER THREAD".red());
+    }
+
+    Ok(rows_count)
+}
+
+/// Rows of the prepared query rendered as text. Results with types which are not rendered
+/// from the binary format are fetched in text format with the simple query protocol
+async fn query_rendered_rows(
+    client: &Client,
+    query: &str,
+    statement: &Statement,
+) -> Result<Vec<Vec<Option<String>>>, Error> {
+    if statement
+        .columns()
+        .iter()
+        .all(|column| is_rendered_from_binary(column.type_()))
+    {
+        let rows = client.query(statement, &[]).await?;
+        return Ok(rows
+            .iter()
+            .map(|row| {
+                (0..row.len())
+                    .map(|col_index| render_value(row, col_index))
+                    .collect()
+            })
+            .collect());
+    }
+    let messages = client.simple_query(query).await?;
+    Ok(messages
+        .iter()
+        .filter_map(|message| match message {
+            SimpleQueryMessage::Row(row) => Some(
+                (0..row.len())
+                    .map(|col_index| row.get(col_index).map(str::to_string))
+                    .collect(),
+            ),
+            _ => None,
+        })
+        .collect())
+}
+
+async fn process_command(
+    mut server: Server,
+    command: String,
+    settings: Arc<Mutex<HashMap<String, String>>>,
+    session_manager: Arc<SessionManager>,
+    tx: Sender<PrinterMessage>,
+    cancel_token_slot: CancelTokenSlot,
+) -> Result<u64, Error> {
+    {
+        // this block for mutex release
+        let settings_lock = settings.lock().unwrap();
+        if let Some(db_name) = settings_lock.get("current_db") {
+            server.set_db_name(db_name.clone());
+        }
+    }
+
+    let connect_result = session_manager.checkout(&server).await;
+    if connect_result.as_ref().is_err() {
         let mut result = String::new();
         result.push_str(&format!(
             "\n[{}:{}] \n",
-            &server.host,
+            &server.get_display_host(),
             &server.db_name.unwrap()
         ));
-        result.push_str(&*statement_result.as_ref().err().unwrap().to_string());
-        result.push_str(&*"\n".to_string());
-        if tx.send(result.clone()).await.is_err() {
+        result.push_str(&get_error_message(connect_result.as_ref().err().unwrap()));
+        result.push('\n');
+        if tx.send(PrinterMessage::Text(result.clone())).await.is_err() {
             eprintln!("{}", result.red());
         }
-        return Ok(0u64);
+        return Err(connect_result.err().unwrap());
     }
-    let statement = statement_result.unwrap();
-    let rows_result = client.execute(&statement, &[]).await;
+
+    let client = connect_result.unwrap();
+    *cancel_token_slot.lock().unwrap() =
+        Some((client.cancel_token(), TlsConnector::new(&server)));
+
+    let query = &command;
+    let rows_result = match client.prepare(query).await {
+        Ok(statement) => client.execute(&statement, &[]).await,
+        Err(e) => Err(e),
+    };
+    session_manager.checkin(&server, client);
     if rows_result.as_ref().is_err() {
         let mut result = String::new();
         result.push_str(&format!(
             "\n[{}:{}] \n",
-            &server.host,
+            &server.get_display_host(),
             &server.db_name.unwrap()
         ));
-        result.push_str(&*rows_result.as_ref().err().unwrap().to_string());
-        result.push_str(&*"\n".to_string());
-        if tx.send(result.clone()).await.is_err() {
+        result.push_str(&rows_result.as_ref().err().unwrap().to_string());
+        result.push('\n');
+        if tx.send(PrinterMessage::Text(result.clone())).await.is_err() {
             eprintln!("{}", result.red());
         }
-        return Ok(0u64);
+        return Err(rows_result.err().unwrap());
     }
 
     let rows = rows_result.unwrap();
@@ -903,49 +2474,460 @@ async fn process_command(
     let mut result = String::new();
     result.push_str(&format!(
         "\n[{}:{}]: rows {}\n",
-        &server.host,
+        &server.get_display_host(),
         &server.db_name.unwrap(),
         rows
     ));
-    if tx.send(result).await.as_ref().is_err() {
+    if tx.send(PrinterMessage::Text(result)).await.as_ref().is_err() {
         eprintln!("{}", "ERROR SENDING RESULT TO PRINTER THREAD".red());
     }
 
     Ok(rows)
 }
 
-async fn process_macro(
-    mut server: Server,
+struct PreparedTransaction {
+    server: Server,
+    gid: String,
+    client: Client,
+    rows: u64,
+}
+
+/// Runs the command in a prepared transaction on every server and commits it
+/// only when every server prepared it, otherwise rolls it back everywhere
+async fn process_atomic_command(
     command: String,
+    servers: Vec<Server>,
     settings: Arc<Mutex<HashMap<String, String>>>,
-    tx: Sender<String>,
-) -> Result<u64, Error> {
-    Ok(0u64)
+    session_manager: Arc<SessionManager>,
+    tx: Sender<PrinterMessage>,
+) -> RequestSummary {
+    let gid = format!("taco_{}", Uuid::new_v4().simple());
+    let mut summary = RequestSummary::default();
+    let request_timeout = get_request_timeout(&settings);
+    let (cancel_tx, cancel_rx) = watch::channel(false);
+    let preparing_transactions = PreparingTransactions::default();
+
+    let mut set = JoinSet::new();
+    let mut server_names = HashMap::new();
+    let current_db = {
+        // this block for mutex release
+        let settings_lock = settings.lock().unwrap();
+        settings_lock.get("current_db").cloned()
+    };
+    let mut target_servers: Vec<(String, Server)> = Vec::new();
+    for (server_index, mut server) in servers.into_iter().enumerate() {
+        let server_name = get_server_name(&server, &settings);
+        if let Some(db_name) = &current_db {
+            server.set_db_name(db_name.clone());
+        }
+        let instance_name = format!(
+            "{}:{}",
+            server.get_display_host(),
+            server.port.unwrap_or_default()
+        );
+        target_servers.push((instance_name, server.clone()));
+        let command_clone = command.clone();
+        // servers of a group can share a PostgreSQL instance, gids are unique per instance
+        let gid_clone = format!("{}_{}", gid, server_index);
+        let session_manager_clone = session_manager.clone();
+        let preparing_transactions_clone = preparing_transactions.clone();
+        let cancel_rx_clone = cancel_rx.clone();
+        let tx_clone = tx.clone();
+        let abort_handle = set.spawn(async move {
+            let cancel_token_slot = CancelTokenSlot::default();
+            let task = prepare_transaction(
+                server,
+                command_clone,
+                gid_clone,
+                session_manager_clone,
+                tx_clone,
+                cancel_token_slot.clone(),
+                preparing_transactions_clone,
+            );
+            run_cancellable(task, cancel_token_slot, request_timeout, cancel_rx_clone).await
+        });
+        server_names.insert(abort_handle.id(), server_name);
+    }
+    // Ctrl-C and the timeout only cancel the prepare phase, the rest are rolled back
+    let results = join_server_tasks(set, server_names, cancel_tx, &tx).await;
+    print_server_report(&results, &tx).await;
+    let mut prepared_transactions: Vec<PreparedTransaction> = Vec::new();
+    for (_, _, prepared_transaction) in results {
+        match prepared_transaction {
+            Some(prepared_transaction) => prepared_transactions.push(prepared_transaction),
+            None => summary.failed_servers += 1,
+        }
+    }
+    // a task dropped after a cancel or a timeout can leave its transaction prepared
+    let orphaned_transactions: Vec<(Server, String)> =
+        std::mem::take(&mut *preparing_transactions.lock().unwrap())
+            .into_iter()
+            .filter(|(_, gid)| {
+                !prepared_transactions
+                    .iter()
+                    .any(|prepared_transaction| &prepared_transaction.gid == gid)
+            })
+            .collect();
+    let mut set = JoinSet::new();
+    for (server, gid) in orphaned_transactions {
+        let tx_clone = tx.clone();
+        set.spawn(async move { rollback_orphaned_transaction(server, gid, tx_clone).await });
+    }
+    set.join_all().await;
+
+    let is_commit = summary.failed_servers == 0;
+    let mut set = JoinSet::new();
+    for prepared_transaction in prepared_transactions {
+        let session_manager_clone = session_manager.clone();
+        let tx_clone = tx.clone();
+        set.spawn(async move {
+            finish_transaction(prepared_transaction, is_commit, session_manager_clone, tx_clone)
+                .await
+        });
+    }
+    while let Some(res) = set.join_next().await {
+        match res {
+            Ok(Ok(rows)) => summary.total_rows += rows,
+            _ => summary.failed_servers += 1,
+        }
+    }
+    report_prepared_transactions(&gid, &target_servers, &session_manager, &tx).await;
+
+    let result = if is_commit && summary.failed_servers == 0 {
+        format!("\n{}\n", format!("TRANSACTION {} COMMITTED ON ALL SERVERS", gid).green())
+    } else if is_commit {
+        format!(
+            "\n{}\n",
+            format!("TRANSACTION {} IS NOT COMMITTED ON ALL SERVERS", gid).red()
+        )
+    } else {
+        format!("\n{}\n", format!("TRANSACTION {} ROLLED BACK", gid).red())
+    };
+    if tx.send(PrinterMessage::Text(result)).await.is_err() {
+        eprintln!("{}", "ERROR SENDING RESULT TO PRINTER THREAD".red());
+    }
+    summary
 }
 
-struct IntervalWrapper {}
+/// Servers and gids of transactions which reached PREPARE TRANSACTION
+type PreparingTransactions = Arc<Mutex<Vec<(Server, String)>>>;
+
+/// Runs BEGIN, the command and PREPARE TRANSACTION, the transaction is rolled back on errors.
+/// The current DB is already set on the server
+async fn prepare_transaction(
+    server: Server,
+    command: String,
+    gid: String,
+    session_manager: Arc<SessionManager>,
+    tx: Sender<PrinterMessage>,
+    cancel_token_slot: CancelTokenSlot,
+    preparing_transactions: PreparingTransactions,
+) -> Result<PreparedTransaction, Error> {
+    let server_name = format!(
+        "{}:{}",
+        server.get_display_host(),
+        server.db_name.clone().unwrap_or_default()
+    );
+
+    let client = match session_manager.checkout(&server).await {
+        Ok(client) => client,
+        Err(e) => {
+            let result = format!("\n[{}] \n{}\n", server_name, get_error_message(&e));
+            if tx.send(PrinterMessage::Text(result.clone())).await.is_err() {
+                eprintln!("{}", result.red());
+            }
+            return Err(e);
+        }
+    };
+    *cancel_token_slot.lock().unwrap() =
+        Some((client.cancel_token(), TlsConnector::new(&server)));
+
+    let prepare_result = async {
+        client.batch_execute("BEGIN").await?;
+        let statement = client.prepare(&command).await?;
+        let rows = client.execute(&statement, &[]).await?;
+        preparing_transactions
+            .lock()
+            .unwrap()
+            .push((server.clone(), gid.clone()));
+        client
+            .batch_execute(&format!("PREPARE TRANSACTION '{}'", gid))
+            .await?;
+        Ok::<u64, Error>(rows)
+    }
+    .await;
 
-impl<'a> FromSql<'a> for IntervalWrapper {
-    fn from_sql(
-        ty: &Type,
-        raw: &'a [u8],
-    ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
-        match *ty {
-            Type::INTERVAL => {
-                let _str_value = std::str::from_utf8(raw)?;
-                // Months: A 32-bit integer representing the number of months in the interval. This part accounts for the year and month components of the interval, where each year is considered to be 12 months.
-                // Days: A 32-bit integer representing the number of days in the interval. This part is separate from the months and directly represents the days component of the interval.
-                // Microseconds: A 64-bit integer representing the time of day component in microseconds. This allows for a precise representation of hours, minutes, seconds, and even fractions of a second within the interval.
-                Ok(IntervalWrapper {})
+    match prepare_result {
+        Ok(rows) => {
+            let result = format!("\n[{}]: prepared, rows {}\n", server_name, rows);
+            if tx.send(PrinterMessage::Text(result)).await.is_err() {
+                eprintln!("{}", "ERROR SENDING RESULT TO PRINTER THREAD".red());
+            }
+            Ok(PreparedTransaction {
+                server,
+                gid,
+                client,
+                rows,
+            })
+        }
+        Err(e) => {
+            // a failed PREPARE TRANSACTION leaves nothing prepared
+            preparing_transactions
+                .lock()
+                .unwrap()
+                .retain(|(_, other)| *other != gid);
+            let _ = client.batch_execute("ROLLBACK").await;
+            session_manager.checkin(&server, client);
+            let result = format!("\n[{}] \n{}\n", server_name, get_error_message(&e));
+            if tx.send(PrinterMessage::Text(result.clone())).await.is_err() {
+                eprintln!("{}", result.red());
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Commits or rolls back the prepared transaction
+async fn finish_transaction(
+    prepared_transaction: PreparedTransaction,
+    is_commit: bool,
+    session_manager: Arc<SessionManager>,
+    tx: Sender<PrinterMessage>,
+) -> Result<u64, Error> {
+    let server = &prepared_transaction.server;
+    let client = &prepared_transaction.client;
+    let server_name = format!(
+        "{}:{}",
+        server.get_display_host(),
+        server.db_name.clone().unwrap_or_default()
+    );
+
+    let finish_command = if is_commit {
+        format!("COMMIT PREPARED '{}'", prepared_transaction.gid)
+    } else {
+        format!("ROLLBACK PREPARED '{}'", prepared_transaction.gid)
+    };
+    let finish_result = client.batch_execute(&finish_command).await;
+    let result = match &finish_result {
+        Ok(_) if is_commit => format!(
+            "\n[{}]: committed, rows {}\n",
+            server_name, prepared_transaction.rows
+        ),
+        Ok(_) => format!("\n[{}]: rolled back\n", server_name),
+        Err(e) => format!("\n[{}] \n{}\n", server_name, get_error_message(e)),
+    };
+    if tx.send(PrinterMessage::Text(result)).await.is_err() {
+        eprintln!("{}", "ERROR SENDING RESULT TO PRINTER THREAD".red());
+    }
+
+    let rows = if is_commit { prepared_transaction.rows } else { 0 };
+    session_manager.checkin(&prepared_transaction.server, prepared_transaction.client);
+    finish_result.map(|_| rows)
+}
+
+/// Reports prepared transactions still left on the servers, also on servers which did not
+/// prepare. Transactions of this request are shown apart from the ones of other taco
+/// sessions, which can still be running, and from others, like the ones of Citus
+async fn report_prepared_transactions(
+    gid: &str,
+    servers: &[(String, Server)],
+    session_manager: &Arc<SessionManager>,
+    tx: &Sender<PrinterMessage>,
+) {
+    // pg_prepared_xacts has transactions of every database of the instance
+    let mut instances: Vec<&(String, Server)> = Vec::new();
+    for target in servers {
+        let (_, server) = target;
+        if !instances
+            .iter()
+            .any(|(_, other)| other.host == server.host && other.port == server.port)
+        {
+            instances.push(target);
+        }
+    }
+    let mut set = JoinSet::new();
+    for (server_name, server) in instances {
+        let server_name = server_name.clone();
+        let server = server.clone();
+        let session_manager = session_manager.clone();
+        set.spawn(async move {
+            let rows = async {
+                let client = session_manager.checkout(&server).await?;
+                let rows = client
+                    .query(
+                        "SELECT gid, database, owner, prepared::text, \
+                        date_trunc('second', now() - prepared)::text \
+                        FROM pg_prepared_xacts ORDER BY prepared",
+                        &[],
+                    )
+                    .await;
+                session_manager.checkin(&server, client);
+                rows
+            }
+            .await;
+            (server_name, rows)
+        });
+    }
+    let request_prefix = format!("{}_", gid);
+    let mut report = String::new();
+    for (server_name, rows) in set.join_all().await {
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(e) => {
+                let message = format!(
+                    "CAN NOT CHECK PREPARED TRANSACTIONS ON [{}]: {}",
+                    server_name,
+                    get_error_message(&e)
+                );
+                report.push_str(&format!("\n{}\n", message.red()));
+                continue;
             }
-            _ => Err("Unsupported type")?,
+        };
+        let get_kind = |transaction_gid: &str| {
+            if transaction_gid.starts_with(&request_prefix) {
+                0
+            } else if transaction_gid.starts_with("taco_") {
+                1
+            } else {
+                2
+            }
+        };
+        for (kind, title) in [
+            (0, "LEFTOVER PREPARED TRANSACTIONS OF THIS REQUEST ON"),
+            (1, "PREPARED TRANSACTIONS OF OTHER TACO SESSIONS ON"),
+            (2, "OTHER PREPARED TRANSACTIONS ON"),
+        ] {
+            let transactions: Vec<String> = rows
+                .iter()
+                .filter(|row| get_kind(row.get(0)) == kind)
+                .map(|row| {
+                    format!(
+                        "  {} (database {}, owner {}, prepared at {}, age {})\n",
+                        row.get::<_, String>(0),
+                        row.get::<_, String>(1),
+                        row.get::<_, String>(2),
+                        row.get::<_, String>(3),
+                        row.get::<_, String>(4)
+                    )
+                })
+                .collect();
+            if transactions.is_empty() {
+                continue;
+            }
+            let title = format!("{} [{}]:", title, server_name);
+            let title = if kind == 0 { title.red() } else { title.yellow() };
+            report.push_str(&format!("\n{}\n{}", title, transactions.concat()));
+        }
+    }
+    if !report.is_empty() && tx.send(PrinterMessage::Text(report)).await.is_err() {
+        eprintln!("{}", "ERROR SENDING RESULT TO PRINTER THREAD".red());
+    }
+}
+
+/// Rolls back the transaction if it was prepared, on a new connection because the session
+/// of the dropped task is gone
+async fn rollback_orphaned_transaction(server: Server, gid: String, tx: Sender<PrinterMessage>) {
+    let server_name = format!(
+        "{}:{}",
+        server.get_display_host(),
+        server.db_name.clone().unwrap_or_default()
+    );
+    let rollback_result = async {
+        let (client, connection) =
+            tokio_postgres::connect(&server.get_connection_string(), TlsConnector::new(&server))
+                .await?;
+        tokio::spawn(connection);
+        let rows = client
+            .query("SELECT 1 FROM pg_prepared_xacts WHERE gid = $1", &[&gid])
+            .await?;
+        if rows.is_empty() {
+            return Ok(false);
         }
+        client
+            .batch_execute(&format!("ROLLBACK PREPARED '{}'", gid))
+            .await?;
+        Ok::<bool, Error>(true)
     }
+    .await;
+    let result = match rollback_result {
+        Ok(false) => return,
+        Ok(true) => format!(
+            "\n{}\n",
+            format!(
+                "[{}]: orphaned prepared transaction {} rolled back",
+                server_name, gid
+            )
+            .yellow()
+        ),
+        Err(e) => format!(
+            "\n{}\n",
+            format!(
+                "[{}]: CAN NOT ROLL BACK PREPARED TRANSACTION {}: {}",
+                server_name,
+                gid,
+                get_error_message(&e)
+            )
+            .red()
+        ),
+    };
+    if tx.send(PrinterMessage::Text(result)).await.is_err() {
+        eprintln!("{}", "ERROR SENDING RESULT TO PRINTER THREAD".red());
+    }
+}
 
-    fn accepts(ty: &Type) -> bool {
-        *ty == Type::INTERVAL
+/// Server errors show their message instead of just "db error"
+/// Connection errors keep the reason, like a failed certificate check, in the source
+fn get_error_message(error: &Error) -> String {
+    match (error.as_db_error(), std::error::Error::source(error)) {
+        (Some(db_error), _) => db_error.to_string(),
+        (None, Some(source)) => format!("{}: {}", error, source),
+        (None, None) => error.to_string(),
     }
 }
 
+fn is_atomic_mode(settings: &Arc<Mutex<HashMap<String, String>>>) -> bool {
+    // this block for mutex release
+    let settings_lock = settings.lock().unwrap();
+    settings_lock.get("atomic_commands").map(String::as_str) == Some("true")
+}
+
+fn process_atomic_command_setting(
+    preprocessed_command: &str,
+    settings: &Arc<Mutex<HashMap<String, String>>>,
+) {
+    let parts_vec: Vec<&str> = preprocessed_command.split_whitespace().collect();
+    // this block for mutex release
+    let mut settings_lock = settings.lock().unwrap();
+    match parts_vec.get(1) {
+        Some(&"on") => {
+            settings_lock.insert("atomic_commands".to_string(), "true".to_string());
+        }
+        Some(&"off") => {
+            settings_lock.insert("atomic_commands".to_string(), "false".to_string());
+        }
+        None => {}
+        _ => {
+            println!("{}", "ATOMIC COMMAND FORMAT: \\atomic <on|off>".yellow());
+            return;
+        }
+    }
+    if settings_lock.get("atomic_commands").map(String::as_str) == Some("true") {
+        println!("{}", "ATOMIC COMMANDS ARE ON".yellow());
+    } else {
+        println!("{}", "ATOMIC COMMANDS ARE OFF".yellow());
+    }
+}
+
+async fn process_macro(
+    mut server: Server,
+    command: String,
+    settings: Arc<Mutex<HashMap<String, String>>>,
+    _session_manager: Arc<SessionManager>,
+    tx: Sender<PrinterMessage>,
+    _cancel_token_slot: CancelTokenSlot,
+) -> Result<u64, Error> {
+    Ok(0u64)
+}
+
 #[tokio::test]
 async fn test_query_data_types() {}
@@ -0,0 +1,4 @@
+pub mod output_formatter;
+pub mod output_writer;
+pub mod result_comparer;
+pub mod result_merger;
\ No newline at end of file
@@ -0,0 +1,453 @@
+use crate::shared::query_result::QueryResult;
+use prettytable::{Cell, Row, Table};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::str::FromStr;
+
+const NULL_VALUE: &str = "NULL";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Table,
+    Csv,
+    Tsv,
+    Json,
+    Jsonl,
+    Markdown,
+    Expanded,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 7] = [
+        "table", "csv", "tsv", "json", "jsonl", "markdown", "expanded",
+    ];
+
+    /// Output format of a redirect file, only these extensions are treated as files
+    pub fn from_extension(extension: &str) -> Option<OutputFormat> {
+        match extension.to_lowercase().as_str() {
+            "txt" => Some(OutputFormat::Table),
+            "csv" => Some(OutputFormat::Csv),
+            "tsv" => Some(OutputFormat::Tsv),
+            "json" => Some(OutputFormat::Json),
+            "jsonl" | "ndjson" => Some(OutputFormat::Jsonl),
+            "md" => Some(OutputFormat::Markdown),
+            _ => None,
+        }
+    }
+
+    pub fn get_extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Table | OutputFormat::Expanded => "txt",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Markdown => "md",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            "expanded" => Ok(OutputFormat::Expanded),
+            _ => Err(anyhow::anyhow!(
+                "UNKNOWN OUTPUT FORMAT <{}>, EXPECTED ONE OF {}",
+                value,
+                OutputFormat::NAMES.join("|")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OutputFormat::Table => "table",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Markdown => "markdown",
+            OutputFormat::Expanded => "expanded",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Renders query results of every server in the selected format.
+/// Header lines of csv, tsv and markdown are written only when columns change,
+/// json rows are collected and written as one array by finish()
+pub struct OutputFormatter {
+    output_format: OutputFormat,
+    show_data_types: bool,
+    last_header: Option<Vec<String>>,
+    /// The output already has a header, the first one is not written again
+    is_header_written: bool,
+    json_rows: Vec<Value>,
+    has_results: bool,
+}
+
+impl OutputFormatter {
+    pub fn new(output_format: OutputFormat, show_data_types: bool) -> Self {
+        Self {
+            output_format,
+            show_data_types,
+            last_header: None,
+            is_header_written: false,
+            json_rows: Vec::new(),
+            has_results: false,
+        }
+    }
+
+    /// Output appended to a file which has a header already
+    pub fn skip_first_header(&mut self) {
+        self.is_header_written = true;
+    }
+
+    pub fn render(&mut self, result: &QueryResult) -> String {
+        self.render_result(result, true)
+    }
+
+    /// Renders rows of many servers merged into one result,
+    /// host, port and db are already leading columns of the result
+    pub fn render_merged(&mut self, result: &QueryResult) -> String {
+        self.render_result(result, false)
+    }
+
+    fn render_result(&mut self, result: &QueryResult, with_source: bool) -> String {
+        self.has_results = true;
+        match self.output_format {
+            OutputFormat::Table => self.render_table(result, with_source),
+            OutputFormat::Expanded => self.render_expanded(result, with_source),
+            OutputFormat::Csv => self.render_delimited(result, with_source, ','),
+            OutputFormat::Tsv => self.render_delimited(result, with_source, '\t'),
+            OutputFormat::Markdown => self.render_markdown(result, with_source),
+            OutputFormat::Json => {
+                self.json_rows
+                    .extend(Self::get_json_rows(result, with_source));
+                String::new()
+            }
+            OutputFormat::Jsonl => Self::get_json_rows(result, with_source)
+                .iter()
+                .map(|row| format!("{}\n", row))
+                .collect(),
+        }
+    }
+
+    pub fn finish(&mut self) -> String {
+        if self.output_format == OutputFormat::Json && self.has_results {
+            let json_rows = Value::Array(std::mem::take(&mut self.json_rows));
+            return format!(
+                "{}\n",
+                serde_json::to_string_pretty(&json_rows).unwrap_or_default()
+            );
+        }
+        String::new()
+    }
+
+    fn get_column_headers(&self, result: &QueryResult) -> Vec<String> {
+        result
+            .columns
+            .iter()
+            .map(|column| {
+                if self.show_data_types {
+                    format!("{}:{}", column.name, column.type_name)
+                } else {
+                    column.name.clone()
+                }
+            })
+            .collect()
+    }
+
+    fn get_title(result: &QueryResult, with_source: bool) -> String {
+        if with_source {
+            format!("\n[{}:{}] \n", result.host, result.db_name)
+        } else {
+            "\n".to_string()
+        }
+    }
+
+    /// host and db values written in front of every row
+    fn get_source_values(result: &QueryResult, with_source: bool) -> Vec<(String, String)> {
+        if !with_source {
+            return Vec::new();
+        }
+        vec![
+            ("host".to_string(), result.host.clone()),
+            ("db".to_string(), result.db_name.clone()),
+        ]
+    }
+
+    fn render_table(&self, result: &QueryResult, with_source: bool) -> String {
+        let mut table = Table::new();
+        let mut header: Vec<Cell> = vec![Cell::new("")];
+        header.extend(
+            self.get_column_headers(result)
+                .iter()
+                .map(|column_header| Cell::new(column_header)),
+        );
+        table.add_row(Row::new(header));
+        for (row_index, row) in result.rows.iter().enumerate() {
+            let mut cells: Vec<Cell> = vec![Cell::new(&row_index.to_string())];
+            cells.extend(
+                row.iter()
+                    .map(|value| Cell::new(value.as_deref().unwrap_or(NULL_VALUE))),
+            );
+            table.add_row(Row::new(cells));
+        }
+        format!("{}{}\n", Self::get_title(result, with_source), table)
+    }
+
+    fn render_expanded(&self, result: &QueryResult, with_source: bool) -> String {
+        let column_headers = self.get_column_headers(result);
+        let name_width = column_headers
+            .iter()
+            .map(|column_header| column_header.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut output = Self::get_title(result, with_source);
+        if result.rows.is_empty() {
+            output.push_str("(0 rows)\n");
+        }
+        for (row_index, row) in result.rows.iter().enumerate() {
+            let record_header = format!("-[ RECORD {} ]", row_index + 1);
+            output.push_str(&format!(
+                "{}{}\n",
+                record_header,
+                "-".repeat((name_width + 3).saturating_sub(record_header.len()))
+            ));
+            for (column_header, value) in column_headers.iter().zip(row) {
+                output.push_str(&format!(
+                    "{:width$} | {}\n",
+                    column_header,
+                    value.as_deref().unwrap_or(NULL_VALUE),
+                    width = name_width
+                ));
+            }
+        }
+        output
+    }
+
+    fn render_delimited(
+        &mut self,
+        result: &QueryResult,
+        with_source: bool,
+        delimiter: char,
+    ) -> String {
+        let mut output = String::new();
+        let source_values = Self::get_source_values(result, with_source);
+        let mut header: Vec<String> = source_values.iter().map(|(name, _)| name.clone()).collect();
+        header.extend(result.columns.iter().map(|column| column.name.clone()));
+        if self.last_header.as_ref() != Some(&header) && !self.is_header_written {
+            let escaped_header: Vec<String> = header
+                .iter()
+                .map(|value| Self::escape_delimited(value, delimiter))
+                .collect();
+            output.push_str(&escaped_header.join(&delimiter.to_string()));
+            output.push('\n');
+        }
+        self.is_header_written = false;
+        self.last_header = Some(header);
+        for row in &result.rows {
+            let mut values: Vec<String> = source_values
+                .iter()
+                .map(|(_, value)| Self::escape_delimited(value, delimiter))
+                .collect();
+            values
+                .extend(row.iter().map(|value| {
+                    Self::escape_delimited(value.as_deref().unwrap_or(""), delimiter)
+                }));
+            output.push_str(&values.join(&delimiter.to_string()));
+            output.push('\n');
+        }
+        output
+    }
+
+    /// csv values are quoted (RFC 4180), tsv values use backslash escapes
+    fn escape_delimited(value: &str, delimiter: char) -> String {
+        if delimiter == '\t' {
+            return value
+                .replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r");
+        }
+        if value.contains([delimiter, '"', '\n', '\r'])
+            || value.starts_with(' ')
+            || value.ends_with(' ')
+        {
+            return format!("\"{}\"", value.replace('"', "\"\""));
+        }
+        value.to_string()
+    }
+
+    fn render_markdown(&mut self, result: &QueryResult, with_source: bool) -> String {
+        let mut output = String::new();
+        let source_values = Self::get_source_values(result, with_source);
+        let mut header: Vec<String> = source_values.iter().map(|(name, _)| name.clone()).collect();
+        header.extend(self.get_column_headers(result));
+        if self.last_header.as_ref() != Some(&header) && !self.is_header_written {
+            let escaped_header: Vec<String> = header
+                .iter()
+                .map(|value| Self::escape_markdown(value))
+                .collect();
+            output.push_str(&format!("\n| {} |\n", escaped_header.join(" | ")));
+            output.push_str(&format!("|{}\n", " --- |".repeat(header.len())));
+        }
+        self.is_header_written = false;
+        self.last_header = Some(header);
+        for row in &result.rows {
+            let mut values: Vec<String> = source_values
+                .iter()
+                .map(|(_, value)| Self::escape_markdown(value))
+                .collect();
+            values.extend(
+                row.iter()
+                    .map(|value| Self::escape_markdown(value.as_deref().unwrap_or(NULL_VALUE))),
+            );
+            output.push_str(&format!("| {} |\n", values.join(" | ")));
+        }
+        output
+    }
+
+    fn escape_markdown(value: &str) -> String {
+        value
+            .replace('|', "\\|")
+            .replace("\r\n", "<br>")
+            .replace('\n', "<br>")
+    }
+
+    /// Source keys are prefixed with "_", so columns named host or db do not overwrite them
+    fn get_json_rows(result: &QueryResult, with_source: bool) -> Vec<Value> {
+        let source_values = Self::get_source_values(result, with_source);
+        result
+            .rows
+            .iter()
+            .map(|row| {
+                let mut object = Map::new();
+                for (name, value) in &source_values {
+                    object.insert(format!("_{}", name), Value::String(value.clone()));
+                }
+                for (column, value) in result.columns.iter().zip(row) {
+                    object.insert(
+                        column.name.clone(),
+                        value.clone().map(Value::String).unwrap_or(Value::Null),
+                    );
+                }
+                Value::Object(object)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::query_result::get_test_result;
+
+    #[test]
+    fn test_render_csv() {
+        let result = get_test_result(
+            "10.0.0.1",
+            &["name", "note"],
+            vec![
+                vec![Some("a,b"), Some("say \"hi\"")],
+                vec![Some(" padded"), Some("line 1\nline 2")],
+                vec![Some("plain"), None],
+            ],
+        );
+        let mut output_formatter = OutputFormatter::new(OutputFormat::Csv, false);
+        assert_eq!(
+            output_formatter.render(&result),
+            "host,db,name,note\n\
+            10.0.0.1,postgres,\"a,b\",\"say \"\"hi\"\"\"\n\
+            10.0.0.1,postgres,\" padded\",\"line 1\nline 2\"\n\
+            10.0.0.1,postgres,plain,\n"
+        );
+        // the header is written again only when columns change
+        assert_eq!(
+            output_formatter.render(&get_test_result("10.0.0.1", &["name", "note"], vec![])),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_render_tsv() {
+        let result = get_test_result(
+            "10.0.0.1",
+            &["value"],
+            vec![vec![Some("a\tb\\c\r\nd")], vec![None]],
+        );
+        let mut output_formatter = OutputFormatter::new(OutputFormat::Tsv, false);
+        assert_eq!(
+            output_formatter.render_merged(&result),
+            "value\na\\tb\\\\c\\r\\nd\n\n"
+        );
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let result = get_test_result(
+            "10.0.0.1",
+            &["value"],
+            vec![vec![Some("a|b\r\nc\nd")], vec![None]],
+        );
+        let mut output_formatter = OutputFormatter::new(OutputFormat::Markdown, true);
+        assert_eq!(
+            output_formatter.render(&result),
+            "\n| host | db | value:text |\n| --- | --- | --- |\n\
+            | 10.0.0.1 | postgres | a\\|b<br>c<br>d |\n\
+            | 10.0.0.1 | postgres | NULL |\n"
+        );
+    }
+
+    #[test]
+    fn test_render_expanded() {
+        let result = get_test_result(
+            "10.0.0.1",
+            &["id", "description"],
+            vec![vec![Some("1"), None]],
+        );
+        let mut output_formatter = OutputFormatter::new(OutputFormat::Expanded, false);
+        assert_eq!(
+            output_formatter.render(&result),
+            "\n[10.0.0.1:postgres] \n\
+            -[ RECORD 1 ]-\n\
+            id          | 1\n\
+            description | NULL\n"
+        );
+        assert_eq!(
+            output_formatter.render_merged(&get_test_result("10.0.0.1", &["id"], vec![])),
+            "\n(0 rows)\n"
+        );
+    }
+
+    #[test]
+    fn test_render_json() {
+        let result = get_test_result("10.0.0.1", &["host", "db"], vec![vec![Some("other"), None]]);
+        let mut output_formatter = OutputFormatter::new(OutputFormat::Json, false);
+        assert_eq!(output_formatter.finish(), "");
+        assert_eq!(output_formatter.render(&result), "");
+        assert_eq!(output_formatter.render_merged(&result), "");
+        assert_eq!(
+            output_formatter.finish(),
+            "[\n  {\n    \"_host\": \"10.0.0.1\",\n    \"_db\": \"postgres\",\n    \
+            \"host\": \"other\",\n    \"db\": null\n  },\n  {\n    \"host\": \"other\",\n    \
+            \"db\": null\n  }\n]\n"
+        );
+        let mut output_formatter = OutputFormatter::new(OutputFormat::Jsonl, false);
+        assert_eq!(
+            output_formatter.render(&result),
+            "{\"_host\":\"10.0.0.1\",\"_db\":\"postgres\",\"host\":\"other\",\"db\":null}\n"
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use crate::output_formatter::output_formatter::{OutputFormat, OutputFormatter};
+use crate::shared::query_result::QueryResult;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputTarget {
+    Stdout,
+    /// One file for all servers, format follows the file extension
+    File {
+        path: PathBuf,
+        append: bool,
+    },
+    /// One "<host>_<db>.<ext>" file per server
+    Directory(PathBuf),
+}
+
+struct Output {
+    formatter: OutputFormatter,
+    writer: Box<dyn Write + Send>,
+}
+
+/// Writes query results to stdout or to files, every output has its own formatter
+/// so csv headers and json arrays are complete in each file
+pub struct OutputWriter {
+    output_target: OutputTarget,
+    output_format: OutputFormat,
+    show_data_types: bool,
+    outputs: HashMap<Option<PathBuf>, Output>,
+    file_paths: Vec<PathBuf>,
+}
+
+impl OutputWriter {
+    pub fn new(
+        output_target: OutputTarget,
+        output_format: OutputFormat,
+        show_data_types: bool,
+    ) -> Self {
+        let output_format = match &output_target {
+            OutputTarget::File { path, .. } => path
+                .extension()
+                .and_then(|extension| OutputFormat::from_extension(&extension.to_string_lossy()))
+                .unwrap_or(output_format),
+            _ => output_format,
+        };
+        Self {
+            output_target,
+            output_format,
+            show_data_types,
+            outputs: HashMap::new(),
+            file_paths: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, result: &QueryResult) -> anyhow::Result<()> {
+        let file_name = format!(
+            "{}_{}",
+            sanitize_file_name(&result.host),
+            sanitize_file_name(&result.db_name)
+        );
+        let output = self.get_output(&file_name)?;
+        let rendered = output.formatter.render(result);
+        output.writer.write_all(rendered.as_bytes())?;
+        Ok(())
+    }
+
+    /// Merged and compared results go to one "merged.<ext>" file in the out dir
+    pub fn write_merged(&mut self, result: &QueryResult) -> anyhow::Result<()> {
+        let output = self.get_output("merged")?;
+        let rendered = output.formatter.render_merged(result);
+        output.writer.write_all(rendered.as_bytes())?;
+        Ok(())
+    }
+
+    /// Finishes every output and returns paths of the written files
+    pub fn finish(&mut self) -> anyhow::Result<Vec<PathBuf>> {
+        for output in self.outputs.values_mut() {
+            let rendered = output.formatter.finish();
+            output.writer.write_all(rendered.as_bytes())?;
+            output.writer.flush()?;
+        }
+        self.outputs.clear();
+        Ok(std::mem::take(&mut self.file_paths))
+    }
+
+    fn get_output(&mut self, file_name: &str) -> anyhow::Result<&mut Output> {
+        let path = match &self.output_target {
+            OutputTarget::Stdout => None,
+            OutputTarget::File { path, .. } => Some(path.clone()),
+            OutputTarget::Directory(directory) => Some(directory.join(format!(
+                "{}.{}",
+                file_name,
+                self.output_format.get_extension()
+            ))),
+        };
+        if !self.outputs.contains_key(&path) {
+            let output = self.open_output(path.as_deref())?;
+            self.outputs.insert(path.clone(), output);
+        }
+        Ok(self.outputs.get_mut(&path).unwrap())
+    }
+
+    fn open_output(&mut self, path: Option<&Path>) -> anyhow::Result<Output> {
+        let mut formatter = OutputFormatter::new(self.output_format, self.show_data_types);
+        let Some(path) = path else {
+            return Ok(Output {
+                formatter,
+                writer: Box::new(io::stdout()),
+            });
+        };
+        let append = matches!(self.output_target, OutputTarget::File { append: true, .. });
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::options()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .map_err(|e| anyhow::anyhow!("CAN NOT OPEN OUTPUT FILE {}: {}", path.display(), e))?;
+        if append && file.metadata()?.len() > 0 {
+            formatter.skip_first_header();
+        }
+        self.file_paths.push(path.to_path_buf());
+        Ok(Output {
+            formatter,
+            writer: Box::new(io::BufWriter::new(file)),
+        })
+    }
+}
+
+fn sanitize_file_name(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::query_result::get_test_result;
+
+    fn get_temp_dir(name: &str) -> PathBuf {
+        let temp_dir = std::env::temp_dir().join(format!("taco_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&temp_dir);
+        temp_dir
+    }
+
+    #[test]
+    fn test_output_format_from_extension() {
+        for (file_name, expected) in [
+            ("out.csv", OutputFormat::Csv),
+            ("out.TSV", OutputFormat::Tsv),
+            ("out.ndjson", OutputFormat::Jsonl),
+            ("out.md", OutputFormat::Markdown),
+            ("out.txt", OutputFormat::Table),
+        ] {
+            let output_target = OutputTarget::File {
+                path: PathBuf::from(file_name),
+                append: false,
+            };
+            let output_writer = OutputWriter::new(output_target, OutputFormat::Json, true);
+            assert_eq!(output_writer.output_format, expected, "{}", file_name);
+        }
+        let output_writer = OutputWriter::new(OutputTarget::Stdout, OutputFormat::Markdown, true);
+        assert_eq!(output_writer.output_format, OutputFormat::Markdown);
+    }
+
+    #[test]
+    fn test_write_file_per_server() {
+        let temp_dir = get_temp_dir("file_per_server");
+        let output_target = OutputTarget::Directory(temp_dir.clone());
+        let mut output_writer = OutputWriter::new(output_target, OutputFormat::Csv, true);
+        for (host, value) in [("10.0.0.1", "1"), ("db/2", "2"), ("10.0.0.1", "3")] {
+            let result = get_test_result(host, &["size:int8"], vec![vec![Some(value)]]);
+            output_writer.write(&result).unwrap();
+        }
+        let mut file_paths = output_writer.finish().unwrap();
+        file_paths.sort();
+        assert_eq!(
+            file_paths,
+            [
+                temp_dir.join("10.0.0.1_postgres.csv"),
+                temp_dir.join("db_2_postgres.csv"),
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(&file_paths[0]).unwrap(),
+            "host,db,size\n10.0.0.1,postgres,1\n10.0.0.1,postgres,3\n"
+        );
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+
+    #[test]
+    fn test_append_to_file() {
+        let temp_dir = get_temp_dir("append_to_file");
+        for (file_name, expected) in [
+            (
+                "out.csv",
+                "host,db,size\n10.0.0.1,postgres,1\n10.0.0.1,postgres,2\n",
+            ),
+            (
+                "out.md",
+                "\n| host | db | size |\n| --- | --- | --- |\n| 10.0.0.1 | postgres | 1 |\n| 10.0.0.1 | postgres | 2 |\n",
+            ),
+            (
+                "out.jsonl",
+                "{\"_host\":\"10.0.0.1\",\"_db\":\"postgres\",\"size\":\"1\"}\n{\"_host\":\"10.0.0.1\",\"_db\":\"postgres\",\"size\":\"2\"}\n",
+            ),
+        ] {
+            let path = temp_dir.join(file_name);
+            for (append, value) in [(false, "1"), (true, "2")] {
+                let output_target = OutputTarget::File {
+                    path: path.clone(),
+                    append,
+                };
+                let mut output_writer =
+                    OutputWriter::new(output_target, OutputFormat::Table, false);
+                let result = get_test_result("10.0.0.1", &["size:int8"], vec![vec![Some(value)]]);
+                output_writer.write(&result).unwrap();
+                output_writer.finish().unwrap();
+            }
+            assert_eq!(
+                fs::read_to_string(&path).unwrap(),
+                expected,
+                "{}",
+                file_name
+            );
+        }
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+}
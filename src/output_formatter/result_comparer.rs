@@ -0,0 +1,98 @@
+use crate::shared::query_result::QueryResult;
+
+/// Servers which returned the same result
+pub struct ResultGroup {
+    pub servers: Vec<String>,
+    pub result: QueryResult,
+    /// Returned by fewer servers than the most common result
+    pub is_outlier: bool,
+}
+
+/// Groups servers by identical result sets.
+/// Row order is ignored, queries without ORDER BY can return rows in any order
+#[derive(Default)]
+pub struct ResultComparer {
+    results: Vec<QueryResult>,
+}
+
+impl ResultComparer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, result: QueryResult) {
+        self.results.push(result);
+    }
+
+    /// Most common result first, servers are sorted in every group
+    pub fn finish(mut self) -> Vec<ResultGroup> {
+        self.results
+            .sort_by(|a, b| (&a.host, a.port, &a.db_name).cmp(&(&b.host, b.port, &b.db_name)));
+        let mut result_groups: Vec<ResultGroup> = Vec::new();
+        for mut result in self.results {
+            result.rows.sort();
+            let server = format!("{}:{}/{}", result.host, result.port, result.db_name);
+            match result_groups.iter_mut().find(|result_group| {
+                result_group.result.columns == result.columns
+                    && result_group.result.rows == result.rows
+            }) {
+                Some(result_group) => result_group.servers.push(server),
+                None => result_groups.push(ResultGroup {
+                    servers: vec![server],
+                    result: QueryResult {
+                        host: String::new(),
+                        port: 0,
+                        db_name: String::new(),
+                        ..result
+                    },
+                    is_outlier: false,
+                }),
+            }
+        }
+        // stable sort keeps groups with the same size in host order
+        result_groups.sort_by_key(|result_group| std::cmp::Reverse(result_group.servers.len()));
+        let max_servers = result_groups
+            .first()
+            .map(|result_group| result_group.servers.len())
+            .unwrap_or(0);
+        for result_group in result_groups.iter_mut() {
+            result_group.is_outlier = result_group.servers.len() < max_servers;
+        }
+        result_groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::query_result::get_test_result;
+
+    #[test]
+    fn test_compare_results_marks_outliers() {
+        let mut result_comparer = ResultComparer::new();
+        result_comparer.add(get_test_result(
+            "w3",
+            &["extversion"],
+            vec![vec![Some("13.0")], vec![Some("1.2")]],
+        ));
+        result_comparer.add(get_test_result(
+            "w1",
+            &["extversion"],
+            vec![vec![Some("1.2")], vec![Some("13.0")]],
+        ));
+        result_comparer.add(get_test_result(
+            "w2",
+            &["extversion"],
+            vec![vec![Some("12.1")], vec![Some("1.2")]],
+        ));
+        let result_groups = result_comparer.finish();
+        assert_eq!(result_groups.len(), 2);
+        assert_eq!(
+            result_groups[0].servers,
+            vec!["w1:5432/postgres", "w3:5432/postgres"]
+        );
+        assert!(!result_groups[0].is_outlier);
+        assert_eq!(result_groups[1].servers, vec!["w2:5432/postgres"]);
+        assert!(result_groups[1].is_outlier);
+    }
+}
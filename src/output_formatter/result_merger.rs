@@ -0,0 +1,188 @@
+use crate::shared::query_result::{QueryResult, QueryResultColumn};
+use std::cmp::Ordering;
+
+const NUMERIC_TYPES: [&str; 8] = [
+    "int2", "int4", "int8", "float4", "float8", "numeric", "oid", "xid",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderByColumn {
+    /// Column name or 1-based column position
+    pub column: String,
+    pub descending: bool,
+}
+
+/// Parses "<column> [asc|desc], ..." of a client-side ORDER BY
+pub fn parse_order_by(value: &str) -> anyhow::Result<Vec<OrderByColumn>> {
+    let mut order_by = Vec::new();
+    for item in value.split(',') {
+        let parts: Vec<&str> = item.split_whitespace().collect();
+        let descending = match parts.as_slice() {
+            [_] => false,
+            [_, direction] if direction.eq_ignore_ascii_case("asc") => false,
+            [_, direction] if direction.eq_ignore_ascii_case("desc") => true,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "WRONG ORDER BY <{}>, EXPECTED <column> [asc|desc], ...",
+                    item.trim()
+                ));
+            }
+        };
+        order_by.push(OrderByColumn {
+            column: parts[0].to_string(),
+            descending,
+        });
+    }
+    Ok(order_by)
+}
+
+/// Collects results of every server and merges results with the same columns
+/// into one result with leading host, port and db columns
+#[derive(Default)]
+pub struct ResultMerger {
+    results: Vec<QueryResult>,
+}
+
+impl ResultMerger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, result: QueryResult) {
+        self.results.push(result);
+    }
+
+    /// Rows are ordered by host, port and db, so the output does not depend
+    /// on the order in which servers answered
+    pub fn finish(mut self) -> Vec<QueryResult> {
+        self.results
+            .sort_by(|a, b| (&a.host, a.port, &a.db_name).cmp(&(&b.host, b.port, &b.db_name)));
+        let mut merged_results: Vec<QueryResult> = Vec::new();
+        for result in self.results {
+            let mut columns = vec![
+                QueryResultColumn {
+                    name: "host".to_string(),
+                    type_name: "text".to_string(),
+                },
+                QueryResultColumn {
+                    name: "port".to_string(),
+                    type_name: "int4".to_string(),
+                },
+                QueryResultColumn {
+                    name: "db".to_string(),
+                    type_name: "text".to_string(),
+                },
+            ];
+            columns.extend(result.columns);
+            let rows = result.rows.into_iter().map(|row| {
+                let mut merged_row = vec![
+                    Some(result.host.clone()),
+                    Some(result.port.to_string()),
+                    Some(result.db_name.clone()),
+                ];
+                merged_row.extend(row);
+                merged_row
+            });
+            match merged_results
+                .iter_mut()
+                .find(|merged_result| merged_result.columns == columns)
+            {
+                Some(merged_result) => merged_result.rows.extend(rows),
+                None => merged_results.push(QueryResult {
+                    host: String::new(),
+                    port: 0,
+                    db_name: String::new(),
+                    columns,
+                    rows: rows.collect(),
+                }),
+            }
+        }
+        merged_results
+    }
+}
+
+/// Sorts rows like PostgreSQL does: NULLs last for asc and first for desc,
+/// numeric columns by value and other columns as text
+pub fn sort_rows(result: &mut QueryResult, order_by: &[OrderByColumn]) -> anyhow::Result<()> {
+    let mut sort_keys: Vec<(usize, bool, bool)> = Vec::new();
+    for order_by_column in order_by {
+        let column_index = match order_by_column.column.parse::<usize>() {
+            Ok(position) if position >= 1 && position <= result.columns.len() => position - 1,
+            _ => result
+                .columns
+                .iter()
+                .position(|column| column.name.eq_ignore_ascii_case(&order_by_column.column))
+                .ok_or_else(|| {
+                    anyhow::anyhow!("UNKNOWN ORDER BY COLUMN <{}>", order_by_column.column)
+                })?,
+        };
+        let is_numeric = NUMERIC_TYPES.contains(&result.columns[column_index].type_name.as_str());
+        sort_keys.push((column_index, is_numeric, order_by_column.descending));
+    }
+    result.rows.sort_by(|a, b| {
+        for (column_index, is_numeric, descending) in &sort_keys {
+            let ordering = compare_values(&a[*column_index], &b[*column_index], *is_numeric);
+            let ordering = if *descending {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    });
+    Ok(())
+}
+
+fn compare_values(a: &Option<String>, b: &Option<String>, is_numeric: bool) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => {
+            if is_numeric && let (Ok(a), Ok(b)) = (a.parse::<f64>(), b.parse::<f64>()) {
+                return a.partial_cmp(&b).unwrap_or(Ordering::Equal);
+            }
+            a.cmp(b)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::query_result::get_test_result;
+
+    #[test]
+    fn test_merge_and_sort_rows() {
+        let mut result_merger = ResultMerger::new();
+        result_merger.add(get_test_result(
+            "w2",
+            &["size:int8"],
+            vec![vec![Some("9")], vec![None]],
+        ));
+        result_merger.add(get_test_result(
+            "w1",
+            &["size:int8"],
+            vec![vec![Some("10")]],
+        ));
+        let mut merged_results = result_merger.finish();
+        assert_eq!(merged_results.len(), 1);
+        let merged_result = &mut merged_results[0];
+        assert_eq!(merged_result.columns[0].name, "host");
+        assert_eq!(merged_result.rows[0][0].as_deref(), Some("w1"));
+
+        sort_rows(merged_result, &parse_order_by("size desc").unwrap()).unwrap();
+        let sizes: Vec<Option<&str>> = merged_result
+            .rows
+            .iter()
+            .map(|row| row[3].as_deref())
+            .collect();
+        assert_eq!(sizes, vec![None, Some("10"), Some("9")]);
+
+        assert!(sort_rows(merged_result, &parse_order_by("missing").unwrap()).is_err());
+        assert!(parse_order_by("size sideways").is_err());
+    }
+}
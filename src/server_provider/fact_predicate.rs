@@ -0,0 +1,376 @@
+use crate::inventory::server::Server;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FactType {
+    Boolean,
+    Integer,
+    Text,
+}
+
+/// Facts of a server which conditions of dynamic groups can use
+const FACTS: [(&str, FactType); 16] = [
+    ("host", FactType::Text),
+    ("port", FactType::Integer),
+    ("is_node_online", FactType::Boolean),
+    ("is_node_consistent", FactType::Boolean),
+    ("postgres_is_leader", FactType::Boolean),
+    ("postgres_is_replica", FactType::Boolean),
+    ("citus_is_leader_coordinator_node", FactType::Boolean),
+    ("citus_is_replica_coordinator_node", FactType::Boolean),
+    ("citus_is_leader_worker_node", FactType::Boolean),
+    ("citus_is_replica_worker_node", FactType::Boolean),
+    ("citus_is_active_worker_node", FactType::Boolean),
+    ("citus_group_id", FactType::Integer),
+    ("patroni_is_primary", FactType::Boolean),
+    ("patroni_is_replica", FactType::Boolean),
+    ("patroni_is_read_write", FactType::Boolean),
+    ("patroni_is_read_only", FactType::Boolean),
+];
+
+#[derive(Debug, PartialEq, PartialOrd)]
+pub enum FactValue {
+    Boolean(bool),
+    Integer(i64),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Operator {
+    fn is_matched(self, ordering: Ordering) -> bool {
+        match self {
+            Self::Equal => ordering.is_eq(),
+            Self::NotEqual => ordering.is_ne(),
+            Self::Less => ordering.is_lt(),
+            Self::LessOrEqual => ordering.is_le(),
+            Self::Greater => ordering.is_gt(),
+            Self::GreaterOrEqual => ordering.is_ge(),
+        }
+    }
+}
+
+/// Condition of a dynamic group on facts of a server: "patroni_is_replica && citus_group_id > 0".
+/// Has ||, &&, !, parentheses and ==, !=, <, <=, >, >= comparisons, a boolean fact alone
+/// is a comparison with true. Comparisons of facts which were not collected are unknown, as NULL
+/// in SQL, and only true conditions match, so "!patroni_is_replica" skips offline servers
+#[derive(Debug)]
+pub enum FactPredicate {
+    Comparison(String, Operator, FactValue),
+    Not(Box<FactPredicate>),
+    And(Box<FactPredicate>, Box<FactPredicate>),
+    Or(Box<FactPredicate>, Box<FactPredicate>),
+}
+
+impl FactPredicate {
+    pub fn parse(condition: &str) -> anyhow::Result<Self> {
+        let mut parser = Parser {
+            chars: condition.chars().collect(),
+            position: 0,
+        };
+        let predicate = parser.parse_or()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            Some(c) => Err(parser.error(&format!("UNEXPECTED <{}>", c))),
+            None => Ok(predicate),
+        }
+    }
+
+    pub fn is_matched(&self, server: &Server) -> bool {
+        self.evaluate(server) == Some(true)
+    }
+
+    /// None when a fact of the condition is unknown and decides the result
+    fn evaluate(&self, server: &Server) -> Option<bool> {
+        match self {
+            Self::Comparison(fact_name, operator, value) => get_fact(server, fact_name)
+                .and_then(|fact| fact.partial_cmp(value))
+                .map(|ordering| operator.is_matched(ordering)),
+            Self::Not(predicate) => predicate.evaluate(server).map(|matched| !matched),
+            Self::And(left, right) => match (left.evaluate(server), right.evaluate(server)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            Self::Or(left, right) => match (left.evaluate(server), right.evaluate(server)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+        }
+    }
+}
+
+fn get_fact(server: &Server, fact_name: &str) -> Option<FactValue> {
+    let flag = match fact_name {
+        "host" => return Some(FactValue::Text(server.host.clone())),
+        "port" => return server.port.map(|port| FactValue::Integer(port.into())),
+        "citus_group_id" => {
+            return server
+                .citus_group_id
+                .map(|group_id| FactValue::Integer(group_id.into()));
+        }
+        "is_node_online" => server.is_node_online,
+        "is_node_consistent" => server.is_node_consistent,
+        "postgres_is_leader" => server.postgres_is_leader,
+        "postgres_is_replica" => server.postgres_is_replica,
+        "citus_is_leader_coordinator_node" => server.citus_is_leader_coordinator_node,
+        "citus_is_replica_coordinator_node" => server.citus_is_replica_coordinator_node,
+        "citus_is_leader_worker_node" => server.citus_is_leader_worker_node,
+        "citus_is_replica_worker_node" => server.citus_is_replica_worker_node,
+        "citus_is_active_worker_node" => server.citus_is_active_worker_node,
+        "patroni_is_primary" => server.patroni_is_primary,
+        "patroni_is_replica" => server.patroni_is_replica,
+        "patroni_is_read_write" => server.patroni_is_read_write,
+        "patroni_is_read_only" => server.patroni_is_read_only,
+        _ => None,
+    };
+    flag.map(FactValue::Boolean)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn parse_or(&mut self) -> anyhow::Result<FactPredicate> {
+        let mut left = self.parse_and()?;
+        while self.skip_token("||") {
+            let right = self.parse_and()?;
+            left = FactPredicate::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> anyhow::Result<FactPredicate> {
+        let mut left = self.parse_operand()?;
+        while self.skip_token("&&") {
+            let right = self.parse_operand()?;
+            left = FactPredicate::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_operand(&mut self) -> anyhow::Result<FactPredicate> {
+        if self.skip_token("!") {
+            return Ok(FactPredicate::Not(Box::new(self.parse_operand()?)));
+        }
+        if self.skip_token("(") {
+            let predicate = self.parse_or()?;
+            if !self.skip_token(")") {
+                return Err(self.error("EXPECTED <)>"));
+            }
+            return Ok(predicate);
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> anyhow::Result<FactPredicate> {
+        self.skip_whitespace();
+        let start = self.position;
+        let fact_name = self.read_word();
+        if fact_name.is_empty() {
+            return Err(self.error("EXPECTED FACT NAME"));
+        }
+        let Some((_, fact_type)) = FACTS.iter().find(|(name, _)| *name == fact_name) else {
+            self.position = start;
+            return Err(self.error(&format!("UNKNOWN FACT <{}>", fact_name)));
+        };
+        let operator = [
+            ("==", Operator::Equal),
+            ("!=", Operator::NotEqual),
+            ("<=", Operator::LessOrEqual),
+            (">=", Operator::GreaterOrEqual),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+        ]
+        .into_iter()
+        .find(|(token, _)| self.skip_token(token))
+        .map(|(_, operator)| operator);
+        let Some(operator) = operator else {
+            if *fact_type != FactType::Boolean {
+                return Err(self.error(&format!("FACT <{}> NEEDS A COMPARISON", fact_name)));
+            }
+            return Ok(FactPredicate::Comparison(
+                fact_name,
+                Operator::Equal,
+                FactValue::Boolean(true),
+            ));
+        };
+        if *fact_type != FactType::Integer
+            && !matches!(operator, Operator::Equal | Operator::NotEqual)
+        {
+            return Err(self.error(&format!(
+                "FACT <{}> CAN ONLY BE COMPARED WITH == OR !=",
+                fact_name
+            )));
+        }
+        let value = self.parse_value(*fact_type)?;
+        Ok(FactPredicate::Comparison(fact_name, operator, value))
+    }
+
+    fn parse_value(&mut self, fact_type: FactType) -> anyhow::Result<FactValue> {
+        self.skip_whitespace();
+        let value = match (fact_type, self.peek()) {
+            (FactType::Text, Some(quote @ ('"' | '\''))) => {
+                self.position += 1;
+                let start = self.position;
+                while self.peek().is_some_and(|c| c != quote) {
+                    self.position += 1;
+                }
+                if self.peek().is_none() {
+                    return Err(self.error(&format!("EXPECTED <{}>", quote)));
+                }
+                self.position += 1;
+                Some(FactValue::Text(
+                    self.chars[start..self.position - 1].iter().collect(),
+                ))
+            }
+            (FactType::Integer, _) => self.read_word().parse().ok().map(FactValue::Integer),
+            (FactType::Boolean, _) => match self.read_word().as_str() {
+                "true" => Some(FactValue::Boolean(true)),
+                "false" => Some(FactValue::Boolean(false)),
+                _ => None,
+            },
+            _ => None,
+        };
+        value.ok_or_else(|| {
+            self.error(match fact_type {
+                FactType::Boolean => "EXPECTED TRUE OR FALSE",
+                FactType::Integer => "EXPECTED A NUMBER",
+                FactType::Text => "EXPECTED A QUOTED TEXT",
+            })
+        })
+    }
+
+    /// Fact name, number or boolean
+    fn read_word(&mut self) -> String {
+        let start = self.position;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    /// Skips the token with the whitespace before it, "!" is not taken from "!="
+    fn skip_token(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let token_chars: Vec<char> = token.chars().collect();
+        let end = self.position + token_chars.len();
+        let is_matched = self.chars.get(self.position..end) == Some(&token_chars[..])
+            && !(token == "!" && self.chars.get(end) == Some(&'='));
+        if is_matched {
+            self.position = end;
+        }
+        is_matched
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow::anyhow!("{} AT POSITION {}", message, self.position + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fact_predicate() {
+        let worker_replica = Server {
+            host: "10.0.0.2".to_string(),
+            port: Some(5432),
+            patroni_is_replica: Some(true),
+            citus_group_id: Some(2),
+            ..Default::default()
+        };
+        let coordinator = Server {
+            host: "10.0.0.1".to_string(),
+            port: Some(5433),
+            patroni_is_replica: Some(false),
+            citus_group_id: Some(0),
+            ..Default::default()
+        };
+        let offline = Server {
+            host: "10.0.0.3".to_string(),
+            ..Default::default()
+        };
+        for (condition, expected) in [
+            (
+                "patroni_is_replica && citus_group_id > 0",
+                [true, false, false],
+            ),
+            ("!patroni_is_replica", [false, true, false]),
+            ("!(patroni_is_replica && host == 'a')", [true, true, true]),
+            (
+                "patroni_is_replica == false || port>=5433",
+                [false, true, false],
+            ),
+            ("citus_group_id != 0", [true, false, false]),
+            (
+                "!(citus_group_id <= 0) && host != '10.0.0.1'",
+                [true, false, false],
+            ),
+            (
+                "host == \"10.0.0.3\" || citus_group_id == -1",
+                [false, false, true],
+            ),
+        ] {
+            let predicate = FactPredicate::parse(condition).unwrap();
+            let matched = [&worker_replica, &coordinator, &offline]
+                .map(|server| predicate.is_matched(server));
+            assert_eq!(matched, expected, "{}", condition);
+        }
+        for (condition, error) in [
+            (
+                "patroni_is_leader",
+                "UNKNOWN FACT <patroni_is_leader> AT POSITION 1",
+            ),
+            (
+                "citus_group_id",
+                "FACT <citus_group_id> NEEDS A COMPARISON AT POSITION 15",
+            ),
+            (
+                "host > 'a'",
+                "FACT <host> CAN ONLY BE COMPARED WITH == OR != AT POSITION 7",
+            ),
+            (
+                "pgl_is_primary == yes",
+                "UNKNOWN FACT <pgl_is_primary> AT POSITION 1",
+            ),
+            (
+                "patroni_is_primary == yes",
+                "EXPECTED TRUE OR FALSE AT POSITION 26",
+            ),
+            ("(patroni_is_primary", "EXPECTED <)> AT POSITION 20"),
+            (
+                "patroni_is_primary patroni_is_replica",
+                "UNEXPECTED <p> AT POSITION 20",
+            ),
+        ] {
+            let message = FactPredicate::parse(condition).unwrap_err().to_string();
+            assert_eq!(message, error);
+        }
+    }
+}
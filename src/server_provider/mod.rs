@@ -1 +1,3 @@
+pub mod fact_predicate;
+pub mod server_group_expression;
 pub mod server_provider;
\ No newline at end of file
@@ -0,0 +1,333 @@
+use crate::inventory::server::Server;
+use regex::Regex;
+
+/// Server group part of a request: server groups combined with "|" (or ","), "&" and "-".
+/// "&" binds tighter than "|" and "-", parentheses group operands
+#[derive(Debug)]
+pub enum ServerGroupExpression {
+    /// Server group name, "<environment>/<cluster>:<group>" for other clusters
+    Group(String),
+    /// host(<host>,...) - servers with these hosts
+    Hosts(Vec<String>),
+    /// host~"<regex>" - servers which hosts match the regular expression
+    HostPattern(Regex),
+    Union(Box<ServerGroupExpression>, Box<ServerGroupExpression>),
+    Intersection(Box<ServerGroupExpression>, Box<ServerGroupExpression>),
+    Difference(Box<ServerGroupExpression>, Box<ServerGroupExpression>),
+}
+
+impl ServerGroupExpression {
+    pub fn parse(expression: &str) -> anyhow::Result<Self> {
+        let mut parser = Parser {
+            expression,
+            chars: expression.chars().collect(),
+            position: 0,
+        };
+        let parsed = parser.parse_union()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            Some(c) => Err(parser.error(&format!("UNEXPECTED <{}>", c))),
+            None => Ok(parsed),
+        }
+    }
+
+    /// Server group names of the expression in order of appearance
+    pub fn get_group_names(&self) -> Vec<&str> {
+        match self {
+            Self::Group(name) => vec![name.as_str()],
+            Self::Hosts(_) | Self::HostPattern(_) => Vec::new(),
+            Self::Union(left, right)
+            | Self::Intersection(left, right)
+            | Self::Difference(left, right) => {
+                let mut group_names = left.get_group_names();
+                group_names.extend(right.get_group_names());
+                group_names
+            }
+        }
+    }
+
+    /// Servers selected by the expression. Server groups come from get_servers, host selectors
+    /// pick from candidates. Servers keep the order of the left operand
+    pub fn evaluate(
+        &self,
+        get_servers: &dyn Fn(&str) -> anyhow::Result<Vec<Server>>,
+        candidates: &[Server],
+    ) -> anyhow::Result<Vec<Server>> {
+        let servers = match self {
+            Self::Group(name) => get_servers(name)?,
+            Self::Hosts(hosts) => candidates
+                .iter()
+                .filter(|server| {
+                    hosts
+                        .iter()
+                        .any(|host| host.eq_ignore_ascii_case(&server.host))
+                })
+                .cloned()
+                .collect(),
+            Self::HostPattern(regex) => candidates
+                .iter()
+                .filter(|server| regex.is_match(&server.host))
+                .cloned()
+                .collect(),
+            Self::Union(left, right) => {
+                let mut servers = left.evaluate(get_servers, candidates)?;
+                for server in right.evaluate(get_servers, candidates)? {
+                    if !contains_server(&servers, &server) {
+                        servers.push(server);
+                    }
+                }
+                servers
+            }
+            Self::Intersection(left, right) => {
+                let right_servers = right.evaluate(get_servers, candidates)?;
+                left.evaluate(get_servers, candidates)?
+                    .into_iter()
+                    .filter(|server| contains_server(&right_servers, server))
+                    .collect()
+            }
+            Self::Difference(left, right) => {
+                let right_servers = right.evaluate(get_servers, candidates)?;
+                left.evaluate(get_servers, candidates)?
+                    .into_iter()
+                    .filter(|server| !contains_server(&right_servers, server))
+                    .collect()
+            }
+        };
+        Ok(servers)
+    }
+}
+
+/// Servers of different clusters can share a host, facts of the same server can differ
+/// between groups, so servers are compared by cluster, host and port
+fn contains_server(servers: &[Server], server: &Server) -> bool {
+    servers.iter().any(|other| {
+        other.cluster_name == server.cluster_name
+            && other.host == server.host
+            && other.port == server.port
+    })
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '/' | ':' | '*')
+}
+
+struct Parser<'a> {
+    expression: &'a str,
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn parse_union(&mut self) -> anyhow::Result<ServerGroupExpression> {
+        let mut left = self.parse_intersection()?;
+        loop {
+            self.skip_whitespace();
+            let operator = self.peek();
+            if !matches!(operator, Some('|' | ',' | '-')) {
+                return Ok(left);
+            }
+            self.position += 1;
+            let right = Box::new(self.parse_intersection()?);
+            left = match operator {
+                Some('-') => ServerGroupExpression::Difference(Box::new(left), right),
+                _ => ServerGroupExpression::Union(Box::new(left), right),
+            };
+        }
+    }
+
+    fn parse_intersection(&mut self) -> anyhow::Result<ServerGroupExpression> {
+        let mut left = self.parse_operand()?;
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('&') {
+                return Ok(left);
+            }
+            self.position += 1;
+            let right = self.parse_operand()?;
+            left = ServerGroupExpression::Intersection(Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_operand(&mut self) -> anyhow::Result<ServerGroupExpression> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let expression = self.parse_union()?;
+                self.skip_whitespace();
+                self.expect(')')?;
+                Ok(expression)
+            }
+            Some(c) if is_name_char(c) => {
+                let name = self.read_name();
+                if name != "host" {
+                    return Ok(ServerGroupExpression::Group(name));
+                }
+                self.skip_whitespace();
+                match self.peek() {
+                    Some('(') => self.parse_hosts(),
+                    Some('~') => self.parse_host_pattern(),
+                    _ => Ok(ServerGroupExpression::Group(name)),
+                }
+            }
+            Some(c) => Err(self.error(&format!("UNEXPECTED <{}>", c))),
+            None => Err(self.error("MISSING SERVER GROUP")),
+        }
+    }
+
+    fn parse_hosts(&mut self) -> anyhow::Result<ServerGroupExpression> {
+        self.expect('(')?;
+        let start = self.position;
+        while self.peek().is_some_and(|c| c != ')') {
+            self.position += 1;
+        }
+        let hosts: Vec<String> = self.chars[start..self.position]
+            .iter()
+            .collect::<String>()
+            .split(',')
+            .map(|host| host.trim().to_string())
+            .collect();
+        self.expect(')')?;
+        if hosts.iter().any(String::is_empty) {
+            return Err(self.error("EMPTY HOST IN host(...)"));
+        }
+        Ok(ServerGroupExpression::Hosts(hosts))
+    }
+
+    fn parse_host_pattern(&mut self) -> anyhow::Result<ServerGroupExpression> {
+        self.expect('~')?;
+        self.skip_whitespace();
+        let quote = match self.peek() {
+            Some(c @ ('"' | '\'')) => c,
+            _ => return Err(self.error("EXPECTED QUOTED REGULAR EXPRESSION")),
+        };
+        self.position += 1;
+        let start = self.position;
+        while self.peek().is_some_and(|c| c != quote) {
+            self.position += 1;
+        }
+        let pattern: String = self.chars[start..self.position].iter().collect();
+        self.expect(quote)?;
+        let regex = Regex::new(&pattern)
+            .map_err(|e| anyhow::anyhow!("WRONG HOST PATTERN <{}>: {}", pattern, e))?;
+        Ok(ServerGroupExpression::HostPattern(regex))
+    }
+
+    /// A "-" between name characters is a part of the name, "all - dr" is a difference
+    fn read_name(&mut self) -> String {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            let is_inner_dash = c == '-'
+                && self.position > start
+                && self
+                    .chars
+                    .get(self.position + 1)
+                    .is_some_and(|c| is_name_char(*c));
+            if !is_name_char(c) && !is_inner_dash {
+                break;
+            }
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    fn expect(&mut self, expected: char) -> anyhow::Result<()> {
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("EXPECTED <{}>", expected)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow::anyhow!(
+            "{} AT POSITION {} OF SERVER GROUP EXPRESSION <{}>",
+            message,
+            self.position + 1,
+            self.expression
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_server(host: &str) -> Server {
+        Server {
+            host: host.to_string(),
+            port: Some(5432),
+            ..Default::default()
+        }
+    }
+
+    fn resolve(expression: &str) -> Vec<String> {
+        let all: Vec<Server> = ["10.0.0.1", "10.0.0.2", "db-3", "db-4"]
+            .into_iter()
+            .map(get_server)
+            .collect();
+        let get_servers = |name: &str| -> anyhow::Result<Vec<Server>> {
+            let hosts: &[&str] = match name {
+                "all" => &["10.0.0.1", "10.0.0.2", "db-3", "db-4"],
+                "pgl" => &["10.0.0.1", "db-3"],
+                "workers" => &["db-3", "db-4"],
+                "dr" => &["10.0.0.2", "db-4"],
+                "dev/c1:primary-2" => &["db-4"],
+                _ => return Err(anyhow::anyhow!("UNKNOWN SERVER GROUP NAME <{}>", name)),
+            };
+            Ok(hosts.iter().map(|host| get_server(host)).collect())
+        };
+        ServerGroupExpression::parse(expression)
+            .unwrap()
+            .evaluate(&get_servers, &all)
+            .unwrap()
+            .into_iter()
+            .map(|server| server.host)
+            .collect()
+    }
+
+    #[test]
+    fn test_server_group_expression() {
+        assert_eq!(resolve("pgl & workers"), ["db-3"]);
+        assert_eq!(resolve("all - dr"), ["10.0.0.1", "db-3"]);
+        assert_eq!(
+            resolve("pgl | dr"),
+            ["10.0.0.1", "db-3", "10.0.0.2", "db-4"]
+        );
+        assert_eq!(resolve("pgl, workers"), ["10.0.0.1", "db-3", "db-4"]);
+        assert_eq!(resolve("dr | pgl & workers"), ["10.0.0.2", "db-4", "db-3"]);
+        assert_eq!(resolve("(dr | pgl) & workers"), ["db-4", "db-3"]);
+        assert_eq!(resolve("host(10.0.0.2, DB-4)"), ["10.0.0.2", "db-4"]);
+        assert_eq!(resolve("host~\"^10\\.\" - dr"), ["10.0.0.1"]);
+        assert_eq!(
+            resolve("all - dev/c1:primary-2"),
+            ["10.0.0.1", "10.0.0.2", "db-3"]
+        );
+        let expression = ServerGroupExpression::parse("all-dr").unwrap();
+        assert_eq!(expression.get_group_names(), ["all-dr"]);
+        for (expression, error) in [
+            ("pgl &", "MISSING SERVER GROUP AT POSITION 6"),
+            ("(pgl | dr", "EXPECTED <)> AT POSITION 10"),
+            ("pgl dr", "UNEXPECTED <d> AT POSITION 5"),
+            (
+                "host~^10",
+                "EXPECTED QUOTED REGULAR EXPRESSION AT POSITION 6",
+            ),
+        ] {
+            let message = ServerGroupExpression::parse(expression)
+                .unwrap_err()
+                .to_string();
+            assert!(message.starts_with(error), "{}", message);
+        }
+    }
+}
@@ -1,15 +1,190 @@
+use crate::cluster_consistency_checker::cluster_consistency_checker::ClusterConsistencyChecker;
+use crate::facts_collector::facts_collector::FactsCollector;
+use crate::inventory::dynamic_group::DynamicGroup;
+use crate::inventory::password_resolver::PasswordResolver;
 use crate::inventory::server::Server;
+use crate::server_provider::fact_predicate::FactPredicate;
+use crate::server_provider::server_group_expression::ServerGroupExpression;
+use crate::session_manager::session_manager::SessionManager;
+use crate::shared::cluster_server_groups::ClusterServerGroups;
 use rayon::iter::ParallelIterator;
 use rayon::prelude::IntoParallelRefIterator;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinSet;
+
+/// Groups built from facts, static groups with these names are replaced by them
+pub const DYNAMIC_SERVER_GROUP_NAMES: [&str; 15] = [
+    "all", "online", "cons", "pgl", "pgr", "clc", "crc", "clw", "crw", "caw", "pp", "pr", "prw",
+    "haproxy_rw", "haproxy_r",
+];
 
 pub struct ServerProvider {
     server_groups: HashMap<String, Vec<Server>>,
+    /// Dynamic groups of the inventory with their conditions
+    dynamic_groups: Vec<(String, FactPredicate)>,
+    /// Static server groups of every cluster for targets across clusters
+    clusters: Vec<ClusterServerGroups>,
+    /// Dynamic groups which are left out because of wrong conditions, with the errors
+    wrong_dynamic_groups: Vec<(String, String)>,
 }
 
 impl ServerProvider {
-    pub async fn new(server_groups: HashMap<String, Vec<Server>>) -> Self {
-        Self { server_groups }
+    /// Dynamic groups with wrong conditions or names of built-in groups are left out,
+    /// the inventory check reports them and wrong conditions are in the warnings
+    pub async fn new(
+        server_groups: HashMap<String, Vec<Server>>,
+        dynamic_groups: &[DynamicGroup],
+        clusters: Vec<ClusterServerGroups>,
+    ) -> Self {
+        let mut predicates: Vec<(String, FactPredicate)> = Vec::new();
+        let mut wrong_dynamic_groups: Vec<(String, String)> = Vec::new();
+        for dynamic_group in dynamic_groups.iter().filter(|dynamic_group| {
+            !DYNAMIC_SERVER_GROUP_NAMES.contains(&dynamic_group.name.as_str())
+        }) {
+            match FactPredicate::parse(&dynamic_group.when) {
+                Ok(predicate) => predicates.push((dynamic_group.name.clone(), predicate)),
+                Err(e) => wrong_dynamic_groups.push((dynamic_group.name.clone(), e.to_string())),
+            }
+        }
+        Self {
+            server_groups,
+            dynamic_groups: predicates,
+            clusters,
+            wrong_dynamic_groups,
+        }
+    }
+
+    pub fn get_warnings(&self) -> Vec<String> {
+        self.wrong_dynamic_groups
+            .iter()
+            .map(|(name, error)| format!("DYNAMIC GROUP <{}> IS LEFT OUT: {}", name, error))
+            .collect()
+    }
+
+    /// Tells why a dynamic group with a wrong condition is not found
+    fn get_unknown_server_group_message(&self, server_group_name: &str) -> String {
+        match self
+            .wrong_dynamic_groups
+            .iter()
+            .find(|(name, _)| name == server_group_name)
+        {
+            Some((_, error)) => format!(
+                "DYNAMIC GROUP <{}> HAS A WRONG CONDITION: {}",
+                server_group_name, error
+            ),
+            None => format!("UNKNOWN SERVER GROUP NAME <{}>", server_group_name),
+        }
+    }
+
+    /// True for "<environment>/<cluster>:<group>" targets
+    pub fn is_cluster_target(server_group_name: &str) -> bool {
+        server_group_name.contains('/')
+    }
+
+    /// Servers selected by a server group expression, see ServerGroupExpression.
+    /// Host selectors pick from this cluster and from the clusters of the expression.
+    /// The flag is true when a selected server belongs to a protected cluster
+    pub async fn get_servers_in_expression(
+        &self,
+        expression: &str,
+        settings: &Arc<Mutex<HashMap<String, String>>>,
+        session_manager: &Arc<SessionManager>,
+    ) -> anyhow::Result<(Vec<Server>, bool)> {
+        let expression = ServerGroupExpression::parse(expression)?;
+        let cluster_targets: Vec<&str> = expression
+            .get_group_names()
+            .into_iter()
+            .filter(|server_group_name| Self::is_cluster_target(server_group_name))
+            .collect();
+        let cluster_servers = self
+            .get_servers_in_clusters(&cluster_targets, settings, session_manager)
+            .await?;
+        let mut candidates = self.get_servers_in_group("all").unwrap_or_default();
+        for server in cluster_servers.values().flatten() {
+            if !candidates.contains(server) {
+                candidates.push(server.clone());
+            }
+        }
+        let get_servers = |server_group_name: &str| match cluster_servers.get(server_group_name) {
+            Some(servers) => Ok(servers.clone()),
+            None => self
+                .get_servers_in_group(server_group_name)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(self.get_unknown_server_group_message(server_group_name))
+                }),
+        };
+        let servers = expression.evaluate(&get_servers, &candidates)?;
+
+        let is_inventory_protected = {
+            // this block for mutex release
+            let settings_lock = settings.lock().unwrap();
+            settings_lock.get("protected_inventory").map(String::as_str) == Some("true")
+        };
+        let is_protected = servers.iter().any(|server| match &server.cluster_name {
+            Some(cluster_label) => self.clusters.iter().any(|cluster| {
+                cluster.protected
+                    && *cluster_label
+                        == format!("{}/{}", cluster.environment_name, cluster.cluster_name)
+            }),
+            None => is_inventory_protected,
+        });
+        Ok((servers, is_protected))
+    }
+
+    /// Servers of "<environment>/<cluster>:<group>" targets, "*" matches every environment
+    /// or cluster. Facts of every referenced cluster are collected for the request and servers
+    /// are labelled with their cluster
+    async fn get_servers_in_clusters(
+        &self,
+        targets: &[&str],
+        settings: &Arc<Mutex<HashMap<String, String>>>,
+        session_manager: &Arc<SessionManager>,
+    ) -> anyhow::Result<HashMap<String, Vec<Server>>> {
+        let cluster_targets = get_cluster_targets(&self.clusters, targets)?;
+
+        let mut set = JoinSet::new();
+        for (index, (cluster, _)) in cluster_targets.iter().enumerate() {
+            let cluster = (*cluster).clone();
+            let settings = settings.clone();
+            let session_manager = session_manager.clone();
+            set.spawn(async move {
+                let server_provider = Self::load_cluster(cluster, settings, session_manager).await;
+                (index, server_provider)
+            });
+        }
+        let mut server_providers: Vec<Option<ServerProvider>> =
+            (0..cluster_targets.len()).map(|_| None).collect();
+        while let Some(joined) = set.join_next().await {
+            let (index, server_provider) = joined?;
+            server_providers[index] = Some(server_provider?);
+        }
+        get_target_servers(
+            cluster_targets,
+            server_providers.into_iter().flatten().collect(),
+        )
+    }
+
+    async fn load_cluster(
+        cluster: ClusterServerGroups,
+        settings: Arc<Mutex<HashMap<String, String>>>,
+        session_manager: Arc<SessionManager>,
+    ) -> anyhow::Result<ServerProvider> {
+        let mut server_groups = cluster.server_groups;
+        PasswordResolver::new().resolve_server_groups(&mut server_groups)?;
+        let mut server_provider =
+            ServerProvider::new(server_groups, &cluster.dynamic_groups, Vec::new()).await;
+        let mut servers = server_provider
+            .get_servers_in_group("all")
+            .unwrap_or_default();
+        let facts_collector = FactsCollector::new(&settings, &session_manager);
+        facts_collector
+            .collect_facts(&mut servers, cluster.citus_db_name)
+            .await;
+        let mut consistency_checker = ClusterConsistencyChecker::new(&settings);
+        consistency_checker.check_cluster_consistency(&mut servers);
+        server_provider.update_server_groups(servers);
+        Ok(server_provider)
     }
 
     pub fn get_servers_in_group(&self, server_group_name: &str) -> Option<Vec<Server>> {
@@ -19,6 +194,12 @@ impl ServerProvider {
         }
     }
 
+    pub fn get_server_group_names(&self) -> Vec<String> {
+        let mut server_group_names: Vec<String> = self.server_groups.keys().cloned().collect();
+        server_group_names.sort();
+        server_group_names
+    }
+
     pub fn update_server_groups(&mut self, main_server_group: Vec<Server>) {
         self.server_groups.remove(SERVER_GROUP_ALL);
         let static_server_groups: HashMap<String, Vec<String>> = self
@@ -205,5 +386,237 @@ impl ServerProvider {
         self.server_groups
             .insert(SERVER_GROUP_HAPROXY_R.to_string(), Vec::new());
         // endregion
+
+        // region dynamic groups of the inventory
+        for (dynamic_group_name, predicate) in &self.dynamic_groups {
+            let dynamic_server_group: Vec<Server> = main_server_group
+                .par_iter()
+                .filter(|s| predicate.is_matched(s))
+                .map(|s| s.clone())
+                .collect();
+            self.server_groups
+                .insert(dynamic_group_name.clone(), dynamic_server_group);
+        }
+        // endregion
+    }
+}
+
+/// Clusters matched by targets with "<group>" names and their targets
+type ClusterTargets<'a> = Vec<(&'a ClusterServerGroups, Vec<(&'a str, &'a str)>)>;
+
+/// Clusters of "<environment>/<cluster>:<group>" targets, a cluster is listed once
+fn get_cluster_targets<'a>(
+    clusters: &'a [ClusterServerGroups],
+    targets: &[&'a str],
+) -> anyhow::Result<ClusterTargets<'a>> {
+    let mut cluster_targets: ClusterTargets = Vec::new();
+    for target in targets.iter().copied() {
+        let Some((environment_name, cluster_name, server_group_name)) = target
+            .split_once(':')
+            .and_then(|(cluster_path, server_group_name)| {
+                let (environment_name, cluster_name) = cluster_path.split_once('/')?;
+                Some((environment_name, cluster_name, server_group_name))
+            })
+        else {
+            return Err(anyhow::anyhow!(
+                "WRONG TARGET <{}>, EXPECTED <environment>/<cluster>:<group>",
+                target
+            ));
+        };
+        let mut is_matched = false;
+        for cluster in clusters.iter().filter(|cluster| {
+            is_name_matched(environment_name, &cluster.environment_name)
+                && is_name_matched(cluster_name, &cluster.cluster_name)
+        }) {
+            is_matched = true;
+            match cluster_targets
+                .iter_mut()
+                .find(|(cluster_target, _)| std::ptr::eq(*cluster_target, cluster))
+            {
+                Some((_, server_groups)) => server_groups.push((target, server_group_name)),
+                None => cluster_targets.push((cluster, vec![(target, server_group_name)])),
+            }
+        }
+        if !is_matched {
+            return Err(anyhow::anyhow!("NO CLUSTER MATCHES <{}>", target));
+        }
+    }
+    Ok(cluster_targets)
+}
+
+/// Servers of every target labelled with their cluster,
+/// server providers are loaded for the clusters in the same order
+fn get_target_servers(
+    cluster_targets: ClusterTargets,
+    server_providers: Vec<ServerProvider>,
+) -> anyhow::Result<HashMap<String, Vec<Server>>> {
+    let mut target_servers: HashMap<String, Vec<Server>> = HashMap::new();
+    for ((cluster, server_groups), server_provider) in
+        cluster_targets.into_iter().zip(server_providers)
+    {
+        let cluster_label = format!("{}/{}", cluster.environment_name, cluster.cluster_name);
+        for (target, server_group_name) in server_groups {
+            let Some(cluster_servers) = server_provider.get_servers_in_group(server_group_name)
+            else {
+                return Err(anyhow::anyhow!(
+                    "{} IN CLUSTER <{}>",
+                    server_provider.get_unknown_server_group_message(server_group_name),
+                    cluster_label
+                ));
+            };
+            let servers = target_servers.entry(target.to_string()).or_default();
+            for mut server in cluster_servers {
+                server.cluster_name = Some(cluster_label.clone());
+                if !servers.contains(&server) {
+                    servers.push(server);
+                }
+            }
+        }
+    }
+    Ok(target_servers)
+}
+
+fn is_name_matched(pattern: &str, name: &str) -> bool {
+    pattern == "*" || pattern.eq_ignore_ascii_case(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_cluster(environment_name: &str, cluster_name: &str, host: &str) -> ClusterServerGroups {
+        let server = Server {
+            host: host.to_string(),
+            ..Default::default()
+        };
+        ClusterServerGroups {
+            environment_name: environment_name.to_string(),
+            cluster_name: cluster_name.to_string(),
+            server_groups: HashMap::from([("primary".to_string(), vec![server])]),
+            dynamic_groups: Vec::new(),
+            citus_db_name: None,
+            protected: false,
+        }
+    }
+
+    fn get_clusters() -> Vec<ClusterServerGroups> {
+        vec![
+            get_cluster("dev", "c1", "db1"),
+            get_cluster("dev", "c2", "db2"),
+            get_cluster("prod", "p1", "db3"),
+        ]
+    }
+
+    #[test]
+    fn test_get_cluster_targets() {
+        let clusters = get_clusters();
+        let cluster_targets =
+            get_cluster_targets(&clusters, &["dev/*:primary", "DEV/c1:all", "*/p1:pgl"]).unwrap();
+        let cluster_targets: Vec<String> = cluster_targets
+            .iter()
+            .map(|(cluster, server_groups)| {
+                let server_groups: Vec<String> = server_groups
+                    .iter()
+                    .map(|(target, server_group_name)| format!("{} {}", target, server_group_name))
+                    .collect();
+                format!(
+                    "{}/{}: {}",
+                    cluster.environment_name,
+                    cluster.cluster_name,
+                    server_groups.join(", ")
+                )
+            })
+            .collect();
+        assert_eq!(
+            cluster_targets,
+            vec![
+                "dev/c1: dev/*:primary primary, DEV/c1:all all",
+                "dev/c2: dev/*:primary primary",
+                "prod/p1: */p1:pgl pgl",
+            ]
+        );
+        assert_eq!(
+            get_cluster_targets(&clusters, &["*/*:all"]).unwrap().len(),
+            3
+        );
+
+        for (target, error) in [
+            ("qa/*:pgl", "NO CLUSTER MATCHES <qa/*:pgl>"),
+            ("dev/c3:pgl", "NO CLUSTER MATCHES <dev/c3:pgl>"),
+            (
+                "dev/c1",
+                "WRONG TARGET <dev/c1>, EXPECTED <environment>/<cluster>:<group>",
+            ),
+            (
+                "dev:pgl",
+                "WRONG TARGET <dev:pgl>, EXPECTED <environment>/<cluster>:<group>",
+            ),
+        ] {
+            let message = get_cluster_targets(&clusters, &[target])
+                .map(|_| ())
+                .unwrap_err()
+                .to_string();
+            assert_eq!(message, error);
+        }
+    }
+
+    #[test]
+    fn test_get_target_servers() {
+        let clusters = get_clusters();
+        let get_server_providers = || {
+            clusters
+                .iter()
+                .map(|cluster| ServerProvider {
+                    server_groups: cluster.server_groups.clone(),
+                    dynamic_groups: Vec::new(),
+                    clusters: Vec::new(),
+                    wrong_dynamic_groups: Vec::new(),
+                })
+                .collect::<Vec<ServerProvider>>()
+        };
+        let cluster_targets =
+            get_cluster_targets(&clusters, &["*/*:primary", "dev/c2:primary"]).unwrap();
+        let target_servers = get_target_servers(cluster_targets, get_server_providers()).unwrap();
+        let get_labels = |target: &str| -> Vec<String> {
+            target_servers[target]
+                .iter()
+                .map(|server| {
+                    format!(
+                        "{} {}",
+                        server.cluster_name.as_deref().unwrap(),
+                        server.host
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            get_labels("*/*:primary"),
+            vec!["dev/c1 db1", "dev/c2 db2", "prod/p1 db3"]
+        );
+        assert_eq!(get_labels("dev/c2:primary"), vec!["dev/c2 db2"]);
+
+        let cluster_targets = get_cluster_targets(&clusters, &["dev/c1:pgl"]).unwrap();
+        let message = get_target_servers(cluster_targets, get_server_providers())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            message,
+            "UNKNOWN SERVER GROUP NAME <pgl> IN CLUSTER <dev/c1>"
+        );
+
+        let mut server_providers = get_server_providers();
+        server_providers[0].wrong_dynamic_groups = vec![(
+            "lagging".to_string(),
+            "EXPECTED FACT NAME AT POSITION 22".to_string(),
+        )];
+        let cluster_targets = get_cluster_targets(&clusters, &["dev/c1:lagging"]).unwrap();
+        let message = get_target_servers(cluster_targets, server_providers)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            message,
+            "DYNAMIC GROUP <lagging> HAS A WRONG CONDITION: EXPECTED FACT NAME AT POSITION 22 \
+            IN CLUSTER <dev/c1>"
+        );
     }
 }
@@ -0,0 +1 @@
+pub mod session_manager;
\ No newline at end of file
@@ -0,0 +1,60 @@
+use crate::inventory::inventory_manager::Server;
+use crate::tls_connector::tls_connector::TlsConnector;
+use colored::Colorize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio_postgres::{Client, Error};
+
+/// Keeps one open connection per server and database between requests,
+/// so SET, SET ROLE and temp tables carry over to the next request.
+/// A session is checked out for the time of one request and checked in afterwards,
+/// a session which is not checked in (request dropped on timeout) is closed
+#[derive(Default)]
+pub struct SessionManager {
+    sessions: Mutex<HashMap<String, Client>>,
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the open session of the server or connects a new one,
+    /// closed sessions are replaced by a new connection
+    pub async fn checkout(&self, server: &Server) -> Result<Client, Error> {
+        let connection_string = server.get_connection_string();
+        let session = self.sessions.lock().unwrap().remove(&connection_string);
+        if let Some(client) = session
+            && !client.is_closed()
+        {
+            return Ok(client);
+        }
+        let (client, connection) =
+            tokio_postgres::connect(&connection_string, TlsConnector::new(server)).await?;
+        tokio::spawn(async move {
+            if connection.await.as_ref().is_err() {
+                eprintln!("{}", "SESSION CLOSED, THE NEXT REQUEST RECONNECTS".red());
+            }
+        });
+        Ok(client)
+    }
+
+    /// Keeps the session for the next request of the server
+    pub fn checkin(&self, server: &Server, client: Client) {
+        if client.is_closed() {
+            return;
+        }
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(server.get_connection_string(), client);
+    }
+
+    /// Closes all sessions and returns how many were open
+    pub fn reset(&self) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        let sessions_count = sessions.len();
+        sessions.clear();
+        sessions_count
+    }
+}
@@ -0,0 +1,15 @@
+use crate::inventory::dynamic_group::DynamicGroup;
+use crate::inventory::server::Server;
+use std::collections::HashMap;
+
+/// Static server groups of one cluster of the inventory, for targets across clusters
+#[derive(Clone)]
+pub struct ClusterServerGroups {
+    pub environment_name: String,
+    pub cluster_name: String,
+    pub server_groups: HashMap<String, Vec<Server>>,
+    pub dynamic_groups: Vec<DynamicGroup>,
+    pub citus_db_name: Option<String>,
+    /// The environment or the cluster is protected
+    pub protected: bool,
+}
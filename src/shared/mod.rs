@@ -3,4 +3,9 @@ pub mod pg_stat_wal_receiver_result;
 pub mod active_worker_nodes_result;
 pub mod patroni_facts_collector_result;
 pub mod pg_dist_node_info_result;
-pub mod request_type;
\ No newline at end of file
+pub mod printer_message;
+pub mod query_result;
+pub mod request_summary;
+pub mod request_type;
+pub mod server_outcome;
+pub mod cluster_server_groups;
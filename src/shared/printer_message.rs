@@ -0,0 +1,8 @@
+use crate::shared::query_result::QueryResult;
+
+/// Message sent from server tasks to the printer task
+#[derive(Debug)]
+pub enum PrinterMessage {
+    Text(String),
+    QueryResult(QueryResult),
+}
@@ -0,0 +1,43 @@
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryResultColumn {
+    pub name: String,
+    pub type_name: String,
+}
+
+/// Rows returned by one server, values are already rendered as text (None is NULL)
+#[derive(Debug, Clone)]
+pub struct QueryResult {
+    pub host: String,
+    pub port: i32,
+    pub db_name: String,
+    pub columns: Vec<QueryResultColumn>,
+    pub rows: Vec<Vec<Option<String>>>,
+}
+
+/// Result of db postgres on port 5432 for tests, columns are "<name>[:<type>]", text by default
+#[cfg(test)]
+pub fn get_test_result(host: &str, columns: &[&str], rows: Vec<Vec<Option<&str>>>) -> QueryResult {
+    QueryResult {
+        host: host.to_string(),
+        port: 5432,
+        db_name: "postgres".to_string(),
+        columns: columns
+            .iter()
+            .map(|column| {
+                let (name, type_name) = column.split_once(':').unwrap_or((column, "text"));
+                QueryResultColumn {
+                    name: name.to_string(),
+                    type_name: type_name.to_string(),
+                }
+            })
+            .collect(),
+        rows: rows
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|value| value.map(String::from))
+                    .collect()
+            })
+            .collect(),
+    }
+}
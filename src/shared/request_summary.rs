@@ -0,0 +1,15 @@
+#[derive(Debug, Default, Clone)]
+pub struct RequestSummary {
+    pub total_rows: u64,
+    pub failed_servers: usize,
+    /// Servers not reached because a rolling request stopped early
+    pub skipped_servers: usize,
+}
+
+impl RequestSummary {
+    pub fn merge(&mut self, other: &RequestSummary) {
+        self.total_rows += other.total_rows;
+        self.failed_servers += other.failed_servers;
+        self.skipped_servers += other.skipped_servers;
+    }
+}
@@ -1,7 +1,8 @@
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum RequestType {
     Query,
     Command,
+    /// "!!" command committed on all servers with two-phase commit or on none
+    AtomicCommand,
     Macro,
-    Unknown,
 }
\ No newline at end of file
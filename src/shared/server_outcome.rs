@@ -0,0 +1,24 @@
+use std::fmt;
+
+/// How a request ended on one server
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ServerOutcome {
+    Completed,
+    Failed,
+    /// Cancelled by Ctrl-C
+    Cancelled,
+    /// Cancelled when the request timeout expired
+    TimedOut,
+}
+
+impl fmt::Display for ServerOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ServerOutcome::Completed => "COMPLETED",
+            ServerOutcome::Failed => "FAILED",
+            ServerOutcome::Cancelled => "CANCELLED",
+            ServerOutcome::TimedOut => "TIMED OUT",
+        };
+        write!(f, "{}", name)
+    }
+}
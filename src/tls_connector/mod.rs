@@ -0,0 +1 @@
+pub mod tls_connector;
\ No newline at end of file
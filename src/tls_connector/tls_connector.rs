@@ -0,0 +1,79 @@
+use crate::inventory::server::Server;
+use native_tls::{Certificate, Identity};
+use std::fs;
+use tokio_postgres::Socket;
+use tokio_postgres::tls::MakeTlsConnect;
+
+/// Makes TLS connections with the sslmode, sslrootcert, sslcert and sslkey of the server.
+/// verify-full checks the certificate and the host name, verify-ca only the certificate,
+/// prefer and require encrypt without checking the certificate like libpq does
+#[derive(Clone)]
+pub struct TlsConnector {
+    sslmode: Option<String>,
+    sslrootcert: Option<String>,
+    sslcert: Option<String>,
+    sslkey: Option<String>,
+}
+
+impl TlsConnector {
+    pub fn new(server: &Server) -> Self {
+        Self {
+            sslmode: server.sslmode.clone(),
+            sslrootcert: server.sslrootcert.clone(),
+            sslcert: server.sslcert.clone(),
+            sslkey: server.sslkey.clone(),
+        }
+    }
+
+    fn build(&self) -> anyhow::Result<native_tls::TlsConnector> {
+        let mut builder = native_tls::TlsConnector::builder();
+        match self.sslmode.as_deref() {
+            // tokio-postgres asks for a connector even if TLS is never used
+            Some("disable") => return Ok(builder.build()?),
+            Some("verify-full") => {}
+            Some("verify-ca") => {
+                builder.danger_accept_invalid_hostnames(true);
+            }
+            _ => {
+                builder.danger_accept_invalid_certs(true);
+            }
+        }
+        if let Some(sslrootcert) = &self.sslrootcert {
+            for certificate in Certificate::stack_from_pem(&read_file(sslrootcert)?)? {
+                builder.add_root_certificate(certificate);
+            }
+        }
+        match (&self.sslcert, &self.sslkey) {
+            (Some(sslcert), Some(sslkey)) => {
+                let key = read_file(sslkey)?;
+                // libpq also reads PKCS#1 and SEC1 keys, native-tls only PKCS#8
+                if !key.windows(17).any(|window| window == b"BEGIN PRIVATE KEY") {
+                    return Err(anyhow::anyhow!(
+                        "SSLKEY {} IS NOT A PKCS#8 KEY, CONVERT IT WITH: openssl pkcs8 -topk8 -nocrypt -in {} -out <pkcs8_key_file>",
+                        sslkey,
+                        sslkey
+                    ));
+                }
+                let identity = Identity::from_pkcs8(&read_file(sslcert)?, &key)?;
+                builder.identity(identity);
+            }
+            (None, None) => {}
+            _ => return Err(anyhow::anyhow!("SSLCERT AND SSLKEY MUST BE SET TOGETHER")),
+        }
+        Ok(builder.build()?)
+    }
+}
+
+impl MakeTlsConnect<Socket> for TlsConnector {
+    type Stream = postgres_native_tls::TlsStream<Socket>;
+    type TlsConnect = postgres_native_tls::TlsConnector;
+    type Error = anyhow::Error;
+
+    fn make_tls_connect(&mut self, domain: &str) -> anyhow::Result<Self::TlsConnect> {
+        Ok(postgres_native_tls::TlsConnector::new(self.build()?, domain))
+    }
+}
+
+fn read_file(path: &str) -> anyhow::Result<Vec<u8>> {
+    fs::read(path).map_err(|e| anyhow::anyhow!("CAN NOT READ {}: {}", path, e))
+}
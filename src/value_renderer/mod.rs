@@ -0,0 +1 @@
+pub mod value_renderer;
\ No newline at end of file
@@ -0,0 +1,723 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Timelike};
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use tokio_postgres::Row;
+use tokio_postgres::types::{Field, FromSql, Kind, Type};
+use uuid::Uuid;
+
+type RenderResult = Result<String, Box<dyn Error + Sync + Send>>;
+
+const RANGE_EMPTY: u8 = 0x01;
+const RANGE_LOWER_INCLUSIVE: u8 = 0x02;
+const RANGE_UPPER_INCLUSIVE: u8 = 0x04;
+const RANGE_LOWER_INFINITE: u8 = 0x08;
+const RANGE_UPPER_INFINITE: u8 = 0x10;
+
+/// Binary value of a column of any type, NULL is read as None
+struct RawValue<'a>(&'a [u8]);
+
+impl<'a> FromSql<'a> for RawValue<'a> {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(RawValue(raw))
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+}
+
+/// Types with a binary decoder below, see is_rendered_from_binary
+const BINARY_RENDERED_TYPES: [Type; 39] = [
+    Type::INT2,
+    Type::INT4,
+    Type::INT8,
+    Type::FLOAT4,
+    Type::FLOAT8,
+    Type::NUMERIC,
+    Type::MONEY,
+    Type::TEXT,
+    Type::VARCHAR,
+    Type::BPCHAR,
+    Type::NAME,
+    Type::UNKNOWN,
+    Type::CHAR,
+    Type::BYTEA,
+    Type::DATE,
+    Type::TIMESTAMP,
+    Type::TIMESTAMPTZ,
+    Type::TIME,
+    Type::TIMETZ,
+    Type::INTERVAL,
+    Type::BOOL,
+    Type::UUID,
+    Type::JSON,
+    Type::XML,
+    Type::JSONB,
+    Type::OID,
+    Type::XID,
+    Type::CID,
+    Type::XID8,
+    Type::TID,
+    Type::PG_LSN,
+    Type::INET,
+    Type::CIDR,
+    Type::MACADDR,
+    Type::MACADDR8,
+    Type::BIT,
+    Type::VARBIT,
+    Type::POINT,
+    Type::VOID,
+];
+
+/// True when values of the type are rendered from the binary format. Other types (reg*,
+/// aclitem, types of extensions) are fetched in text format to look exactly like in psql,
+/// some of them have no binary format at all
+pub fn is_rendered_from_binary(ty: &Type) -> bool {
+    match ty.kind() {
+        Kind::Enum(_) => true,
+        Kind::Domain(base_type) => is_rendered_from_binary(base_type),
+        Kind::Array(element_type) | Kind::Range(element_type) | Kind::Multirange(element_type) => {
+            is_rendered_from_binary(element_type)
+        }
+        Kind::Composite(fields) => fields
+            .iter()
+            .all(|field| is_rendered_from_binary(field.type_())),
+        _ => BINARY_RENDERED_TYPES.contains(ty),
+    }
+}
+
+/// Renders a column value as text close to psql output, None is NULL.
+/// Values which can not be decoded are shown as hex
+pub fn render_value(row: &Row, col_index: usize) -> Option<String> {
+    let column_type = row.columns()[col_index].type_();
+    match row.try_get::<_, Option<RawValue>>(col_index) {
+        Ok(value) => value.map(|value| render_raw_value(column_type, value.0)),
+        Err(e) => Some(format!("?{}?", e)),
+    }
+}
+
+fn render_raw_value(ty: &Type, raw: &[u8]) -> String {
+    let result = match ty.kind() {
+        Kind::Enum(_) => render_text(raw),
+        Kind::Domain(base_type) => Ok(render_raw_value(base_type, raw)),
+        Kind::Array(element_type) => render_array(element_type, raw),
+        Kind::Range(element_type) => render_range(element_type, raw),
+        Kind::Multirange(element_type) => render_multirange(element_type, raw),
+        Kind::Composite(fields) => render_composite(fields, raw),
+        _ => render_simple_value(ty, raw),
+    };
+    result.unwrap_or_else(|_| render_hex(raw))
+}
+
+fn render_simple_value(ty: &Type, raw: &[u8]) -> RenderResult {
+    // https://www.postgresql.org/docs/current/datatype.html
+    match *ty {
+        // region Numeric Types
+        // https://www.postgresql.org/docs/current/datatype-numeric.html
+        Type::INT2 => Ok(i16::from_sql(ty, raw)?.to_string()),
+        Type::INT4 => Ok(i32::from_sql(ty, raw)?.to_string()),
+        Type::INT8 => Ok(i64::from_sql(ty, raw)?.to_string()),
+        Type::FLOAT4 => {
+            let value = f32::from_sql(ty, raw)?;
+            Ok(render_float(value as f64, value.to_string()))
+        }
+        Type::FLOAT8 => {
+            let value = f64::from_sql(ty, raw)?;
+            Ok(render_float(value, value.to_string()))
+        }
+        Type::NUMERIC => render_numeric(raw),
+        // endregion
+
+        // region Monetary Types
+        // https://www.postgresql.org/docs/current/datatype-money.html
+        Type::MONEY => render_money(raw),
+        // endregion
+
+        // region Character Types
+        // https://www.postgresql.org/docs/current/datatype-character.html
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME | Type::UNKNOWN => {
+            render_text(raw)
+        }
+        Type::CHAR => Ok(raw
+            .first()
+            .filter(|value| **value != 0)
+            .map(|value| (*value as char).to_string())
+            .unwrap_or_default()),
+        // endregion
+
+        // region Binary Data Types
+        // https://www.postgresql.org/docs/current/datatype-binary.html
+        Type::BYTEA => Ok(render_hex(raw)),
+        // endregion
+
+        // region Date/Time Types
+        // https://www.postgresql.org/docs/current/datatype-datetime.html
+        Type::DATE => match i32::from_sql(&Type::INT4, raw)? {
+            i32::MAX => Ok("infinity".to_string()),
+            i32::MIN => Ok("-infinity".to_string()),
+            _ => Ok(NaiveDate::from_sql(ty, raw)?.to_string()),
+        },
+        Type::TIMESTAMP | Type::TIMESTAMPTZ => match i64::from_sql(&Type::INT8, raw)? {
+            i64::MAX => Ok("infinity".to_string()),
+            i64::MIN => Ok("-infinity".to_string()),
+            _ if *ty == Type::TIMESTAMP => Ok(NaiveDateTime::from_sql(ty, raw)?.to_string()),
+            _ => Ok(format_timestamptz(&DateTime::<Local>::from_sql(ty, raw)?)),
+        },
+        Type::TIME => Ok(NaiveTime::from_sql(ty, raw)?.to_string()),
+        Type::TIMETZ => render_timetz(raw),
+        Type::INTERVAL => Ok(IntervalWrapper::from_sql(ty, raw)?.to_string()),
+        // endregion
+
+        // region Boolean Type
+        // https://www.postgresql.org/docs/current/datatype-boolean.html
+        Type::BOOL => Ok(bool::from_sql(ty, raw)?.to_string()),
+        // endregion
+
+        // region UUID Type
+        // https://www.postgresql.org/docs/current/datatype-uuid.html
+        Type::UUID => Ok(Uuid::from_sql(ty, raw)?.to_string()),
+        // endregion
+
+        // region JSON Types
+        // https://www.postgresql.org/docs/current/datatype-json.html
+        Type::JSON | Type::XML => render_text(raw),
+        // jsonb starts with a format version byte
+        Type::JSONB => render_text(raw.get(1..).unwrap_or_default()),
+        // endregion
+
+        // region Object Identifier Types
+        // https://www.postgresql.org/docs/current/datatype-oid.html
+        // reg* types are fetched as text, their binary value is only the oid
+        Type::OID | Type::XID | Type::CID => Ok(ByteReader::new(raw).read_u32()?.to_string()),
+        Type::XID8 => Ok(ByteReader::new(raw).read_u64()?.to_string()),
+        Type::TID => {
+            let mut reader = ByteReader::new(raw);
+            Ok(format!("({},{})", reader.read_u32()?, reader.read_u16()?))
+        }
+        // endregion
+
+        // region pg_lsn Type
+        // https://www.postgresql.org/docs/current/datatype-pg-lsn.html
+        Type::PG_LSN => {
+            let lsn = ByteReader::new(raw).read_u64()?;
+            Ok(format!("{:X}/{:X}", lsn >> 32, lsn & 0xFFFF_FFFF))
+        }
+        // endregion
+
+        // region Network Address Types
+        // https://www.postgresql.org/docs/current/datatype-net-types.html
+        Type::INET | Type::CIDR => render_inet(ty, raw),
+        Type::MACADDR | Type::MACADDR8 => Ok(raw
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<String>>()
+            .join(":")),
+        // endregion
+
+        // region Bit String Types
+        // https://www.postgresql.org/docs/current/datatype-bit.html
+        Type::BIT | Type::VARBIT => render_bits(raw),
+        // endregion
+
+        // region Geometric Types
+        // https://www.postgresql.org/docs/current/datatype-geometric.html
+        Type::POINT => {
+            let mut reader = ByteReader::new(raw);
+            Ok(format!("({},{})", reader.read_f64()?, reader.read_f64()?))
+        }
+        // endregion
+        Type::VOID => Ok(String::new()),
+        _ => Err(format!("no binary decoder for type {}", ty).into()),
+    }
+}
+
+fn render_text(raw: &[u8]) -> RenderResult {
+    Ok(std::str::from_utf8(raw)?.to_string())
+}
+
+fn render_hex(raw: &[u8]) -> String {
+    let mut output = String::with_capacity(raw.len() * 2 + 2);
+    output.push_str("\\x");
+    for byte in raw {
+        output.push_str(&format!("{:02x}", byte));
+    }
+    output
+}
+
+fn render_float(value: f64, rendered: String) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f64::INFINITY {
+        "Infinity".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Infinity".to_string()
+    } else {
+        rendered
+    }
+}
+
+/// numeric is sent as base 10000 digits, NaN and infinities are special signs
+fn render_numeric(raw: &[u8]) -> RenderResult {
+    let mut reader = ByteReader::new(raw);
+    let digits_count = reader.read_i16()?;
+    let weight = reader.read_i16()? as i32;
+    let sign = reader.read_u16()?;
+    let display_scale = reader.read_u16()? as usize;
+    let mut digits: Vec<i16> = Vec::new();
+    for _ in 0..digits_count {
+        digits.push(reader.read_i16()?);
+    }
+    let get_digit = |index: i32| -> i16 {
+        if index < 0 {
+            return 0;
+        }
+        digits.get(index as usize).copied().unwrap_or(0)
+    };
+
+    let mut output = match sign {
+        0x0000 => String::new(),
+        0x4000 => "-".to_string(),
+        0xC000 => return Ok("NaN".to_string()),
+        0xD000 => return Ok("Infinity".to_string()),
+        0xF000 => return Ok("-Infinity".to_string()),
+        _ => return Err("unknown numeric sign".into()),
+    };
+    if weight < 0 {
+        output.push('0');
+    }
+    for index in 0..=weight {
+        if index == 0 {
+            output.push_str(&get_digit(index).to_string());
+        } else {
+            output.push_str(&format!("{:04}", get_digit(index)));
+        }
+    }
+    if display_scale > 0 {
+        let mut fraction = String::new();
+        let mut index = weight + 1;
+        while fraction.len() < display_scale {
+            fraction.push_str(&format!("{:04}", get_digit(index)));
+            index += 1;
+        }
+        fraction.truncate(display_scale);
+        output.push('.');
+        output.push_str(&fraction);
+    }
+    Ok(output)
+}
+
+/// money is sent as cents, currency symbol depends on lc_monetary and is not shown
+fn render_money(raw: &[u8]) -> RenderResult {
+    let cents = ByteReader::new(raw).read_i64()?;
+    let sign = if cents < 0 { "-" } else { "" };
+    let cents = cents.unsigned_abs();
+    Ok(format!("{}{}.{:02}", sign, cents / 100, cents % 100))
+}
+
+/// timetz is sent as microseconds since midnight and zone offset in seconds west of UTC
+fn render_timetz(raw: &[u8]) -> RenderResult {
+    let mut reader = ByteReader::new(raw);
+    let microseconds = reader.read_i64()?;
+    let offset_seconds = -reader.read_i32()?;
+    Ok(format_time(microseconds) + &format_offset(offset_seconds))
+}
+
+/// "YYYY-MM-DD HH:MM:SS[.ffffff]+HH[:MM]" as psql shows it
+fn format_timestamptz<Tz: TimeZone>(timestamp: &DateTime<Tz>) -> String {
+    let time = timestamp.time();
+    let microseconds = i64::from(time.num_seconds_from_midnight()) * 1_000_000
+        + i64::from(time.nanosecond() / 1_000);
+    format!(
+        "{} {}{}",
+        timestamp.date_naive().format("%Y-%m-%d"),
+        format_time(microseconds),
+        format_offset(timestamp.offset().fix().local_minus_utc())
+    )
+}
+
+/// "+HH[:MM[:SS]]" of an offset east of UTC
+fn format_offset(offset_seconds: i32) -> String {
+    let mut output = String::from(if offset_seconds < 0 { "-" } else { "+" });
+    let offset_seconds = offset_seconds.abs();
+    output.push_str(&format!("{:02}", offset_seconds / 3600));
+    if offset_seconds % 3600 != 0 {
+        output.push_str(&format!(":{:02}", offset_seconds % 3600 / 60));
+    }
+    if offset_seconds % 60 != 0 {
+        output.push_str(&format!(":{:02}", offset_seconds % 60));
+    }
+    output
+}
+
+/// "HH:MM:SS[.ffffff]" without trailing zeros of the fraction, hours may exceed 24
+fn format_time(microseconds: i64) -> String {
+    let sign = if microseconds < 0 { "-" } else { "" };
+    let microseconds = microseconds.unsigned_abs();
+    let seconds = microseconds / 1_000_000;
+    let mut output = format!(
+        "{}{:02}:{:02}:{:02}",
+        sign,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    );
+    let fraction = microseconds % 1_000_000;
+    if fraction != 0 {
+        output.push_str(format!(".{:06}", fraction).trim_end_matches('0'));
+    }
+    output
+}
+
+fn render_inet(ty: &Type, raw: &[u8]) -> RenderResult {
+    let mut reader = ByteReader::new(raw);
+    let family = reader.read_u8()?;
+    let netmask_bits = reader.read_u8()?;
+    let is_cidr = reader.read_u8()? != 0 || *ty == Type::CIDR;
+    let address_length = reader.read_u8()? as usize;
+    let address = reader.read_bytes(address_length)?;
+    let (ip_address, max_bits) = match family {
+        // PGSQL_AF_INET
+        2 => {
+            let octets: [u8; 4] = address.try_into()?;
+            (IpAddr::V4(Ipv4Addr::from(octets)), 32)
+        }
+        // PGSQL_AF_INET6
+        3 => {
+            let octets: [u8; 16] = address.try_into()?;
+            (IpAddr::V6(Ipv6Addr::from(octets)), 128)
+        }
+        _ => return Err("unknown inet family".into()),
+    };
+    if is_cidr || netmask_bits != max_bits {
+        Ok(format!("{}/{}", ip_address, netmask_bits))
+    } else {
+        Ok(ip_address.to_string())
+    }
+}
+
+fn render_bits(raw: &[u8]) -> RenderResult {
+    let mut reader = ByteReader::new(raw);
+    let bits_count = reader.read_i32()? as usize;
+    let bytes = reader.read_bytes(bits_count.div_ceil(8))?;
+    Ok((0..bits_count)
+        .map(|index| {
+            if bytes[index / 8] & (0x80 >> (index % 8)) != 0 {
+                '1'
+            } else {
+                '0'
+            }
+        })
+        .collect())
+}
+
+fn render_array(element_type: &Type, raw: &[u8]) -> RenderResult {
+    let mut reader = ByteReader::new(raw);
+    let dimensions_count = reader.read_i32()?;
+    let _has_nulls = reader.read_i32()?;
+    let _element_oid = reader.read_u32()?;
+    if dimensions_count == 0 {
+        return Ok("{}".to_string());
+    }
+    let mut dimensions: Vec<(i32, i32)> = Vec::new();
+    for _ in 0..dimensions_count {
+        let length = reader.read_i32()?;
+        let lower_bound = reader.read_i32()?;
+        dimensions.push((length, lower_bound));
+    }
+    let elements_count: i32 = dimensions.iter().map(|(length, _)| length).product();
+    let mut elements: Vec<String> = Vec::new();
+    for _ in 0..elements_count {
+        elements.push(match reader.read_value()? {
+            Some(value) => quote_array_element(&render_raw_value(element_type, value)),
+            None => "NULL".to_string(),
+        });
+    }
+
+    let mut output = String::new();
+    // "[0:2]={...}" like psql when arrays do not start at 1
+    if dimensions.iter().any(|(_, lower_bound)| *lower_bound != 1) {
+        for (length, lower_bound) in &dimensions {
+            output.push_str(&format!("[{}:{}]", lower_bound, lower_bound + length - 1));
+        }
+        output.push('=');
+    }
+    let lengths: Vec<usize> = dimensions
+        .iter()
+        .map(|(length, _)| *length as usize)
+        .collect();
+    output.push_str(&join_array_dimension(&lengths, &elements));
+    Ok(output)
+}
+
+fn join_array_dimension(lengths: &[usize], elements: &[String]) -> String {
+    if lengths.len() <= 1 || elements.is_empty() {
+        return format!("{{{}}}", elements.join(","));
+    }
+    let chunk_size = (elements.len() / lengths[0]).max(1);
+    let parts: Vec<String> = elements
+        .chunks(chunk_size)
+        .map(|chunk| join_array_dimension(&lengths[1..], chunk))
+        .collect();
+    format!("{{{}}}", parts.join(","))
+}
+
+fn quote_array_element(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value.eq_ignore_ascii_case("NULL")
+        || value
+            .chars()
+            .any(|c| matches!(c, '{' | '}' | ',' | '"' | '\\') || c.is_whitespace());
+    if !needs_quotes {
+        return value.to_string();
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn render_range(element_type: &Type, raw: &[u8]) -> RenderResult {
+    let mut reader = ByteReader::new(raw);
+    let flags = reader.read_u8()?;
+    if flags & RANGE_EMPTY != 0 {
+        return Ok("empty".to_string());
+    }
+    let mut read_bound = |is_infinite: bool| -> RenderResult {
+        if is_infinite {
+            return Ok(String::new());
+        }
+        let value = reader.read_value()?.ok_or("range bound is NULL")?;
+        Ok(quote_composite_value(&render_raw_value(element_type, value), "()[],"))
+    };
+    let lower_bound = read_bound(flags & RANGE_LOWER_INFINITE != 0)?;
+    let upper_bound = read_bound(flags & RANGE_UPPER_INFINITE != 0)?;
+    Ok(format!(
+        "{}{},{}{}",
+        if flags & RANGE_LOWER_INCLUSIVE != 0 { '[' } else { '(' },
+        lower_bound,
+        upper_bound,
+        if flags & RANGE_UPPER_INCLUSIVE != 0 { ']' } else { ')' }
+    ))
+}
+
+fn render_multirange(element_type: &Type, raw: &[u8]) -> RenderResult {
+    let mut reader = ByteReader::new(raw);
+    let ranges_count = reader.read_i32()?;
+    let mut ranges: Vec<String> = Vec::new();
+    for _ in 0..ranges_count {
+        let range = reader.read_value()?.ok_or("range is NULL")?;
+        ranges.push(render_range(element_type, range)?);
+    }
+    Ok(format!("{{{}}}", ranges.join(",")))
+}
+
+/// Anonymous records are fetched as text, only composite types have their fields
+fn render_composite(fields: &[Field], raw: &[u8]) -> RenderResult {
+    let mut reader = ByteReader::new(raw);
+    let fields_count = reader.read_i32()?;
+    let mut values: Vec<String> = Vec::new();
+    for field_index in 0..fields_count as usize {
+        // the field type oid, the types come from the fields
+        reader.read_u32()?;
+        let field = fields
+            .get(field_index)
+            .ok_or("record has more fields than its type")?;
+        values.push(match reader.read_value()? {
+            Some(value) => quote_composite_value(&render_raw_value(field.type_(), value), "()"),
+            None => String::new(),
+        });
+    }
+    Ok(format!("({})", values.join(",")))
+}
+
+/// Quotes record fields and range bounds like record_out and range_out do
+fn quote_composite_value(value: &str, special_chars: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value.chars().any(|c| {
+            special_chars.contains(c) || matches!(c, ',' | '"' | '\\') || c.is_whitespace()
+        });
+    if !needs_quotes {
+        return value.to_string();
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\"\""))
+}
+
+/// interval is sent as microseconds, days and months, which are not convertible to each other
+#[derive(Debug, Clone, PartialEq)]
+struct IntervalWrapper {
+    microseconds: i64,
+    days: i32,
+    months: i32,
+}
+
+impl<'a> FromSql<'a> for IntervalWrapper {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let mut reader = ByteReader::new(raw);
+        Ok(IntervalWrapper {
+            microseconds: reader.read_i64()?,
+            days: reader.read_i32()?,
+            months: reader.read_i32()?,
+        })
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::INTERVAL
+    }
+}
+
+/// Same as intervalstyle "postgres": "1 year 2 mons 3 days 04:05:06.7"
+impl fmt::Display for IntervalWrapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+        let mut push_part = |value: i32, unit: &str| {
+            if value != 0 {
+                let plural = if value == 1 { "" } else { "s" };
+                parts.push(format!("{} {}{}", value, unit, plural));
+            }
+        };
+        push_part(self.months / 12, "year");
+        push_part(self.months % 12, "mon");
+        push_part(self.days, "day");
+        if self.microseconds != 0 || parts.is_empty() {
+            parts.push(format_time(self.microseconds));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Reads big-endian values of the binary protocol
+struct ByteReader<'a> {
+    raw: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(raw: &'a [u8]) -> Self {
+        Self { raw, position: 0 }
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], Box<dyn Error + Sync + Send>> {
+        let end = self.position + length;
+        let bytes = self
+            .raw
+            .get(self.position..end)
+            .ok_or("unexpected end of value")?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, Box<dyn Error + Sync + Send>> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_i16(&mut self) -> Result<i16, Box<dyn Error + Sync + Send>> {
+        Ok(i16::from_be_bytes(self.read_bytes(2)?.try_into()?))
+    }
+
+    fn read_u16(&mut self) -> Result<u16, Box<dyn Error + Sync + Send>> {
+        Ok(u16::from_be_bytes(self.read_bytes(2)?.try_into()?))
+    }
+
+    fn read_i32(&mut self) -> Result<i32, Box<dyn Error + Sync + Send>> {
+        Ok(i32::from_be_bytes(self.read_bytes(4)?.try_into()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Box<dyn Error + Sync + Send>> {
+        Ok(u32::from_be_bytes(self.read_bytes(4)?.try_into()?))
+    }
+
+    fn read_i64(&mut self) -> Result<i64, Box<dyn Error + Sync + Send>> {
+        Ok(i64::from_be_bytes(self.read_bytes(8)?.try_into()?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, Box<dyn Error + Sync + Send>> {
+        Ok(u64::from_be_bytes(self.read_bytes(8)?.try_into()?))
+    }
+
+    fn read_f64(&mut self) -> Result<f64, Box<dyn Error + Sync + Send>> {
+        Ok(f64::from_be_bytes(self.read_bytes(8)?.try_into()?))
+    }
+
+    /// Length-prefixed value of arrays, ranges and records, length -1 is NULL
+    fn read_value(&mut self) -> Result<Option<&'a [u8]>, Box<dyn Error + Sync + Send>> {
+        let length = self.read_i32()?;
+        if length < 0 {
+            return Ok(None);
+        }
+        Ok(Some(self.read_bytes(length as usize)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_numeric(weight: i16, sign: u16, display_scale: u16, digits: &[i16]) -> Vec<u8> {
+        let mut raw = Vec::new();
+        raw.extend((digits.len() as i16).to_be_bytes());
+        raw.extend(weight.to_be_bytes());
+        raw.extend(sign.to_be_bytes());
+        raw.extend(display_scale.to_be_bytes());
+        for digit in digits {
+            raw.extend(digit.to_be_bytes());
+        }
+        raw
+    }
+
+    #[test]
+    fn test_render_numeric() {
+        assert_eq!(
+            render_numeric(&get_numeric(1, 0, 3, &[12, 3456, 7800])).unwrap(),
+            "123456.780"
+        );
+        assert_eq!(
+            render_numeric(&get_numeric(-1, 0x4000, 4, &[12])).unwrap(),
+            "-0.0012"
+        );
+        assert_eq!(render_numeric(&get_numeric(0, 0xC000, 0, &[])).unwrap(), "NaN");
+    }
+
+    #[test]
+    fn test_render_interval() {
+        let interval = IntervalWrapper {
+            microseconds: 14_706_789_000,
+            days: 3,
+            months: 14,
+        };
+        assert_eq!(interval.to_string(), "1 year 2 mons 3 days 04:05:06.789");
+        let interval = IntervalWrapper {
+            microseconds: 0,
+            days: -1,
+            months: 0,
+        };
+        assert_eq!(interval.to_string(), "-1 days");
+    }
+
+    #[test]
+    fn test_format_timestamptz() {
+        let offset = chrono::FixedOffset::east_opt(3600).unwrap();
+        let timestamp = offset.with_ymd_and_hms(2024, 1, 1, 10, 0, 0).unwrap();
+        assert_eq!(format_timestamptz(&timestamp), "2024-01-01 10:00:00+01");
+        let offset = chrono::FixedOffset::west_opt(9 * 3600 + 30 * 60).unwrap();
+        let timestamp = offset
+            .with_ymd_and_hms(2024, 12, 31, 23, 59, 1)
+            .unwrap()
+            .with_nanosecond(250_000_000)
+            .unwrap();
+        assert_eq!(format_timestamptz(&timestamp), "2024-12-31 23:59:01.25-09:30");
+    }
+
+    #[test]
+    fn test_quote_array_element() {
+        assert_eq!(quote_array_element("abc"), "abc");
+        assert_eq!(quote_array_element("a b"), "\"a b\"");
+        assert_eq!(quote_array_element("null"), "\"null\"");
+        assert_eq!(quote_array_element("a\"b"), "\"a\\\"b\"");
+    }
+    #[test]
+    fn test_is_rendered_from_binary() {
+        assert!(is_rendered_from_binary(&Type::INT4_ARRAY));
+        assert!(is_rendered_from_binary(&Type::TSTZ_RANGE));
+        assert!(!is_rendered_from_binary(&Type::REGCLASS));
+        assert!(!is_rendered_from_binary(&Type::REGTYPE_ARRAY));
+        assert!(!is_rendered_from_binary(&Type::RECORD));
+    }
+}